@@ -0,0 +1,44 @@
+//! Full file scan benchmark, `SequentialScanner` against the per-entry
+//! `DiskEntryReader`, on a file of small entries.
+//!
+//! cargo bench --features bench
+
+use std::fs::File;
+use std::io::BufWriter;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use slmlib::disk::format::{DiskEntry, DiskEntryReader, EntryIO, SequentialScanner};
+use tempdir::TempDir;
+
+const ENTRIES: u64 = 100_000;
+
+fn bench_scan(c: &mut Criterion) {
+    let dir = TempDir::new("sequential_scan").unwrap();
+    let path = dir.path().join("data");
+    let mut w = BufWriter::new(File::create(&path).unwrap());
+    for i in 0..ENTRIES {
+        DiskEntry::with_seq(format!("meta:{:08}", i).into_bytes(), vec![7u8; 32], i)
+            .write_to(&mut w)
+            .unwrap();
+    }
+    drop(w);
+
+    let mut group = c.benchmark_group("scan");
+    group.sample_size(20);
+    group.bench_function("disk_entry_reader", |b| {
+        b.iter(|| {
+            let reader = DiskEntryReader::new(File::open(&path).unwrap());
+            black_box(reader.map(|e| e.unwrap().size()).sum::<u64>())
+        })
+    });
+    group.bench_function("sequential_scanner", |b| {
+        b.iter(|| {
+            let scanner = SequentialScanner::new(File::open(&path).unwrap());
+            black_box(scanner.map(|e| e.unwrap().size()).sum::<u64>())
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_scan);
+criterion_main!(benches);
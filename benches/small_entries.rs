@@ -0,0 +1,82 @@
+//! Small entries benchmark, `read_small`/`write_small` against the
+//! general `read_from`/`write_to` path.
+//!
+//! cargo bench --features bench
+
+use std::fs::File;
+use std::io::{Cursor, Seek, SeekFrom};
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use slmlib::disk::format::{DiskEntry, EntryIO, SMALL_ENTRY_SIZE};
+use tempdir::TempDir;
+
+const ENTRIES: u64 = 1000;
+
+fn entries() -> Vec<DiskEntry> {
+    (0..ENTRIES)
+        .map(|i| {
+            DiskEntry::with_seq(
+                format!("meta:{:06}", i).into_bytes(),
+                i.to_le_bytes().to_vec(),
+                i,
+            )
+        })
+        .collect()
+}
+
+/// Writes go to an unbuffered file, where the syscalls saved show.
+fn bench_write(c: &mut Criterion) {
+    let entries = entries();
+    let dir = TempDir::new("small_entries").unwrap();
+    let mut w = File::create(dir.path().join("data")).unwrap();
+
+    let mut group = c.benchmark_group("write");
+    group.bench_function("write_to", |b| {
+        b.iter(|| {
+            w.seek(SeekFrom::Start(0)).unwrap();
+            for entry in &entries {
+                black_box(entry.write_to(&mut w).unwrap());
+            }
+        })
+    });
+    group.bench_function("write_small", |b| {
+        b.iter(|| {
+            w.seek(SeekFrom::Start(0)).unwrap();
+            let mut offset = 0;
+            for entry in &entries {
+                offset += black_box(entry.write_small(&mut w, offset).unwrap()).1;
+            }
+        })
+    });
+    group.finish();
+}
+
+fn bench_read(c: &mut Criterion) {
+    let mut file = Cursor::new(Vec::new());
+    let offsets: Vec<u64> = entries()
+        .iter()
+        .map(|entry| entry.write_to(&mut file).unwrap().0)
+        .collect();
+
+    let mut group = c.benchmark_group("read");
+    group.bench_function("read_from", |b| {
+        b.iter(|| {
+            for &offset in &offsets {
+                black_box(DiskEntry::read_from(&mut file, offset).unwrap());
+            }
+        })
+    });
+    group.bench_function("read_small", |b| {
+        let mut buf = [0u8; SMALL_ENTRY_SIZE];
+        b.iter(|| {
+            for &offset in &offsets {
+                let entry = DiskEntry::read_small(&mut file, offset, &mut buf).unwrap();
+                black_box(entry.map(|entry| entry.value.len()));
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_write, bench_read);
+criterion_main!(benches);
@@ -15,7 +15,7 @@ pub fn run() {
 
     loop {
         let mut cmd = String::new();
-        io::stdout().write("> ".as_bytes()).unwrap();
+        io::stdout().write_all("> ".as_bytes()).unwrap();
         io::stdout().flush().unwrap();
 
         io::stdin()
@@ -43,7 +43,7 @@ pub fn run() {
     }
 }
 
-fn process_db_command(db: &mut lsm::Lsm, cmds: &Vec<&str>) {
+fn process_db_command(db: &mut lsm::Lsm, cmds: &[&str]) {
     match cmds[0] {
         "set" => {
             db.put(cmds[1].as_bytes().to_vec(), cmds[2].as_bytes().to_vec())
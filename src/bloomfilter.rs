@@ -1,3 +1,221 @@
 //! BloomFilter Module.
+//!
+//! Per data file filter of its keys, so point-gets skip files that can't
+//! hold the key. Hashes are computed here rather than with the std
+//! hasher, whose output may change between releases, so a filter written
+//! to disk stays valid.
+//!
+//! # encoded filter:
+//! - magic: `BLOOM_MAGIC`
+//! - num_hashes: u32
+//! - num_bits: u64
+//! - bits: [u64], `num_bits` rounded up to whole words.
+//! - crc: u32, of all the above.
+//!
+//! Fields are little endian, as in disk entry headers.
 
-pub struct BloomFilter {}
+use std::io::{Read, Seek, Write};
+
+use crate::disk::format::HintEntryReader;
+use crate::error::{LSMLibError, Result};
+
+/// Magic starting an encoded bloom filter.
+pub const BLOOM_MAGIC: [u8; 4] = *b"LSMB";
+
+/// False positive rate of filters unless asked otherwise.
+pub const DEFAULT_FP_RATE: f64 = 0.01;
+
+/// Size of the encoded fields before the bits.
+const BLOOM_PREFIX_SIZE: usize = 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    num_bits: u64,
+    num_hashes: u32,
+}
+
+impl BloomFilter {
+    /// Filter sized for `expected` keys at false positive rate `fp_rate`,
+    /// between 0 and 1 exclusive, any other rate is an `InvalidInput`
+    /// error.
+    pub fn new(expected: usize, fp_rate: f64) -> Result<Self> {
+        if !(fp_rate > 0.0 && fp_rate < 1.0) {
+            return Err(LSMLibError::InvalidInput(format!(
+                "false positive rate {} is not between 0 and 1",
+                fp_rate
+            )));
+        }
+        let n = expected.max(1) as f64;
+        let ln2 = std::f64::consts::LN_2;
+        let num_bits = (-n * fp_rate.ln() / (ln2 * ln2)).ceil().max(64.0) as u64;
+        let num_hashes = ((num_bits as f64 / n) * ln2).round().clamp(1.0, 32.0) as u32;
+        Ok(Self {
+            bits: vec![0; num_bits.div_ceil(64) as usize],
+            num_bits,
+            num_hashes,
+        })
+    }
+
+    /// Filter of the keys of hint file `r` at false positive rate
+    /// `fp_rate`, a corrupted hint fails the build. The hints are read
+    /// twice, once to size the filter then for their keys, one at a time.
+    pub fn from_hints<R>(r: &mut R, fp_rate: f64) -> Result<Self>
+    where
+        R: Read + Seek,
+    {
+        let mut count = 0;
+        for hint in HintEntryReader::new(&mut *r) {
+            hint?;
+            count += 1;
+        }
+        let mut filter = Self::new(count, fp_rate)?;
+        for hint in HintEntryReader::new(r) {
+            filter.insert(&hint?.1.key);
+        }
+        Ok(filter)
+    }
+
+    pub fn insert(&mut self, key: &[u8]) {
+        for bit in self.bit_positions(key) {
+            self.bits[(bit / 64) as usize] |= 1 << (bit % 64);
+        }
+    }
+
+    /// Whether `key` may have been inserted, `false` is certain.
+    pub fn contains(&self, key: &[u8]) -> bool {
+        self.bit_positions(key)
+            .all(|bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+
+    pub fn num_bits(&self) -> u64 {
+        self.num_bits
+    }
+
+    pub fn num_hashes(&self) -> u32 {
+        self.num_hashes
+    }
+
+    /// Bits of `key`, by double hashing.
+    fn bit_positions(&self, key: &[u8]) -> impl Iterator<Item = u64> {
+        let h1 = fnv1a(key);
+        let h2 = mix(h1) | 1;
+        let num_bits = self.num_bits;
+        (0..self.num_hashes as u64).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % num_bits)
+    }
+
+    /// Write the encoded filter with a single write, returns the number
+    /// of bytes written.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> Result<u64> {
+        let mut buf = Vec::with_capacity(BLOOM_PREFIX_SIZE + self.bits.len() * 8 + 4);
+        buf.extend_from_slice(&BLOOM_MAGIC);
+        buf.extend_from_slice(&self.num_hashes.to_le_bytes());
+        buf.extend_from_slice(&self.num_bits.to_le_bytes());
+        for word in &self.bits {
+            buf.extend_from_slice(&word.to_le_bytes());
+        }
+        buf.extend_from_slice(&crc32fast::hash(&buf).to_le_bytes());
+        w.write_all(&buf)?;
+        Ok(buf.len() as u64)
+    }
+
+    /// Read a filter written by `write_to`, a filter failing its crc check
+    /// is a `CorruptedBloomFilter` error.
+    pub fn read_from<R: Read>(r: &mut R) -> Result<Self> {
+        let corrupted = |reason: &str| LSMLibError::CorruptedBloomFilter(reason.to_string());
+
+        let mut prefix = [0u8; BLOOM_PREFIX_SIZE];
+        r.read_exact(&mut prefix)?;
+        if prefix[..4] != BLOOM_MAGIC {
+            return Err(corrupted("bad magic"));
+        }
+        let num_hashes = u32::from_le_bytes(prefix[4..8].try_into().unwrap());
+        let num_bits = u64::from_le_bytes(prefix[8..16].try_into().unwrap());
+        if num_bits == 0 || num_hashes == 0 {
+            return Err(corrupted("empty filter"));
+        }
+
+        let words = num_bits.div_ceil(64);
+        let mut body = Vec::new();
+        r.take(words * 8 + 4).read_to_end(&mut body)?;
+        if (body.len() as u64) < words * 8 + 4 {
+            return Err(corrupted("bits are truncated"));
+        }
+        let (bits, crc) = body.split_at(body.len() - 4);
+        let mut hasher = crc32fast::Hasher::new();
+        hasher.update(&prefix);
+        hasher.update(bits);
+        if hasher.finalize() != u32::from_le_bytes(crc.try_into().unwrap()) {
+            return Err(corrupted("crc mismatch"));
+        }
+
+        Ok(Self {
+            bits: bits
+                .chunks_exact(8)
+                .map(|word| u64::from_le_bytes(word.try_into().unwrap()))
+                .collect(),
+            num_bits,
+            num_hashes,
+        })
+    }
+}
+
+/// 64 bits FNV-1a.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xCBF2_9CE4_8422_2325, |h, &b| {
+        (h ^ b as u64).wrapping_mul(0x0000_0100_0000_01B3)
+    })
+}
+
+/// Finalizer of splitmix64, deriving the second hash from the first.
+fn mix(mut h: u64) -> u64 {
+    h = (h ^ (h >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    h = (h ^ (h >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    h ^ (h >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use crate::disk::format::{DiskEntry, EntryIO};
+
+    #[test]
+    fn test_bloom_filter() {
+        let mut data = Cursor::new(Vec::new());
+        let mut hints = Cursor::new(Vec::new());
+        for i in 0..1000 {
+            let entry = DiskEntry::new(format!("key{}", i).into_bytes(), b"v".to_vec());
+            let (_, hint) = entry.write_and_hint(&mut data).unwrap();
+            hint.write_to(&mut hints).unwrap();
+        }
+
+        let filter = BloomFilter::from_hints(&mut hints, DEFAULT_FP_RATE).unwrap();
+        assert!((0..1000).all(|i| filter.contains(format!("key{}", i).as_bytes())));
+        let false_positives = (0..10_000)
+            .filter(|i| filter.contains(format!("other{}", i).as_bytes()))
+            .count();
+        assert!(false_positives < 300, "{} false positives", false_positives);
+
+        let mut buf = Vec::new();
+        let len = filter.write_to(&mut buf).unwrap();
+        assert_eq!(len, buf.len() as u64);
+        assert_eq!(BloomFilter::read_from(&mut buf.as_slice()).unwrap(), filter);
+
+        buf[20] ^= 0xFF;
+        assert!(matches!(
+            BloomFilter::read_from(&mut buf.as_slice()),
+            Err(LSMLibError::CorruptedBloomFilter(_))
+        ));
+        assert!(BloomFilter::read_from(&mut &buf[..10]).is_err());
+
+        for fp_rate in [0.0, 1.0, f64::NAN] {
+            assert!(matches!(
+                BloomFilter::from_hints(&mut hints, fp_rate),
+                Err(LSMLibError::InvalidInput(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,36 @@
+//! Clock Module.
+
+use chrono::Utc;
+
+/// Source of the timestamps stored in entry headers.
+pub trait Clock: Send + Sync {
+    /// Current time, seconds since the unix epoch.
+    fn now_secs(&self) -> u32;
+}
+
+/// Wall-clock time, used unless another clock is given.
+///
+/// The header field is a `u32`, which runs out on 2106-02-07, reading
+/// the time past that date panics rather than wrapping around.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_secs(&self) -> u32 {
+        Utc::now()
+            .timestamp()
+            .try_into()
+            .expect("timestamp does not fit the u32 header field")
+    }
+}
+
+/// Clock standing still at the given time, for deterministic tests or to
+/// keep the timestamps of a replication source.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct FixedClock(pub u32);
+
+impl Clock for FixedClock {
+    fn now_secs(&self) -> u32 {
+        self.0
+    }
+}
@@ -0,0 +1,522 @@
+//! Async EntryIO Module.
+
+use std::collections::VecDeque;
+use std::future::Future;
+use std::io::SeekFrom;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+use futures_sink::Sink;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc::error::{SendError, TrySendError};
+use tokio::sync::mpsc::{channel, OwnedPermit, Receiver, Sender};
+
+use crate::disk::format::{
+    short_read, DiskEntry, Field, Header, HeaderDecoder, HintEntry, HEADER_SIZE, HINT_HEADER_SIZE,
+};
+use crate::error::{LSMLibError, Result};
+
+/// Bytes buffered by an `AsyncBatchWriter` unless asked otherwise.
+pub const DEFAULT_BATCH_SIZE: usize = 1024 * 1024;
+
+/// Offsets the receiver of an `AsyncBatchWriter` holds unless asked
+/// otherwise.
+pub const DEFAULT_OFFSETS_CAPACITY: usize = 1024;
+
+/// Bytes of the stream from `offset` on, leaving it at `offset`. See
+/// `available_at` of the blocking path.
+async fn available_at<S>(s: &mut S, offset: u64) -> Result<u64>
+where
+    S: AsyncSeek + Unpin,
+{
+    let len = s.seek(SeekFrom::End(0)).await?;
+    if offset > len {
+        return Err(LSMLibError::OffsetOutOfRange { offset, len });
+    }
+    s.seek(SeekFrom::Start(offset)).await?;
+    Ok(len - offset)
+}
+
+/// `Padding` error for the all-zero header at `offset`, see `padding` of
+/// the blocking path.
+async fn padding<R>(r: &mut R, offset: u64) -> Result<LSMLibError>
+where
+    R: AsyncRead + AsyncSeek + Unpin,
+{
+    r.seek(SeekFrom::Start(offset)).await?;
+    let mut buf = [0u8; 4096];
+    let mut len = 0;
+    loop {
+        let n = r.read(&mut buf).await?;
+        match buf[..n].iter().position(|b| *b != 0) {
+            Some(pos) => {
+                return Ok(LSMLibError::Padding {
+                    offset,
+                    len: len + pos as u64,
+                })
+            }
+            None if n == 0 => return Ok(LSMLibError::Padding { offset, len }),
+            None => len += n as u64,
+        }
+    }
+}
+
+/// Header between a flagged `header` and its baseline `alias`, see
+/// `resolve_alias` of the blocking path. Leaves `r` at the key of the
+/// header returned.
+async fn resolve_alias<R>(
+    r: &mut R,
+    decoder: &HeaderDecoder,
+    header: Header,
+    alias: Header,
+) -> Result<Header>
+where
+    R: AsyncRead + AsyncSeek + Unpin,
+{
+    let offset = decoder.offset();
+    let mut next = Vec::with_capacity(HEADER_SIZE);
+    r.seek(SeekFrom::Start(offset + alias.entry_len())).await?;
+    (&mut *r)
+        .take(HEADER_SIZE as u64)
+        .read_to_end(&mut next)
+        .await?;
+
+    let resolved = if decoder.alias_followed(&alias, &next)
+        && !(decoder.finish(&header).is_ok() && passes_crc(r, offset, &header).await?)
+        && passes_crc(r, offset, &alias).await?
+    {
+        alias
+    } else {
+        header
+    };
+    r.seek(SeekFrom::Start(offset + resolved.size() as u64))
+        .await?;
+    Ok(resolved)
+}
+
+/// Whether the entry of `header` at `offset` of `r` passes its crc.
+async fn passes_crc<R>(r: &mut R, offset: u64, header: &Header) -> Result<bool>
+where
+    R: AsyncRead + AsyncSeek + Unpin,
+{
+    r.seek(SeekFrom::Start(offset + header.size() as u64))
+        .await?;
+    let mut key = vec![0u8; header.key_sz() as usize];
+    r.read_exact(&mut key).await?;
+    let mut value = vec![0u8; header.value_sz() as usize];
+    r.read_exact(&mut value).await?;
+    Ok(header.matches(&key, &value))
+}
+
+/// Async counterpart of `EntryIO`, entries are decoded and checked the
+/// same way as on the blocking path, through `HeaderDecoder`.
+pub trait AsyncEntryIO {
+    type Entry;
+
+    fn read_from<R>(
+        r: &mut R,
+        offset: u64,
+    ) -> impl Future<Output = Result<Option<Self::Entry>>> + Send
+    where
+        R: AsyncRead + AsyncSeek + Unpin + Send;
+
+    /// Write the entry at the current position of `w` with a single
+    /// write, returns the offset it starts at and the number of bytes
+    /// written.
+    fn write_to<W>(&self, w: &mut W) -> impl Future<Output = Result<(u64, u64)>> + Send
+    where
+        W: AsyncWrite + AsyncSeek + Unpin + Send;
+}
+
+impl AsyncEntryIO for DiskEntry {
+    type Entry = Self;
+
+    async fn read_from<R>(r: &mut R, offset: u64) -> Result<Option<Self::Entry>>
+    where
+        R: AsyncRead + AsyncSeek + Unpin + Send,
+    {
+        let available = available_at(r, offset).await?;
+        if available == 0 {
+            return Ok(None);
+        }
+
+        let decoder = HeaderDecoder::new(offset, available);
+        decoder.check_prefix(HEADER_SIZE)?;
+        let mut buf = [0u8; HEADER_SIZE];
+        r.read_exact(&mut buf).await?;
+        let Some(mut header) = decoder.prefix(buf)? else {
+            return Err(padding(r, offset).await?);
+        };
+        r.read_exact(header.tail_mut()).await?;
+        if let Some(alias) = decoder.alias(&header) {
+            header = resolve_alias(r, &decoder, header, alias).await?;
+        }
+        decoder.finish(&header)?;
+
+        // the file may still shrink under us after the check.
+        let needed = header.size() as u64 + header.key_sz() + header.value_sz();
+        let short = |field| move |e| short_read(e, field, offset, needed, available);
+
+        let mut key = vec![0u8; header.key_sz() as usize];
+        r.read_exact(&mut key).await.map_err(short(Field::Key))?;
+
+        let mut value = vec![0u8; header.value_sz() as usize];
+        r.read_exact(&mut value)
+            .await
+            .map_err(short(Field::Value))?;
+
+        Ok(Some(Self::decode(header, key, value).offset(offset)))
+    }
+
+    async fn write_to<W>(&self, w: &mut W) -> Result<(u64, u64)>
+    where
+        W: AsyncWrite + AsyncSeek + Unpin + Send,
+    {
+        let offset = w.stream_position().await?;
+
+        let mut buf = Vec::with_capacity(self.size() as usize);
+        self.encode_into(&mut buf);
+        w.write_all(&buf).await?;
+
+        Ok((offset, buf.len() as u64))
+    }
+}
+
+impl AsyncEntryIO for HintEntry {
+    type Entry = Self;
+
+    async fn read_from<R>(r: &mut R, offset: u64) -> Result<Option<Self::Entry>>
+    where
+        R: AsyncRead + AsyncSeek + Unpin + Send,
+    {
+        let available = available_at(r, offset).await?;
+        if available == 0 {
+            return Ok(None);
+        }
+
+        let decoder = HeaderDecoder::new(offset, available);
+        decoder.check_prefix(HINT_HEADER_SIZE)?;
+        let mut buf = [0u8; HINT_HEADER_SIZE];
+        r.read_exact(&mut buf).await?;
+        let mut header = decoder.hint_prefix(buf)?;
+        r.read_exact(header.tail_mut()).await?;
+        decoder.finish_hint(&header)?;
+
+        let needed = header.size() + header.key_sz();
+        let mut key = vec![0u8; header.key_sz()];
+        r.read_exact(&mut key)
+            .await
+            .map_err(|e| short_read(e, Field::Key, offset, needed as u64, available))?;
+
+        Self::decode(header, key, offset).map(Some)
+    }
+
+    async fn write_to<W>(&self, w: &mut W) -> Result<(u64, u64)>
+    where
+        W: AsyncWrite + AsyncSeek + Unpin + Send,
+    {
+        let offset = w.stream_position().await?;
+
+        let mut buf = Vec::with_capacity(self.hint_size() as usize);
+        self.encode_into(&mut buf);
+        w.write_all(&buf).await?;
+
+        Ok((offset, buf.len() as u64))
+    }
+}
+
+/// Sink of entries batching them into few writes to `inner`, buffering up
+/// to `limit` bytes: past it, the sink is not ready until the buffer is
+/// flushed, so a fast producer waits on the writer rather than growing
+/// the buffer. An entry larger than the limit is buffered alone.
+///
+/// The offset of each entry is sent on the receiver handed out with the
+/// writer once the entry is written to `inner`, in the order entries
+/// were sent. Offsets start at `start`, the position of `inner`. The
+/// receiver holds up to `capacity` offsets, past it the sink is not ready
+/// nor flushed until offsets are received, so a receiver left behind
+/// slows the writer down rather than piling offsets up. Offsets are
+/// dropped once the receiver is.
+pub struct AsyncBatchWriter<W> {
+    inner: W,
+    buf: Vec<u8>,
+    /// bytes of `buf` already written.
+    written: usize,
+    limit: usize,
+    /// offset of the first byte of `buf`.
+    offset: u64,
+    /// offsets of the entries of `buf`.
+    pending: Vec<u64>,
+    /// offsets of the entries written, not sent yet.
+    unsent: VecDeque<u64>,
+    offsets: Offsets,
+}
+
+type Reserve =
+    Pin<Box<dyn Future<Output = std::result::Result<OwnedPermit<u64>, SendError<()>>> + Send>>;
+
+/// Sending end of the offsets of an `AsyncBatchWriter`.
+enum Offsets {
+    Idle(Sender<u64>),
+    /// waiting for room in the channel.
+    Reserving(Reserve),
+    /// the receiver is gone.
+    Closed,
+}
+
+impl<W> AsyncBatchWriter<W>
+where
+    W: AsyncWrite + Unpin,
+{
+    pub fn new(inner: W, start: u64) -> (Self, Receiver<u64>) {
+        Self::with_limit(inner, start, DEFAULT_BATCH_SIZE, DEFAULT_OFFSETS_CAPACITY)
+    }
+
+    /// Writer buffering up to `limit` bytes, whose receiver holds up to
+    /// `capacity` offsets, at least one.
+    pub fn with_limit(
+        inner: W,
+        start: u64,
+        limit: usize,
+        capacity: usize,
+    ) -> (Self, Receiver<u64>) {
+        let (offsets, rx) = channel(capacity.max(1));
+        let writer = Self {
+            inner,
+            buf: Vec::with_capacity(limit),
+            written: 0,
+            limit,
+            offset: start,
+            pending: Vec::new(),
+            unsent: VecDeque::new(),
+            offsets: Offsets::Idle(offsets),
+        };
+        (writer, rx)
+    }
+
+    /// Offset the next entry will be written at.
+    pub fn position(&self) -> u64 {
+        self.offset + self.buf.len() as u64
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Send the offsets of the entries written, waiting for room in the
+    /// channel.
+    fn poll_send_offsets(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        while let Some(&offset) = self.unsent.front() {
+            match std::mem::replace(&mut self.offsets, Offsets::Closed) {
+                Offsets::Idle(tx) => match tx.try_send(offset) {
+                    Ok(()) => {
+                        self.unsent.pop_front();
+                        self.offsets = Offsets::Idle(tx);
+                    }
+                    Err(TrySendError::Full(_)) => {
+                        self.offsets = Offsets::Reserving(Box::pin(tx.reserve_owned()));
+                    }
+                    Err(TrySendError::Closed(_)) => self.unsent.clear(),
+                },
+                Offsets::Reserving(mut reserve) => match reserve.as_mut().poll(cx) {
+                    Poll::Ready(Ok(permit)) => {
+                        self.unsent.pop_front();
+                        self.offsets = Offsets::Idle(permit.send(offset));
+                    }
+                    Poll::Ready(Err(_)) => self.unsent.clear(),
+                    Poll::Pending => {
+                        self.offsets = Offsets::Reserving(reserve);
+                        return Poll::Pending;
+                    }
+                },
+                // nobody listening for offsets is fine.
+                Offsets::Closed => self.unsent.clear(),
+            }
+        }
+        Poll::Ready(())
+    }
+
+    /// Write out the buffer, then queue the offsets of its entries.
+    fn poll_write_buf(&mut self, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        while self.written < self.buf.len() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.buf[self.written..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(
+                    std::io::Error::from(std::io::ErrorKind::WriteZero).into()
+                ));
+            }
+            self.written += n;
+        }
+
+        self.offset += self.buf.len() as u64;
+        self.buf.clear();
+        self.written = 0;
+        self.unsent.extend(self.pending.drain(..));
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W> Sink<DiskEntry> for AsyncBatchWriter<W>
+where
+    W: AsyncWrite + Unpin,
+{
+    type Error = LSMLibError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_send_offsets(cx));
+        if this.buf.len() >= this.limit {
+            ready!(this.poll_write_buf(cx))?;
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn start_send(self: Pin<&mut Self>, entry: DiskEntry) -> Result<()> {
+        let this = self.get_mut();
+        this.pending.push(this.position());
+        entry.encode_into(&mut this.buf);
+        Ok(())
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_buf(cx))?;
+        ready!(this.poll_send_offsets(cx));
+        Poll::Ready(Ok(ready!(Pin::new(&mut this.inner).poll_flush(cx))?))
+    }
+
+    fn poll_close(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<()>> {
+        ready!(self.as_mut().poll_flush(cx))?;
+        Poll::Ready(Ok(ready!(Pin::new(&mut self.inner).poll_shutdown(cx))?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use futures_util::{FutureExt, SinkExt};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_async_entry_io() {
+        let entry = DiskEntry::with_seq(b"hello".to_vec(), b"world".to_vec(), 1);
+
+        let mut cursor = Cursor::new(Vec::new());
+        let (offset, size) = entry.write_to(&mut cursor).await.unwrap();
+        assert_eq!(size, entry.size());
+
+        let e = DiskEntry::read_from(&mut cursor, offset)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(e.is_validate());
+        assert_eq!(e.value, b"world".to_vec());
+        assert_eq!(e.offset, Some(offset));
+        assert!(DiskEntry::read_from(&mut cursor, size)
+            .await
+            .unwrap()
+            .is_none());
+        assert!(matches!(
+            DiskEntry::read_from(&mut cursor, size + 1).await,
+            Err(LSMLibError::OffsetOutOfRange { .. })
+        ));
+
+        // the same header checks as on the blocking path.
+        let mut buf = cursor.into_inner();
+        buf.extend_from_slice(&[0u8; 30]);
+        assert!(matches!(
+            DiskEntry::read_from(&mut Cursor::new(&buf), size).await,
+            Err(LSMLibError::Padding { len: 30, .. })
+        ));
+        assert!(matches!(
+            DiskEntry::read_from(&mut Cursor::new(&buf[..5]), 0).await,
+            Err(LSMLibError::TruncatedEntry {
+                field: Field::Header,
+                available: 5,
+                ..
+            })
+        ));
+        buf[crate::disk::layout::FLAGS_POS] |= crate::disk::layout::FLAG_TOMBSTONE;
+        assert!(matches!(
+            DiskEntry::read_from(&mut Cursor::new(&buf), 0).await,
+            Err(LSMLibError::ImplausibleHeader { offset: 0 })
+        ));
+
+        let hint = HintEntry::try_from(&e.offset(offset)).unwrap();
+        let mut cursor = Cursor::new(Vec::new());
+        let (offset, size) = hint.write_to(&mut cursor).await.unwrap();
+        assert_eq!(size, hint.hint_size());
+
+        let h = HintEntry::read_from(&mut cursor, offset)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(h.key, b"hello".to_vec());
+        assert_eq!(h.seq(), Some(1));
+        let buf = cursor.into_inner();
+        assert!(matches!(
+            HintEntry::read_from(&mut Cursor::new(&buf[..5]), 0).await,
+            Err(LSMLibError::TruncatedEntry {
+                field: Field::Header,
+                ..
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_async_batch_writer() {
+        let entries: Vec<_> = (0..20)
+            .map(|i| DiskEntry::new(format!("key{}", i).into_bytes(), vec![i as u8; 50]))
+            .collect();
+
+        // the duplex buffer holds less than the batch limit, the writer
+        // stalls until the other end is read.
+        let (w, mut r) = tokio::io::duplex(64);
+        let (mut writer, mut offsets) =
+            AsyncBatchWriter::with_limit(w, 0, 256, DEFAULT_OFFSETS_CAPACITY);
+        let mut sent = 0;
+        while writer.feed(entries[sent].clone()).now_or_never().is_some() {
+            sent += 1;
+        }
+        assert!(sent < entries.len());
+        assert!(writer.buf.len() < 256 + entries[0].size() as usize);
+
+        let reader = tokio::spawn(async move {
+            let mut data = Vec::new();
+            r.read_to_end(&mut data).await.unwrap();
+            data
+        });
+        for entry in &entries[sent..] {
+            writer.feed(entry.clone()).await.unwrap();
+        }
+        writer.close().await.unwrap();
+        // the sender closes with the writer, ending the offsets.
+        drop(writer);
+        let data = reader.await.unwrap();
+
+        let mut cursor = Cursor::new(data);
+        for entry in &entries {
+            let offset = offsets.recv().await.unwrap();
+            let read = <DiskEntry as crate::disk::format::EntryIO>::read_from(&mut cursor, offset)
+                .unwrap()
+                .unwrap();
+            assert_eq!(read.key, entry.key);
+        }
+        assert!(offsets.recv().await.is_none());
+
+        // a receiver left behind holds the writer back once full.
+        let w = Cursor::new(Vec::new());
+        let (mut writer, mut offsets) = AsyncBatchWriter::with_limit(w, 0, 0, 2);
+        let mut sent = 0;
+        while writer.feed(entries[sent].clone()).now_or_never().is_some() {
+            sent += 1;
+        }
+        assert_eq!(sent, 4);
+        assert_eq!(offsets.recv().await, Some(0));
+        writer.feed(entries[sent].clone()).await.unwrap();
+        drop(offsets);
+        writer.send(entries[sent + 1].clone()).await.unwrap();
+    }
+}
@@ -0,0 +1,348 @@
+//! Batch Module.
+//!
+//! Atomic groups of entries: a `WriteBatch` is written between a begin
+//! and a commit marker, entries protected by their crc like any other.
+//! Batches delimit themselves, so plain entries may be written between
+//! and after them. After a crash, a batch begun but not committed at the
+//! end of the file was torn midway and is discarded, see
+//! `truncate_uncommitted`.
+//!
+//! All flag bits are taken, so a marker is told apart by a version byte
+//! of its own, which only `begin_marker` and `commit_marker` write, and
+//! has an empty key. Readers of entries skip markers, see `is_marker`.
+//!
+//! # marker value:
+//! - magic: `BEGIN_MAGIC` or `COMMIT_MAGIC`
+//! - count: u64, number of entries of the batch.
+//!
+//! Fields are little endian, as in `Header`.
+
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use super::format::{DiskEntry, DiskEntryReader, EntryBuf, SetLen};
+use crate::error::{LSMLibError, Result};
+
+/// Magic starting the value of a begin marker.
+pub const BEGIN_MAGIC: [u8; 4] = *b"LSMB";
+
+/// Magic starting the value of a commit marker.
+pub const COMMIT_MAGIC: [u8; 4] = *b"LSMC";
+
+/// Size of the value of a begin or commit marker.
+pub const COMMIT_MARKER_VALUE_SIZE: usize = 12;
+
+/// Entries written all or none.
+#[derive(Debug, Default)]
+pub struct WriteBatch {
+    entries: Vec<DiskEntry>,
+}
+
+impl WriteBatch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, entry: DiskEntry) {
+        self.entries.push(entry);
+    }
+
+    pub fn put(&mut self, key: impl Into<EntryBuf>, value: impl Into<EntryBuf>) {
+        self.push(DiskEntry::new(key, value));
+    }
+
+    pub fn delete(&mut self, key: impl Into<EntryBuf>) {
+        self.push(DiskEntry::tombstone(key));
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Write the entries between their begin and commit markers at the
+    /// current position of `w` with a single write, returns the offset of
+    /// each entry. The batch is committed once the commit marker is
+    /// durable, sync `w` for that.
+    pub fn write_to<W>(self, w: &mut W) -> Result<Vec<u64>>
+    where
+        W: Write + Seek,
+    {
+        let count = self.entries.len() as u64;
+        let mut entries = Vec::with_capacity(self.entries.len() + 2);
+        entries.push(begin_marker(count));
+        entries.extend(self.entries);
+        entries.push(commit_marker(count));
+        let mut offsets = DiskEntry::write_batch(&entries, w)?;
+        offsets.pop();
+        offsets.remove(0);
+        Ok(offsets)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Marker {
+    Begin(u64),
+    Commit(u64),
+}
+
+fn marker_entry(magic: [u8; 4], count: u64) -> DiskEntry {
+    let mut value = Vec::with_capacity(COMMIT_MARKER_VALUE_SIZE);
+    value.extend_from_slice(&magic);
+    value.extend_from_slice(&count.to_le_bytes());
+    DiskEntry::batch_marker(value)
+}
+
+fn marker(entry: &DiskEntry) -> Option<Marker> {
+    let value = &entry.value[..];
+    if !entry.is_batch_marker() || value.len() != COMMIT_MARKER_VALUE_SIZE {
+        return None;
+    }
+    let count = u64::from_le_bytes(value[4..].try_into().unwrap());
+    match value[..4].try_into().unwrap() {
+        BEGIN_MAGIC => Some(Marker::Begin(count)),
+        COMMIT_MAGIC => Some(Marker::Commit(count)),
+        _ => None,
+    }
+}
+
+/// Begin marker of a batch of `count` entries.
+pub fn begin_marker(count: u64) -> DiskEntry {
+    marker_entry(BEGIN_MAGIC, count)
+}
+
+/// Commit marker of a batch of `count` entries.
+pub fn commit_marker(count: u64) -> DiskEntry {
+    marker_entry(COMMIT_MAGIC, count)
+}
+
+/// Number of entries of the batch `entry` begins, `None` if it is not a
+/// begin marker.
+pub fn begin_count(entry: &DiskEntry) -> Option<u64> {
+    match marker(entry)? {
+        Marker::Begin(count) => Some(count),
+        Marker::Commit(_) => None,
+    }
+}
+
+/// Number of entries of the batch `entry` commits, `None` if it is not a
+/// commit marker.
+pub fn commit_count(entry: &DiskEntry) -> Option<u64> {
+    match marker(entry)? {
+        Marker::Commit(count) => Some(count),
+        Marker::Begin(_) => None,
+    }
+}
+
+/// Whether `entry` is a begin or commit marker rather than data, for
+/// readers to skip.
+pub fn is_marker(entry: &DiskEntry) -> bool {
+    marker(entry).is_some()
+}
+
+/// End of the committed data of `r`, read from offset 0: plain entries,
+/// and batches from their begin marker up to a commit marker agreeing on
+/// the count. A batch still open at the end of `r`, or torn by a
+/// truncated entry, was torn by a crash, the data ends where it begins.
+///
+/// Anything else failing before the end is corruption rather than a torn
+/// write, and returned as an error instead of cutting committed data: the
+/// error of an unreadable entry, or `BatchMismatch` for a batch whose
+/// markers disagree with its entries.
+pub fn committed_len<R>(r: &mut R) -> Result<u64>
+where
+    R: Read + Seek,
+{
+    let mut committed = 0;
+    // count and entries read so far of the open batch.
+    let mut open: Option<(u64, u64)> = None;
+    for entry in DiskEntryReader::new(r).with_markers() {
+        let entry = entry?;
+        let offset = entry.offset.unwrap_or_default();
+        let end = offset + entry.size();
+        match (marker(&entry), open.as_mut()) {
+            (None, None) => committed = end,
+            (None, Some((_, read))) => *read += 1,
+            (Some(Marker::Begin(count)), None) => open = Some((count, 0)),
+            (Some(Marker::Commit(count)), Some(&mut (expected, read)))
+                if count == expected && read == count =>
+            {
+                committed = end;
+                open = None;
+            }
+            _ => return Err(LSMLibError::BatchMismatch { offset }),
+        }
+    }
+    Ok(committed)
+}
+
+/// Truncate `f` at `committed_len`, dropping the entries of a torn
+/// batch, returns the new length. `f` is left positioned there for the
+/// next batch.
+pub fn truncate_uncommitted<F>(f: &mut F) -> Result<u64>
+where
+    F: Read + Seek + Write + SetLen,
+{
+    let len = committed_len(f)?;
+    f.set_len(len)?;
+    f.seek(SeekFrom::Start(len))?;
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use crate::disk::format::{recover_entries, truncate_to_last_valid, EntryIO};
+
+    #[test]
+    fn test_write_batch() {
+        let mut cursor = Cursor::new(Vec::new());
+        let mut batch = WriteBatch::new();
+        batch.put(b"a".to_vec(), b"1".to_vec());
+        batch.delete(b"b".to_vec());
+        let offsets = batch.write_to(&mut cursor).unwrap();
+        let committed = cursor.get_ref().len() as u64;
+
+        let entry = DiskEntry::read_from_checked(&mut cursor, offsets[1])
+            .unwrap()
+            .unwrap();
+        assert!(entry.is_tombstone());
+        let marker = DiskEntry::read_from_checked(&mut cursor, offsets[1] + entry.size())
+            .unwrap()
+            .unwrap();
+        assert_eq!(commit_count(&marker), Some(2));
+        assert_eq!(commit_count(&entry), None);
+        assert!(is_marker(&begin_marker(2)) && !is_marker(&entry));
+        assert_eq!(committed_len(&mut cursor).unwrap(), committed);
+
+        // plain writes between and after batches are kept.
+        let plain = DiskEntry::new(b"p".to_vec(), b"0".to_vec());
+        cursor.seek(SeekFrom::End(0)).unwrap();
+        plain.write_to(&mut cursor).unwrap();
+        let mut batch = WriteBatch::new();
+        batch.put(b"c".to_vec(), b"3".to_vec());
+        batch.write_to(&mut cursor).unwrap();
+        plain.write_to(&mut cursor).unwrap();
+        let committed = cursor.get_ref().len() as u64;
+        assert_eq!(committed_len(&mut cursor).unwrap(), committed);
+
+        // readers skip the markers.
+        let keys: Vec<_> = DiskEntryReader::new(&mut cursor)
+            .map(|e| e.unwrap().key)
+            .collect();
+        assert_eq!(keys, [&b"a"[..], b"b", b"p", b"c", b"p"]);
+
+        // a batch torn before its commit marker.
+        cursor.seek(SeekFrom::End(0)).unwrap();
+        let mut batch = WriteBatch::new();
+        batch.put(b"d".to_vec(), b"4".to_vec());
+        batch.put(b"e".to_vec(), b"5".to_vec());
+        batch.write_to(&mut cursor).unwrap();
+        let len = cursor.get_ref().len();
+        cursor
+            .get_mut()
+            .truncate(len - commit_marker(2).size() as usize);
+        assert_eq!(truncate_uncommitted(&mut cursor).unwrap(), committed);
+        assert_eq!(cursor.get_ref().len() as u64, committed);
+
+        // an empty batch is only its markers.
+        assert!(WriteBatch::new().write_to(&mut cursor).unwrap().is_empty());
+        let (marker, next) = DiskEntry::read_at(&mut cursor, committed).unwrap().unwrap();
+        assert!(is_marker(&marker) && commit_count(&marker).is_none());
+        let (marker, _) = DiskEntry::read_at(&mut cursor, next).unwrap().unwrap();
+        assert_eq!(commit_count(&marker), Some(0));
+        assert_eq!(
+            committed_len(&mut cursor).unwrap(),
+            cursor.get_ref().len() as u64
+        );
+    }
+
+    #[test]
+    fn test_committed_len_corruption() {
+        let mut cursor = Cursor::new(Vec::new());
+        let plain = DiskEntry::new(b"p".to_vec(), b"0".to_vec());
+        plain.write_to(&mut cursor).unwrap();
+        let mut batch = WriteBatch::new();
+        batch.put(b"a".to_vec(), b"1".to_vec());
+        batch.write_to(&mut cursor).unwrap();
+        plain.write_to(&mut cursor).unwrap();
+        let len = cursor.get_ref().len();
+
+        // a corrupted entry followed by committed data is not a torn tail.
+        let mut corrupted = cursor.clone();
+        corrupted.get_mut()[plain.size() as usize - 1] ^= 0xFF;
+        let e = truncate_uncommitted(&mut corrupted).unwrap_err();
+        assert!(matches!(e, LSMLibError::CrcMismatch { .. }));
+        assert_eq!(corrupted.get_ref().len(), len);
+
+        // nor a commit marker disagreeing with its batch.
+        let entries = [
+            begin_marker(2),
+            DiskEntry::new(b"b".to_vec(), b"2".to_vec()),
+            commit_marker(1),
+        ];
+        cursor.seek(SeekFrom::End(0)).unwrap();
+        DiskEntry::write_batch(&entries, &mut cursor).unwrap();
+        plain.write_to(&mut cursor).unwrap();
+        assert!(matches!(
+            committed_len(&mut cursor).unwrap_err(),
+            LSMLibError::BatchMismatch { .. }
+        ));
+    }
+
+    #[test]
+    fn test_marker_lookalike() {
+        // a user entry shaped like a marker is still data.
+        let lookalike = DiskEntry::new(Vec::new(), begin_marker(1).value.clone());
+        assert!(!is_marker(&lookalike) && begin_count(&lookalike).is_none());
+        assert_eq!(begin_count(&begin_marker(1)), Some(1));
+
+        let mut cursor = Cursor::new(Vec::new());
+        lookalike.write_to(&mut cursor).unwrap();
+        let entries: Vec<_> = DiskEntryReader::new(&mut cursor)
+            .map(|e| e.unwrap())
+            .collect();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].value, lookalike.value);
+        assert!(!is_marker(&entries[0]));
+    }
+
+    #[test]
+    fn test_recover_batches() {
+        let mut cursor = Cursor::new(Vec::new());
+        let plain = DiskEntry::new(b"p".to_vec(), b"0".to_vec());
+        plain.write_to(&mut cursor).unwrap();
+        let mut batch = WriteBatch::new();
+        batch.put(b"a".to_vec(), b"1".to_vec());
+        batch.write_to(&mut cursor).unwrap();
+        let committed = cursor.get_ref().len() as u64;
+
+        // a trailing batch torn before its commit marker.
+        let mut batch = WriteBatch::new();
+        batch.put(b"b".to_vec(), b"2".to_vec());
+        batch.put(b"c".to_vec(), b"3".to_vec());
+        batch.write_to(&mut cursor).unwrap();
+        let len = cursor.get_ref().len() - commit_marker(2).size() as usize;
+        cursor.get_mut().truncate(len);
+
+        let keys: Vec<_> = recover_entries(&mut cursor).map(|e| e.key).collect();
+        assert_eq!(keys, [&b"p"[..], b"a"]);
+        assert_eq!(truncate_to_last_valid(&mut cursor).unwrap(), committed);
+        assert_eq!(cursor.get_ref().len() as u64, committed);
+
+        // a batch losing an entry to corruption is dropped whole.
+        let mut batch = WriteBatch::new();
+        batch.put(b"d".to_vec(), b"4".to_vec());
+        batch.put(b"e".to_vec(), b"5".to_vec());
+        let offsets = batch.write_to(&mut cursor).unwrap();
+        plain.write_to(&mut cursor).unwrap();
+        cursor.get_mut()[offsets[1] as usize] ^= 0xFF;
+        let keys: Vec<_> = recover_entries(&mut cursor).map(|e| e.key).collect();
+        assert_eq!(keys, [&b"p"[..], b"a", b"p"]);
+    }
+}
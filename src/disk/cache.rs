@@ -0,0 +1,155 @@
+//! Cache Module.
+//!
+//! Values of hot keys kept in memory, keyed by where their entry lives:
+//! the id of its data file and its offset there. Data files are append
+//! only, so a cached value never goes stale, a rewritten key lives at a
+//! new offset.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+/// File id and offset of a cached entry.
+type Slot = (u64, u64);
+
+/// Values bounded by their total size, the least recently used evicted
+/// first. Shared between threads, see `SnapshotReader::with_cache`.
+#[derive(Debug)]
+pub struct ValueCache {
+    capacity: u64,
+    inner: Mutex<Lru>,
+}
+
+#[derive(Debug, Default)]
+struct Lru {
+    /// value and last use of each slot.
+    values: HashMap<Slot, (Vec<u8>, u64)>,
+    /// slots by last use, oldest first.
+    uses: BTreeMap<u64, Slot>,
+    tick: u64,
+    bytes: u64,
+}
+
+impl Lru {
+    fn touch(&mut self, slot: Slot) -> Option<&Vec<u8>> {
+        self.tick += 1;
+        let (value, used) = self.values.get_mut(&slot)?;
+        self.uses.remove(used);
+        *used = self.tick;
+        self.uses.insert(self.tick, slot);
+        Some(value)
+    }
+
+    fn remove(&mut self, slot: Slot) -> Option<Vec<u8>> {
+        let (value, used) = self.values.remove(&slot)?;
+        self.uses.remove(&used);
+        self.bytes -= value.len() as u64;
+        Some(value)
+    }
+}
+
+impl ValueCache {
+    /// Cache holding up to `capacity` bytes of values.
+    pub fn new(capacity: u64) -> Self {
+        Self {
+            capacity,
+            inner: Mutex::new(Lru::default()),
+        }
+    }
+
+    /// Value of the entry at `offset` in data file `file_id`, if cached.
+    pub fn get(&self, file_id: u64, offset: u64) -> Option<Vec<u8>> {
+        self.inner.lock().unwrap().touch((file_id, offset)).cloned()
+    }
+
+    /// Cache `value` of the entry at `offset` in data file `file_id`,
+    /// evicting the least recently used values to make room. A value
+    /// larger than the whole cache is not cached.
+    pub fn insert(&self, file_id: u64, offset: u64, value: Vec<u8>) {
+        let size = value.len() as u64;
+        if size > self.capacity {
+            return;
+        }
+
+        let mut lru = self.inner.lock().unwrap();
+        lru.remove((file_id, offset));
+        while lru.bytes + size > self.capacity {
+            let Some((_, &oldest)) = lru.uses.iter().next() else {
+                break;
+            };
+            lru.remove(oldest);
+        }
+        lru.tick += 1;
+        let tick = lru.tick;
+        lru.uses.insert(tick, (file_id, offset));
+        lru.values.insert((file_id, offset), (value, tick));
+        lru.bytes += size;
+    }
+
+    /// Drop the value of the entry at `offset` in data file `file_id`.
+    pub fn remove(&self, file_id: u64, offset: u64) -> Option<Vec<u8>> {
+        self.inner.lock().unwrap().remove((file_id, offset))
+    }
+
+    /// Drop every value of data file `file_id`, such as once it is
+    /// merged away.
+    pub fn remove_file(&self, file_id: u64) {
+        let mut lru = self.inner.lock().unwrap();
+        let slots: Vec<_> = lru
+            .values
+            .keys()
+            .filter(|(id, _)| *id == file_id)
+            .copied()
+            .collect();
+        for slot in slots {
+            lru.remove(slot);
+        }
+    }
+
+    /// Total size of the cached values.
+    pub fn bytes(&self) -> u64 {
+        self.inner.lock().unwrap().bytes
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.capacity
+    }
+
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_value_cache() {
+        let cache = ValueCache::new(30);
+        cache.insert(1, 0, vec![0; 10]);
+        cache.insert(1, 64, vec![1; 10]);
+        cache.insert(2, 0, vec![2; 10]);
+        assert_eq!(cache.bytes(), 30);
+
+        // the use of (1, 0) makes (1, 64) the least recently used.
+        assert_eq!(cache.get(1, 0), Some(vec![0; 10]));
+        cache.insert(3, 0, vec![3; 5]);
+        assert_eq!(cache.get(1, 64), None);
+        assert_eq!(cache.get(1, 0), Some(vec![0; 10]));
+        assert_eq!((cache.len(), cache.bytes()), (3, 25));
+
+        cache.insert(4, 0, vec![4; 31]);
+        assert_eq!(cache.get(4, 0), None);
+        cache.insert(2, 0, vec![2; 1]);
+        assert_eq!(cache.bytes(), 16);
+
+        cache.remove_file(1);
+        assert_eq!(cache.get(1, 0), None);
+        assert_eq!(cache.remove(3, 0), Some(vec![3; 5]));
+        assert_eq!((cache.len(), cache.bytes()), (1, 1));
+    }
+}
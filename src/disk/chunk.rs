@@ -0,0 +1,173 @@
+//! Chunk Module.
+//!
+//! Values too large to hold in memory, or over the size limits, written
+//! as a run of chunk entries sharing the key, each a plain disk entry
+//! with its own crc so corruption stays local to a chunk. All flag bits
+//! are taken, so the value of each chunk starts with a prefix telling
+//! its place in the run:
+//!
+//! # chunk prefix:
+//! - chunk_index: u32
+//! - chunk_count: u32
+//!
+//! Fields are little endian, as in `Header`.
+
+use std::io::{Read, Seek, Write};
+
+use super::format::{now_timestamp, DiskEntry, DiskEntryBuilder, EntryIO};
+use crate::error::{LSMLibError, Result};
+
+/// Size of the prefix starting the value of every chunk.
+pub const CHUNK_PREFIX_SIZE: usize = 8;
+
+/// Bytes of the value held by each chunk but the last, unless asked
+/// otherwise.
+pub const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Writes and reassembles chunked values.
+pub struct ChunkedEntry;
+
+impl ChunkedEntry {
+    /// Write the `len` bytes of the value of `key` read from `r` as
+    /// chunks of `chunk_size` bytes, back to back at the current
+    /// position of `w`. Only one chunk is in memory at a time. Returns
+    /// the offset of each chunk, the first one locating the value.
+    pub fn write<R, W>(
+        key: &[u8],
+        r: &mut R,
+        len: u64,
+        chunk_size: usize,
+        w: &mut W,
+    ) -> Result<Vec<u64>>
+    where
+        R: Read,
+        W: Write + Seek,
+    {
+        if chunk_size == 0 {
+            return Err(LSMLibError::InvalidInput(
+                "chunk size must not be zero".to_string(),
+            ));
+        }
+        let count = len.div_ceil(chunk_size as u64).max(1);
+        let count = u32::try_from(count).map_err(|_| LSMLibError::ValueIsTooLarge {
+            size: len,
+            limit: u32::MAX as u64 * chunk_size as u64,
+        })?;
+
+        let timestamp = now_timestamp();
+        let mut offsets = Vec::with_capacity(count as usize);
+        let mut remaining = len;
+        for index in 0..count {
+            let n = remaining.min(chunk_size as u64) as usize;
+            let mut value = vec![0u8; CHUNK_PREFIX_SIZE + n];
+            value[0..4].copy_from_slice(&index.to_le_bytes());
+            value[4..8].copy_from_slice(&count.to_le_bytes());
+            r.read_exact(&mut value[CHUNK_PREFIX_SIZE..])?;
+            remaining -= n as u64;
+
+            let chunk = DiskEntryBuilder::new(key.to_vec(), value)
+                .timestamp(timestamp)
+                .build()?;
+            offsets.push(chunk.write_to(w)?.0);
+        }
+        Ok(offsets)
+    }
+
+    /// Copy the value whose first chunk is at `offset` of `r` to `w`,
+    /// returns its length. Chunks are read in order, each checked against
+    /// its crc, then its key and place in the run against the first one:
+    /// a broken run is a `ChunkMismatch` error telling which chunk.
+    pub fn read<R, W>(r: &mut R, offset: u64, w: &mut W) -> Result<u64>
+    where
+        R: Read + Seek,
+        W: Write,
+    {
+        let mut key = None;
+        let mut count = 1;
+        let mut index = 0;
+        let mut offset = offset;
+        let mut len = 0;
+        while index < count {
+            let chunk =
+                DiskEntry::read_from_checked(r, offset)?.ok_or(LSMLibError::ChunkMismatch {
+                    offset,
+                    field: "count",
+                })?;
+            let mismatch = |field| LSMLibError::ChunkMismatch { offset, field };
+            let value = &chunk.value[..];
+            if value.len() < CHUNK_PREFIX_SIZE {
+                return Err(mismatch("prefix"));
+            }
+            let chunk_index = u32::from_le_bytes(value[0..4].try_into().unwrap());
+            let chunk_count = u32::from_le_bytes(value[4..8].try_into().unwrap());
+
+            let key = key.get_or_insert_with(|| chunk.key.to_vec());
+            if chunk.key[..] != key[..] {
+                return Err(mismatch("key"));
+            }
+            if chunk_index != index {
+                return Err(mismatch("index"));
+            }
+            if index == 0 {
+                count = chunk_count;
+            } else if chunk_count != count {
+                return Err(mismatch("count"));
+            }
+
+            w.write_all(&value[CHUNK_PREFIX_SIZE..])?;
+            len += (value.len() - CHUNK_PREFIX_SIZE) as u64;
+            offset += chunk.size();
+            index += 1;
+        }
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    #[test]
+    fn test_chunked_entry() {
+        let value: Vec<u8> = (0..10_000u32).map(|i| i as u8).collect();
+        let mut cursor = Cursor::new(Vec::new());
+        DiskEntry::new(b"before".to_vec(), b"x".to_vec())
+            .write_to(&mut cursor)
+            .unwrap();
+        let offsets =
+            ChunkedEntry::write(b"big", &mut value.as_slice(), 10_000, 4096, &mut cursor).unwrap();
+        assert_eq!(offsets.len(), 3);
+
+        let mut out = Vec::new();
+        let len = ChunkedEntry::read(&mut cursor, offsets[0], &mut out).unwrap();
+        assert_eq!((len, out), (10_000, value));
+
+        let mut out = Vec::new();
+        ChunkedEntry::write(b"empty", &mut &[][..], 0, 4096, &mut cursor).unwrap();
+        let last = DiskEntry::read_at(&mut cursor, offsets[2])
+            .unwrap()
+            .unwrap()
+            .1;
+        assert_eq!(ChunkedEntry::read(&mut cursor, last, &mut out).unwrap(), 0);
+
+        // starting mid-run, then a corrupted chunk.
+        assert!(matches!(
+            ChunkedEntry::read(&mut cursor, offsets[1], &mut Vec::new()),
+            Err(LSMLibError::ChunkMismatch { field: "index", .. })
+        ));
+        cursor.get_mut()[offsets[1] as usize + 100] ^= 0xFF;
+        assert!(matches!(
+            ChunkedEntry::read(&mut cursor, offsets[0], &mut Vec::new()),
+            Err(LSMLibError::CrcMismatch { offset: Some(o), .. }) if o == offsets[1]
+        ));
+
+        let len = cursor.get_ref().len();
+        assert!(matches!(
+            ChunkedEntry::write(b"zero", &mut &[0u8; 10][..], 10, 0, &mut cursor),
+            Err(LSMLibError::InvalidInput(_))
+        ));
+        assert_eq!(cursor.get_ref().len(), len);
+    }
+}
@@ -0,0 +1,62 @@
+//! Cipher Module.
+
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Nonce};
+
+use crate::error::{LSMLibError, Result};
+
+/// Size of the random nonce starting every sealed value.
+pub const NONCE_SIZE: usize = 12;
+
+/// Size of the authentication tag ending every sealed value.
+pub const TAG_SIZE: usize = 16;
+
+/// ChaCha20-Poly1305 cipher encrypting entry values at rest, see
+/// `DiskEntry::new_encrypted`.
+#[derive(Clone)]
+pub struct Cipher(ChaCha20Poly1305);
+
+impl Cipher {
+    pub fn new(key: &[u8; 32]) -> Self {
+        Self(ChaCha20Poly1305::new(key.into()))
+    }
+
+    /// Encrypt `plaintext` under a random nonce, authenticating `aad`
+    /// along with it. Returns the nonce, the ciphertext then the tag.
+    pub(crate) fn seal(&self, aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let sealed = self
+            .0
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: plaintext,
+                    aad,
+                },
+            )
+            .map_err(|_| LSMLibError::Custom("failed to encrypt value".to_string()))?;
+
+        let mut buf = Vec::with_capacity(NONCE_SIZE + sealed.len());
+        buf.extend_from_slice(&nonce);
+        buf.extend_from_slice(&sealed);
+        Ok(buf)
+    }
+
+    /// Decrypt what `seal` returned for `aad`, `None` if it was sealed
+    /// with another key or for another `aad`, or was tampered with.
+    pub(crate) fn open(&self, aad: &[u8], sealed: &[u8]) -> Option<Vec<u8>> {
+        if sealed.len() < NONCE_SIZE + TAG_SIZE {
+            return None;
+        }
+        let (nonce, msg) = sealed.split_at(NONCE_SIZE);
+        self.0
+            .decrypt(Nonce::from_slice(nonce), Payload { msg, aad })
+            .ok()
+    }
+}
+
+impl std::fmt::Debug for Cipher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Cipher(..)")
+    }
+}
@@ -1,19 +1,191 @@
 //! CRC Module.
+//!
+//! Checksums of entries and hints. As in `layout`, only `core` is used
+//! here, I/O is left to `format`, so both build without `std`.
+#![deny(
+    clippy::std_instead_of_core,
+    clippy::std_instead_of_alloc,
+    clippy::alloc_instead_of_core
+)]
 
-pub(super) fn hash(k: &[u8], v: &[u8]) -> u32 {
-    let mut hasher = crc32fast::Hasher::new();
+/// Checksum algorithm protecting disk entries.
+pub trait Checksum: Default {
+    /// Whether the checksum is 64 bits wide, entries then store its
+    /// upper half in an extra header field.
+    const WIDE: bool = false;
+
+    fn update(&mut self, bytes: &[u8]);
+
+    fn finalize(self) -> u64;
+
+    /// Checksum of an entry with no flags set.
+    fn compute(key: &[u8], value: &[u8]) -> u64 {
+        hash::<Self>(key, value, 0, &[])
+    }
+}
+
+/// CRC32 checksum, used by all files written without the `crc64`
+/// feature.
+#[derive(Default)]
+pub struct Crc32(crc32fast::Hasher);
+
+impl Checksum for Crc32 {
+    fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    fn finalize(self) -> u64 {
+        // we XOR the hash to make sure it's something other than 0 when empty,
+        // because 0 is an easy value to create accidentally or via corruption.
+        (self.0.finalize() ^ 0xFF) as u64
+    }
+}
+
+/// Lookup table of CRC-64/XZ, reflected polynomial of ECMA-182.
+const CRC64_TABLE: [u64; 256] = {
+    let mut table = [0u64; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut crc = i as u64;
+        let mut bit = 0;
+        while bit < 8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xC96C_5795_D787_0F42
+            } else {
+                crc >> 1
+            };
+            bit += 1;
+        }
+        table[i] = crc;
+        i += 1;
+    }
+    table
+};
+
+/// CRC64 checksum, for stores large enough for CRC32 collisions to let
+/// corruption through. Entries written with it are readable whatever
+/// the features.
+pub struct Crc64(u64);
+
+impl Default for Crc64 {
+    fn default() -> Self {
+        Self(!0)
+    }
+}
+
+impl Checksum for Crc64 {
+    const WIDE: bool = true;
+
+    fn update(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = CRC64_TABLE[((self.0 ^ b as u64) & 0xFF) as usize] ^ (self.0 >> 8);
+        }
+    }
+
+    fn finalize(self) -> u64 {
+        // same XOR as `Crc32`.
+        !self.0 ^ 0xFF
+    }
+}
+
+/// Checksum algorithm of an entry, told by its crc64 flag: readers pick
+/// the right one whatever the features, see `detect_checksum`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ChecksumKind {
+    /// Entries without the crc64 flag, all those written before it.
+    Crc32,
+
+    Crc64,
+}
+
+impl ChecksumKind {
+    pub fn of<C: Checksum>() -> Self {
+        if C::WIDE {
+            Self::Crc64
+        } else {
+            Self::Crc32
+        }
+    }
+}
+
+/// Checksum used unless another one is asked for.
+#[cfg(not(feature = "crc64"))]
+pub type DefaultChecksum = Crc32;
+
+/// Checksum used unless another one is asked for.
+#[cfg(feature = "crc64")]
+pub type DefaultChecksum = Crc64;
+
+/// Checksum of an entry, see `EntryHasher`.
+///
+/// Key and value are fed back to back, the boundary between them is
+/// told by the `key_sz` field among the `header` bytes: with the header
+/// crc flag, set on all new entries, "ab"/"c" and "a"/"bc" can't share a
+/// checksum. Entries written before it can, and keep their checksums.
+/// The `header` bytes of an entry are `layout::entry::covered`.
+pub fn hash<C: Checksum>(k: &[u8], v: &[u8], flags: u8, header: &[u8]) -> u64 {
+    let mut hasher = EntryHasher::<C>::new();
     hasher.update(k);
     hasher.update(v);
+    hasher.finalize_with(flags, header)
+}
 
-    // we XOR the hash to make sure it's something other than 0 when empty,
-    // because 0 is an easy value to create accidentally or via corruption.
-    hasher.finalize() ^ 0xFF
+/// Incremental entry checksum, fed the key then the value in as many
+/// pieces as needed, so a large value never has to be in memory whole.
+#[derive(Default)]
+pub struct EntryHasher<C: Checksum = DefaultChecksum>(C);
+
+impl<C: Checksum> EntryHasher<C> {
+    pub fn new() -> Self {
+        Self(C::default())
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        self.0.update(bytes);
+    }
+
+    /// Checksum of an entry with no flags set.
+    pub fn finalize(self) -> u64 {
+        self.0.finalize()
+    }
+
+    /// Checksum of an entry with `flags` set, covering the `header`
+    /// bytes the flags ask for.
+    pub fn finalize_with(mut self, flags: u8, header: &[u8]) -> u64 {
+        // flags are only mixed in when some are set, so entries written
+        // before the flags byte existed still validate. Header bytes are
+        // only covered when a flag asks for it.
+        if flags != 0 {
+            self.0.update(&[flags]);
+            self.0.update(header);
+        }
+
+        self.0.finalize()
+    }
 }
 
+/// Checksum of a batch or frame length, so a corrupted length is
+/// detected before the bytes it covers are read.
 #[inline]
-pub(super) fn hash_batch_len(len: usize) -> u32 {
+pub fn hash_batch_len(len: usize) -> u32 {
     let mut hasher = crc32fast::Hasher::new();
     hasher.update(&(len as u64).to_le_bytes());
 
     hasher.finalize() ^ 0xFF
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crc64() {
+        // check value of CRC-64/XZ, before the XOR.
+        let mut crc = Crc64::default();
+        crc.update(b"12345");
+        crc.update(b"6789");
+        assert_eq!(crc.finalize() ^ 0xFF, 0x995D_C9BB_DF19_39FA);
+
+        assert_ne!(Crc64::compute(b"", b""), 0);
+    }
+}
@@ -1,443 +1,7278 @@
 //! Format: Entries Module.
 
 use std::{
+    cmp::Ordering,
+    collections::{HashSet, VecDeque},
     fmt::Display,
-    io::{Read, Seek, SeekFrom, Write},
+    fs::File,
+    io::{self, BufWriter, Cursor, Read, Seek, SeekFrom, Write},
+    ops::{BitOr, Range},
+    time::Duration,
 };
 
-use crate::disk::crc::hash;
-use crate::error::Result;
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+#[cfg(feature = "serde")]
+use std::borrow::Cow;
+
+use crate::clock::{Clock, SystemClock};
+use crate::disk::batch;
+#[cfg(feature = "encryption")]
+use crate::disk::cipher::Cipher;
+use crate::disk::crc::{
+    hash, hash_batch_len, Checksum, ChecksumKind, Crc32, Crc64, DefaultChecksum, EntryHasher,
+};
+use crate::disk::vlog::{ValueLogReader, ValuePointer};
+use crate::error::{LSMLibError, Result};
 
 /// EntryIO trait.
 pub trait EntryIO {
     type Entry;
 
+    /// Read the entry at `offset` of `r`, `None` at EOF. Disk entries are
+    /// returned with their offset set.
     fn read_from<R>(r: &mut R, offset: u64) -> Result<Option<Self::Entry>>
     where
         R: Read + Seek;
 
-    fn write_to<W>(&self, w: &mut W) -> Result<u64>
+    /// Write the entry at the current position of `w`, returns the
+    /// offset it starts at and the number of bytes written.
+    fn write_to<W>(&self, w: &mut W) -> Result<(u64, u64)>
     where
-        W: Write + Seek;
-}
-
-pub const HEADER_SIZE: usize = 16;
+        W: Write + Seek,
+    {
+        let offset = w.stream_position()?;
+        self.write_to_at(w, offset)
+    }
 
-/// Entry Header
-///
-/// # fields:
-/// - crc: u32
-/// - timestamp: u32
-/// - key_sz: u32
-/// - value_sz: u32
-///
-#[derive(Debug, Clone)]
-pub struct Header([u8; HEADER_SIZE]);
+    /// Same as `write_to`, for callers tracking the position of `w`
+    /// themselves, `offset` is taken as is and returned.
+    fn write_to_at<W>(&self, w: &mut W, offset: u64) -> Result<(u64, u64)>
+    where
+        W: Write;
+}
 
-impl Header {
-    pub fn new(crc: u32, timestamp: u32, key_sz: u32, value_sz: u32) -> Self {
-        let mut buf = [0u8; HEADER_SIZE];
+/// Writer whose written data can be made durable.
+pub trait Syncable {
+    /// Flush buffered data and wait until it reached the storage device.
+    fn sync_data(&mut self) -> io::Result<()>;
+}
 
-        buf[0..4].copy_from_slice(&crc.to_le_bytes());
-        buf[4..8].copy_from_slice(&timestamp.to_le_bytes());
-        buf[8..12].copy_from_slice(&key_sz.to_le_bytes());
-        buf[12..16].copy_from_slice(&value_sz.to_le_bytes());
+impl Syncable for File {
+    fn sync_data(&mut self) -> io::Result<()> {
+        File::sync_data(self)
+    }
+}
 
-        Self(buf)
+impl<W: Write + Syncable> Syncable for BufWriter<W> {
+    fn sync_data(&mut self) -> io::Result<()> {
+        self.flush()?;
+        self.get_mut().sync_data()
     }
+}
 
-    pub fn crc(&self) -> u32 {
-        u32::from_le_bytes(self.0[0..4].try_into().unwrap())
+/// In-memory buffers have nothing to sync.
+impl<T> Syncable for Cursor<T> {
+    fn sync_data(&mut self) -> io::Result<()> {
+        Ok(())
     }
+}
 
-    pub fn timestamp(&self) -> u32 {
-        u32::from_le_bytes(self.0[4..8].try_into().unwrap())
+impl<S: Syncable + ?Sized> Syncable for &mut S {
+    fn sync_data(&mut self) -> io::Result<()> {
+        (**self).sync_data()
     }
+}
+
+/// File whose length can be changed.
+pub trait SetLen {
+    /// Truncate or extend to `len` bytes, the position is left as is.
+    fn set_len(&mut self, len: u64) -> io::Result<()>;
+}
 
-    pub fn key_sz(&self) -> u32 {
-        u32::from_le_bytes(self.0[8..12].try_into().unwrap())
+impl SetLen for File {
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        File::set_len(self, len)
     }
+}
 
-    pub fn value_sz(&self) -> u32 {
-        u32::from_le_bytes(self.0[12..16].try_into().unwrap())
+impl SetLen for Cursor<Vec<u8>> {
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        self.get_mut().resize(len as usize, 0);
+        Ok(())
     }
 }
 
-impl AsRef<[u8]> for Header {
-    fn as_ref(&self) -> &[u8] {
-        &self.0
+impl SetLen for Cursor<&mut Vec<u8>> {
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        self.get_mut().resize(len as usize, 0);
+        Ok(())
     }
 }
 
-impl From<[u8; HEADER_SIZE]> for Header {
-    fn from(buf: [u8; HEADER_SIZE]) -> Self {
-        Self(buf)
+impl<S: SetLen + ?Sized> SetLen for &mut S {
+    fn set_len(&mut self, len: u64) -> io::Result<()> {
+        (**self).set_len(len)
     }
 }
 
-/// Disk Entry
-#[derive(Debug, Clone)]
-pub struct DiskEntry {
-    /// header of the disk entry.
-    header: Header,
+/// Source readable at any offset through a shared reference, so any
+/// number of threads can read it at once without a lock.
+pub trait ReadAt {
+    /// Fill `buf` with the bytes at `offset`, an `UnexpectedEof` error
+    /// if too few remain.
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()>;
+}
 
-    /// key of the disk entry.
-    pub key: Vec<u8>,
+#[cfg(unix)]
+impl ReadAt for File {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        std::os::unix::fs::FileExt::read_exact_at(self, buf, offset)
+    }
+}
 
-    /// value of the disk entry.
-    pub value: Vec<u8>,
+#[cfg(windows)]
+impl ReadAt for File {
+    fn read_exact_at(&self, mut buf: &mut [u8], mut offset: u64) -> io::Result<()> {
+        use std::os::windows::fs::FileExt;
+        while !buf.is_empty() {
+            match self.seek_read(buf, offset) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => {
+                    buf = &mut buf[n..];
+                    offset += n as u64;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+}
 
-    /// offset of the disk entry in the disk file.
-    pub offset: Option<u64>,
+impl ReadAt for [u8] {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        let start = usize::try_from(offset).unwrap_or(usize::MAX);
+        let bytes = start
+            .checked_add(buf.len())
+            .and_then(|end| self.get(start..end))
+            .ok_or(io::ErrorKind::UnexpectedEof)?;
+        buf.copy_from_slice(bytes);
+        Ok(())
+    }
+}
 
-    /// file id of the disk entry may stored.
-    pub file_id: Option<u64>,
+impl ReadAt for Vec<u8> {
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        self.as_slice().read_exact_at(buf, offset)
+    }
 }
 
-impl DiskEntry {
-    pub fn new(key: Vec<u8>, value: Vec<u8>) -> Self {
-        let crc = hash(&key, &value);
-        let timestamp = chrono::Utc::now().timestamp().try_into().unwrap();
-        let key_sz = key.len() as u32;
-        let value_sz = value.len() as u32;
-        let header = Header::new(crc, timestamp, key_sz, value_sz);
+/// Part of an entry, or of a hint, reported as truncated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Header,
+    Key,
+    Value,
+}
 
-        Self {
-            header,
-            key,
-            value,
-            offset: None,
-            file_id: None,
+impl Field {
+    /// First part not fitting in `available` bytes, for a header of
+    /// `header_sz` bytes followed by a key of `key_sz` bytes.
+    pub(super) fn short_of(available: u64, header_sz: usize, key_sz: u64) -> Self {
+        if available < header_sz as u64 {
+            Field::Header
+        } else if available - (header_sz as u64) < key_sz {
+            Field::Key
+        } else {
+            Field::Value
         }
     }
+}
 
-    pub fn crc(&self) -> u32 {
-        self.header.crc()
+impl Display for Field {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Field::Header => write!(f, "header"),
+            Field::Key => write!(f, "key"),
+            Field::Value => write!(f, "value"),
+        }
     }
+}
 
-    pub fn timestamp(&self) -> u32 {
-        self.header.timestamp()
+/// Round `pos` up to the next multiple of `align`, an `align` of 0 or 1
+/// leaves it unchanged.
+pub fn align_up(pos: u64, align: u64) -> u64 {
+    if align <= 1 {
+        pos
+    } else {
+        pos.div_ceil(align) * align
     }
+}
 
-    pub fn size(&self) -> u64 {
-        (HEADER_SIZE + self.key.len() + self.value.len()) as u64
+/// Largest entry read and written by `DiskEntry::read_small` and
+/// `DiskEntry::write_small` through a stack buffer.
+pub const SMALL_ENTRY_SIZE: usize = 256;
+
+/// Marker starting every frame written by `DiskEntry::write_frame`.
+pub const FRAME_MAGIC: [u8; 4] = *b"LSMF";
+
+/// Size of the frame prefix: magic, entry length and crc of the length.
+pub const FRAME_PREFIX_SIZE: usize = 16;
+
+pub use super::layout::{HEADER_SIZE, HINT_HEADER_SIZE, WIDE_HEADER_SIZE, WIDE_HINT_HEADER_SIZE};
+
+use super::layout::{
+    self, ext_size, FLAGS_POS, FLAG_COMPRESSED, FLAG_CRC64, FLAG_ENCRYPTED, FLAG_EXPIRE,
+    FLAG_HEADER_CRC, FLAG_HINT_CRC, FLAG_SEQ, FLAG_TOMBSTONE, HEADER_BUF_SIZE, HINT_CRC_SIZE,
+    MAX_HEADER_SIZE, MAX_HINT_HEADER_SIZE, VERSION_POS,
+};
+
+/// Flags of a new entry whose crc is computed with `C`.
+fn checksum_flags<C: Checksum>() -> u8 {
+    if C::WIDE {
+        FLAG_HEADER_CRC | FLAG_CRC64
+    } else {
+        FLAG_HEADER_CRC
     }
+}
 
-    pub fn entry_size(k: &[u8], v: &[u8]) -> u64 {
-        (HEADER_SIZE + k.len() + v.len()) as u64
+/// Crc of an entry with `header`, a `Crc64` if the header says so. Else
+/// it's computed with `C`, or `Crc32` if `C` is 64 bits wide, as entries
+/// without the flag predate 64 bits crcs.
+fn entry_crc<C: Checksum>(header: &Header, key: &[u8], value: &[u8]) -> u64 {
+    let (flags, covered) = (header.flag_bits(), header.covered());
+    if flags & FLAG_CRC64 != 0 {
+        hash::<Crc64>(key, value, flags, covered)
+    } else if C::WIDE {
+        hash::<Crc32>(key, value, flags, covered)
+    } else {
+        hash::<C>(key, value, flags, covered)
     }
+}
 
-    pub fn offset(mut self, offset: u64) -> Self {
-        self.offset = Some(offset);
-        self
+/// Buffer holding the key and value of a disk entry, `bytes::Bytes`
+/// with the `bytes` feature for cheap clones and slicing.
+#[cfg(not(feature = "bytes"))]
+pub type EntryBuf = Vec<u8>;
+
+/// Buffer holding the key and value of a disk entry, `bytes::Bytes`
+/// with the `bytes` feature for cheap clones and slicing.
+#[cfg(feature = "bytes")]
+pub type EntryBuf = bytes::Bytes;
+
+/// Turn an entry buffer into a `Vec`, without copying when it is one.
+#[cfg(not(feature = "bytes"))]
+pub fn into_vec(buf: EntryBuf) -> Vec<u8> {
+    buf
+}
+
+/// Turn an entry buffer into a `Vec`, without copying when it is one.
+#[cfg(feature = "bytes")]
+pub fn into_vec(buf: EntryBuf) -> Vec<u8> {
+    buf.into()
+}
+
+#[cfg(not(feature = "bytes"))]
+pub(super) fn from_vec(v: Vec<u8>) -> EntryBuf {
+    v
+}
+
+#[cfg(feature = "bytes")]
+pub(super) fn from_vec(v: Vec<u8>) -> EntryBuf {
+    v.into()
+}
+
+/// Order of writes to the same key, see `DiskEntry::recency`.
+pub type Recency = (Option<u64>, u32);
+
+/// Compression codec for entry values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    /// zstd at the given compression level.
+    Zstd(i32),
+}
+
+impl Codec {
+    fn compress(self, value: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Codec::Zstd(level) => Ok(zstd::bulk::compress(value, level)?),
+        }
     }
+}
 
-    pub fn file_id(mut self, file_id: u64) -> Self {
-        self.file_id = Some(file_id);
-        self
+/// Decompress a stored value, the level is not needed to decode zstd.
+fn decompress(stored: &[u8]) -> Result<Vec<u8>> {
+    Ok(zstd::stream::decode_all(stored)?)
+}
+
+/// Current time as stored in headers, see `SystemClock`.
+pub(crate) fn now_timestamp() -> u32 {
+    SystemClock.now_secs()
+}
+
+/// Header timestamp as a `DateTime`.
+fn to_datetime(timestamp: u32) -> DateTime<Utc> {
+    Utc.timestamp_opt(timestamp as i64, 0).unwrap()
+}
+
+/// Total length of the stream, leaves the stream at its end.
+fn stream_len<S: Seek>(s: &mut S) -> Result<u64> {
+    Ok(s.seek(SeekFrom::End(0))?)
+}
+
+/// Bytes of the stream from `offset` on, an `OffsetOutOfRange` error
+/// past its end: such an offset comes from a corrupted index, not a
+/// clean EOF.
+fn available_at<S: Seek>(s: &mut S, offset: u64) -> Result<u64> {
+    let len = stream_len(s)?;
+    if offset > len {
+        return Err(LSMLibError::OffsetOutOfRange { offset, len });
     }
+    Ok(len - offset)
+}
 
-    pub fn is_validate(&self) -> bool {
-        self.header.crc() == hash(&self.key, &self.value)
+/// `Padding` error for the all-zero header prefix read at `offset`,
+/// measuring the zero run starting there up to the next non-zero byte or
+/// EOF. A valid header never is all zero, as its crc is not.
+fn padding<R: Read + Seek>(r: &mut R, offset: u64) -> Result<LSMLibError> {
+    r.seek(SeekFrom::Start(offset))?;
+    let mut buf = [0u8; 4096];
+    let mut len = 0;
+    loop {
+        let n = r.read(&mut buf)?;
+        match buf[..n].iter().position(|b| *b != 0) {
+            Some(pos) => {
+                return Ok(LSMLibError::Padding {
+                    offset,
+                    len: len + pos as u64,
+                })
+            }
+            None if n == 0 => return Ok(LSMLibError::Padding { offset, len }),
+            None => len += n as u64,
+        }
     }
+}
 
-    pub fn crc_expected(&self) -> u32 {
-        self.header.crc()
+/// Fill `buf` from `r` up to EOF, returns the number of bytes read: 0 at
+/// a clean EOF, less than `buf.len()` for a stream ending within it.
+fn read_full<R: Read>(r: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match r.read(&mut buf[n..]) {
+            Ok(0) => break,
+            Ok(read) => n += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
     }
+    Ok(n)
+}
 
-    pub fn crc_actual(&self) -> u32 {
-        hash(&self.key, &self.value)
+/// Turn an `UnexpectedEof` from reading `field` of the entry at `offset`
+/// into a `TruncatedEntry` error.
+pub(super) fn short_read(
+    e: io::Error,
+    field: Field,
+    offset: u64,
+    needed: u64,
+    available: u64,
+) -> LSMLibError {
+    if e.kind() == io::ErrorKind::UnexpectedEof {
+        LSMLibError::TruncatedEntry {
+            field,
+            offset,
+            needed,
+            available,
+        }
+    } else {
+        e.into()
     }
 }
 
-impl Display for DiskEntry {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "DiskEntry(file_id={:?}, key='{}', offset={:?}, size={})",
-            self.file_id,
-            String::from_utf8_lossy(self.key.as_ref()),
-            self.offset,
-            self.size(),
-        )
+fn hexdump(bytes: &[u8]) -> String {
+    use std::fmt::Write;
+
+    let mut out = String::new();
+    for (i, line) in bytes.chunks(16).enumerate() {
+        let _ = write!(out, "{:08x} ", i * 16);
+        for j in 0..16 {
+            if j == 8 {
+                out.push(' ');
+            }
+            match line.get(j) {
+                Some(b) => {
+                    let _ = write!(out, " {:02x}", b);
+                }
+                None => out.push_str("   "),
+            }
+        }
+        out.push_str("  |");
+        out.extend(line.iter().map(|&b| {
+            if b.is_ascii_graphic() || b == b' ' {
+                b as char
+            } else {
+                '.'
+            }
+        }));
+        out.push_str("|\n");
     }
+    out
 }
 
-impl EntryIO for DiskEntry {
-    type Entry = Self;
+/// Flags byte of an entry header, covered by the entry crc.
+///
+/// Attribute flags tell how to interpret the entry, layout flags which
+/// optional fields the header holds and are set along with them. All
+/// eight bits are assigned, a new attribute needs a new format version.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct EntryFlags(u8);
 
-    fn read_from<R>(r: &mut R, offset: u64) -> Result<Option<Self::Entry>>
-    where
-        R: Read + Seek,
-    {
-        r.seek(SeekFrom::Start(offset))?;
+impl EntryFlags {
+    pub const TOMBSTONE: Self = Self(FLAG_TOMBSTONE);
+    pub const COMPRESSED: Self = Self(FLAG_COMPRESSED);
+    pub const ENCRYPTED: Self = Self(FLAG_ENCRYPTED);
+    pub const HEADER_CRC: Self = Self(FLAG_HEADER_CRC);
+    pub const SEQ: Self = Self(FLAG_SEQ);
+    pub const EXPIRE: Self = Self(FLAG_EXPIRE);
+    pub const CRC64: Self = Self(FLAG_CRC64);
+    pub const HINT_CRC: Self = Self(FLAG_HINT_CRC);
 
-        let mut buf = [0u8; HEADER_SIZE];
-        if r.read(&mut buf)? == 0 {
-            return Ok(None);
-        }
+    /// Flags settable with `Header::with_flag`.
+    pub const ATTRIBUTES: Self = Self(FLAG_TOMBSTONE | FLAG_COMPRESSED | FLAG_ENCRYPTED);
 
-        let header = Header::from(buf);
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
 
-        let mut key = vec![0u8; header.key_sz() as usize];
-        r.read_exact(&mut key)?;
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
 
-        let mut value = vec![0u8; header.value_sz() as usize];
-        r.read_exact(&mut value)?;
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
 
-        Ok(Some(Self {
-            header,
-            key,
-            value,
-            offset: None,
-            file_id: None,
-        }))
+impl BitOr for EntryFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
     }
+}
 
-    fn write_to<W>(&self, w: &mut W) -> Result<u64>
-    where
-        W: Write + Seek,
-    {
-        let offset = w.stream_position()?;
+/// Layout version of entry headers, stored in the version byte.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum FormatVersion {
+    /// 16 bytes header with 24 bits sizes. Baseline headers, written
+    /// before the version byte existed, are 16 bytes long too and read
+    /// as compact, see `layout::is_baseline`.
+    Compact = 0,
 
-        w.write_all(self.header.as_ref())?;
-        w.write_all(self.key.as_ref())?;
-        w.write_all(self.value.as_ref())?;
+    /// 32 bytes header with 64 bits sizes.
+    Wide = 1,
+}
 
-        Ok(offset)
+impl FormatVersion {
+    /// Smallest layout able to hold the given sizes.
+    pub const fn for_sizes(key_sz: u64, value_sz: u64) -> Self {
+        if layout::needs_wide(key_sz, value_sz) {
+            Self::Wide
+        } else {
+            Self::Compact
+        }
+    }
+
+    /// Size of the layout fields, see `Header::encoded_len`.
+    pub const fn header_size(self) -> usize {
+        match self {
+            Self::Compact => HEADER_SIZE,
+            Self::Wide => WIDE_HEADER_SIZE,
+        }
+    }
+
+    pub const fn hint_header_size(self) -> usize {
+        match self {
+            Self::Compact => HINT_HEADER_SIZE,
+            Self::Wide => WIDE_HINT_HEADER_SIZE,
+        }
     }
 }
 
-pub const HINT_HEADER_SIZE: usize = 20;
+impl TryFrom<u8> for FormatVersion {
+    type Error = LSMLibError;
 
-/// Hint Entry Header Structure.
+    fn try_from(v: u8) -> Result<Self> {
+        match v {
+            0 => Ok(Self::Compact),
+            1 => Ok(Self::Wide),
+            _ => Err(LSMLibError::UnknownVersion(v)),
+        }
+    }
+}
+
+/// Entry Header
 ///
-/// # fields:
-/// - offset: u64
-/// - key_sz: u32
-/// - value_sz: u32
+/// All layouts share the first 16 bytes, so the version and flags are
+/// known before the rest of the header is read.
+///
+/// Fields are little endian on every host, so files, including all
+/// written so far, are shared between little and big endian machines
+/// as is and need no byte order option or migration.
+///
+/// # compact fields:
+/// - crc: u32
 /// - timestamp: u32
+/// - key_sz: u24
+/// - version: u8
+/// - value_sz: u24
+/// - flags: u8
 ///
-#[derive(Debug)]
-pub struct HintHeader([u8; HINT_HEADER_SIZE]);
+/// # wide fields:
+/// - crc: u32
+/// - timestamp: u32
+/// - reserved: [u8; 3]
+/// - version: u8
+/// - reserved: [u8; 3]
+/// - flags: u8
+/// - key_sz: u64
+/// - value_sz: u64
+///
+/// # optional fields, following the layout fields:
+/// - seq: u64, if the seq flag is set.
+/// - expire_at: u32, if the expire flag is set.
+/// - crc_hi: u32, upper half of the crc, if the crc64 flag is set.
+///
+#[derive(Debug, Clone, Copy)]
+pub struct Header([u8; HEADER_BUF_SIZE]);
 
-impl HintHeader {
-    pub fn new(offset: u64, key_sz: u32, value_sz: u32, timestamp: u32) -> Self {
-        let mut buf = [0u8; HINT_HEADER_SIZE];
+// the largest header holds every optional field.
+const _: () = assert!(
+    Header::encoded_len(FormatVersion::Compact) == HEADER_SIZE
+        && Header::encoded_len(FormatVersion::Wide) == WIDE_HEADER_SIZE
+        && Header::encoded_len_with(
+            FormatVersion::Wide,
+            EntryFlags::from_bits(FLAG_SEQ | FLAG_EXPIRE | FLAG_CRC64)
+        ) == MAX_HEADER_SIZE
+);
 
-        buf[0..8].copy_from_slice(&offset.to_le_bytes());
-        buf[8..12].copy_from_slice(&key_sz.to_le_bytes());
-        buf[12..16].copy_from_slice(&value_sz.to_le_bytes());
-        buf[16..20].copy_from_slice(&timestamp.to_le_bytes());
+impl Header {
+    /// Encoded size of the layout fields of `version`, where the optional
+    /// fields start. Offsets within entries are computed from it rather
+    /// than from `HEADER_SIZE`, the size of the compact layout only.
+    pub const fn encoded_len(version: FormatVersion) -> usize {
+        version.header_size()
+    }
 
-        Self(buf)
+    /// Encoded size of a header of `version` with `flags`, optional
+    /// fields included.
+    pub const fn encoded_len_with(version: FormatVersion, flags: EntryFlags) -> usize {
+        Self::encoded_len(version) + ext_size(flags.bits())
     }
 
-    pub fn offset(&self) -> u64 {
-        u64::from_le_bytes(self.0[0..8].try_into().unwrap())
+    /// The upper half of `crc` is only stored with the crc64 flag.
+    pub fn new(crc: u64, timestamp: u32, key_sz: u64, value_sz: u64, flags: u8) -> Self {
+        let mut header = Self([0u8; HEADER_BUF_SIZE]);
+        layout::entry::init(&mut header.0, timestamp, key_sz, value_sz, flags);
+        header.set_crc(crc);
+        header
     }
 
-    pub fn key_sz(&self) -> usize {
-        u32::from_le_bytes(self.0[8..12].try_into().unwrap()) as usize
+    /// Header holding exactly `fields`, nothing is checked: the crc is
+    /// not recomputed and flags are taken as given. Meant for fixtures
+    /// and format migrations crafting specific byte patterns.
+    ///
+    /// Without the header crc flag this is a baseline header, holding
+    /// neither flags nor optional fields, see `layout::is_baseline`.
+    pub fn from_fields(fields: &HeaderFields) -> Self {
+        let mut header = Self::new(
+            fields.crc,
+            fields.timestamp,
+            fields.key_sz,
+            fields.value_sz,
+            fields.flags,
+        );
+        if let Some(seq) = fields.seq {
+            header.set_seq(seq);
+        }
+        if let Some(expire_at) = fields.expire_at {
+            header.set_expire_at(expire_at);
+        }
+        header
     }
 
-    pub fn value_sz(&self) -> usize {
-        u32::from_le_bytes(self.0[12..16].try_into().unwrap()) as usize
+    /// Crc of the entry, 64 bits wide with the crc64 flag.
+    pub fn crc(&self) -> u64 {
+        layout::entry::crc(&self.0)
     }
 
     pub fn timestamp(&self) -> u32 {
-        u32::from_le_bytes(self.0[16..20].try_into().unwrap())
+        layout::entry::timestamp(&self.0)
     }
-}
 
-impl AsRef<[u8; HINT_HEADER_SIZE]> for HintHeader {
-    fn as_ref(&self) -> &[u8; HINT_HEADER_SIZE] {
-        &self.0
+    pub fn key_sz(&self) -> u64 {
+        layout::entry::key_sz(&self.0)
     }
-}
 
-impl From<[u8; HINT_HEADER_SIZE]> for HintHeader {
-    fn from(buf: [u8; HINT_HEADER_SIZE]) -> Self {
-        Self(buf)
+    pub fn value_sz(&self) -> u64 {
+        layout::entry::value_sz(&self.0)
     }
-}
 
-/// Entry in the hint file.
-#[derive(Debug)]
-pub struct HintEntry {
-    /// header of hint entry.
-    header: HintHeader,
+    pub fn seq(&self) -> Option<u64> {
+        layout::entry::seq(&self.0)
+    }
 
-    /// key of disk entry.
-    pub key: Vec<u8>,
+    /// Must be set before the expiry time and the crc.
+    fn set_seq(&mut self, seq: u64) {
+        layout::entry::set_seq(&mut self.0, seq)
+    }
 
-    /// file_id of hint entry, also is disk entry.
-    pub file_id: Option<u64>,
-}
+    /// Expiry time, seconds since the unix epoch.
+    pub fn expire_at(&self) -> Option<u32> {
+        layout::entry::expire_at(&self.0)
+    }
 
-impl HintEntry {
-    pub fn new(key: Vec<u8>, offset: u64, size: u64, timestamp: u32) -> Self {
-        let key_sz = key.len() as u32;
-        let value_sz = size as u32 - HEADER_SIZE as u32 - key_sz;
-        let header = HintHeader::new(offset, key_sz, value_sz, timestamp);
-        Self {
-            header,
-            key,
-            file_id: None,
-        }
+    /// Must be set before the crc.
+    fn set_expire_at(&mut self, expire_at: u32) {
+        layout::entry::set_expire_at(&mut self.0, expire_at)
     }
 
-    pub fn offset(&self) -> u64 {
-        self.header.offset()
+    fn set_crc(&mut self, crc: u64) {
+        layout::entry::set_crc(&mut self.0, crc)
     }
 
-    pub fn size(&self) -> u64 {
-        (HEADER_SIZE + self.header.key_sz() + self.header.value_sz()) as u64
+    /// Whether this is the header of a batch marker, see `batch`.
+    pub fn is_batch_marker(&self) -> bool {
+        layout::entry::is_marker(&self.0)
     }
 
-    pub fn timestamp(&self) -> u32 {
-        self.header.timestamp()
+    /// Must be set before the crc.
+    fn set_batch_marker(&mut self) {
+        layout::entry::set_marker(&mut self.0)
     }
 
-    pub fn hint_size(&self) -> u64 {
-        HINT_HEADER_SIZE as u64 + self.key.len() as u64
+    /// Header bytes covered by the entry crc, besides the flags: all
+    /// but the crc itself with the header crc flag, else only the
+    /// optional fields besides the crc.
+    fn covered(&self) -> &[u8] {
+        layout::entry::covered(&self.0)
     }
 
-    pub fn file_id(mut self, file_id: u64) -> Self {
-        self.file_id = Some(file_id);
-        self
+    /// Encoded size of this header.
+    pub fn size(&self) -> usize {
+        Self::encoded_len_with(self.version(), self.flags())
     }
 
-    pub fn key_sz(&self) -> usize {
-        self.header.key_sz()
+    pub fn version(&self) -> FormatVersion {
+        if layout::is_wide(&self.0) {
+            FormatVersion::Wide
+        } else {
+            FormatVersion::Compact
+        }
     }
 
-    pub fn value_sz(&self) -> usize {
-        self.header.value_sz()
+    fn flag_bits(&self) -> u8 {
+        layout::flags(&self.0)
     }
-}
 
-impl Display for HintEntry {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "HintEntry(key='{}', offset={}, size={})",
-            String::from_utf8_lossy(self.key.as_ref()),
-            self.offset(),
-            self.size(),
-        )
+    pub fn is_tombstone(&self) -> bool {
+        self.flag_bits() & FLAG_TOMBSTONE != 0
     }
-}
 
-impl From<&DiskEntry> for HintEntry {
-    fn from(v: &DiskEntry) -> Self {
-        let header = HintHeader::new(
-            v.offset.unwrap(),
-            v.key.len() as u32,
-            v.value.len() as u32,
-            v.timestamp(),
-        );
-        Self {
-            header,
-            key: v.key.clone(),
-            file_id: v.file_id.clone(),
-        }
+    pub fn is_compressed(&self) -> bool {
+        self.flag_bits() & FLAG_COMPRESSED != 0
     }
-}
 
-impl EntryIO for HintEntry {
-    type Entry = Self;
+    pub fn is_encrypted(&self) -> bool {
+        self.flag_bits() & FLAG_ENCRYPTED != 0
+    }
 
-    fn read_from<R>(r: &mut R, offset: u64) -> Result<Option<Self::Entry>>
-    where
-        R: Read + Seek,
-    {
-        r.seek(SeekFrom::Start(offset))?;
+    pub fn flags(&self) -> EntryFlags {
+        EntryFlags(self.flag_bits())
+    }
 
-        let mut buf = [0u8; HINT_HEADER_SIZE];
-        if r.read(&mut buf)? == 0 {
-            return Ok(None);
+    pub fn checksum_kind(&self) -> ChecksumKind {
+        if self.flag_bits() & FLAG_CRC64 != 0 {
+            ChecksumKind::Crc64
+        } else {
+            ChecksumKind::Crc32
         }
+    }
 
-        let header = HintHeader::from(buf);
-
-        let mut key = vec![0u8; header.key_sz()];
+    /// Header with the attribute flags of `flag` set, its layout flags
+    /// are ignored: those are set along with their fields, see
+    /// `HeaderFields`. As with `from_fields`, the crc is not recomputed.
+    /// A baseline header, without the header crc flag, has no flags.
+    pub fn with_flag(mut self, flag: EntryFlags) -> Self {
+        if layout::is_baseline(&self.0) {
+            return self;
+        }
+        self.0[FLAGS_POS] |= flag.0 & EntryFlags::ATTRIBUTES.0;
+        self
+    }
+
+    /// Bytes following the shared 16 bytes prefix, to be filled by readers.
+    pub(super) fn tail_mut(&mut self) -> &mut [u8] {
+        let size = self.size();
+        &mut self.0[HEADER_SIZE..size]
+    }
+
+    /// Size of the entry of this header, header included.
+    pub(super) fn entry_len(&self) -> u64 {
+        (self.size() as u64)
+            .saturating_add(self.key_sz())
+            .saturating_add(self.value_sz())
+    }
+
+    /// The 16 bytes of this flagged header read in the baseline layout:
+    /// a baseline value of 128 MiB or more may have the header crc flag
+    /// bit set in its top byte, so either reading may be the right one,
+    /// see `layout::is_baseline`. `None` for a baseline header.
+    pub(super) fn baseline_alias(&self) -> Option<Header> {
+        if layout::is_baseline(&self.0) {
+            return None;
+        }
+        let mut alias = Self([0u8; HEADER_BUF_SIZE]);
+        alias.0[..HEADER_SIZE].copy_from_slice(&self.0[..HEADER_SIZE]);
+        layout::entry::set_baseline(&mut alias.0);
+        Some(alias)
+    }
+
+    /// Whether `key` and `value` pass the crc of this header.
+    pub(super) fn matches(&self, key: &[u8], value: &[u8]) -> bool {
+        entry_crc::<DefaultChecksum>(self, key, value) == self.crc()
+    }
+
+    /// Check the entry of this header fits in the `available` bytes from
+    /// `offset`. A corrupted header may declare huge sizes, this is done
+    /// before allocating anything.
+    pub(super) fn check_fits(&self, offset: u64, available: u64) -> Result<()> {
+        let needed = self.entry_len();
+        if needed > available {
+            return Err(LSMLibError::TruncatedEntry {
+                field: Field::short_of(available, self.size(), self.key_sz()),
+                offset,
+                needed,
+                available,
+            });
+        }
+        Ok(())
+    }
+
+    /// Whether the header could have been written, a cheap check run
+    /// before reading the entry, ahead of its crc check. The entry has to
+    /// fit the `remaining` bytes, and the flags have to agree with each
+    /// other and with the sizes: a hint only flag, a tombstone with a
+    /// value or an empty compressed value signal corruption.
+    ///
+    /// Entries of an empty key and value are valid, their crc is never
+    /// zero, and an all-zero header is padding, see `LSMLibError::Padding`.
+    pub fn is_plausible(&self, remaining: u64) -> bool {
+        let needed = self.entry_len();
+        let flags = self.flag_bits();
+
+        needed <= remaining
+            && flags & FLAG_HINT_CRC == 0
+            && !(self.is_tombstone() && self.value_sz() != 0)
+            && !(self.is_compressed() && self.value_sz() == 0)
+            && !(self.is_batch_marker() && self.key_sz() != 0)
+    }
+}
+
+/// Checks of the header of the entry at `offset`, the stream holding
+/// `available` bytes from there, without doing any IO: readers read the
+/// header however they read and hand its bytes over, so every reader,
+/// from a file, a buffer or async, rejects the same headers.
+#[derive(Debug, Clone, Copy)]
+pub(super) struct HeaderDecoder {
+    offset: u64,
+    available: u64,
+}
+
+impl HeaderDecoder {
+    pub(super) fn new(offset: u64, available: u64) -> Self {
+        Self { offset, available }
+    }
+
+    pub(super) fn truncated(&self, field: Field, needed: usize) -> LSMLibError {
+        LSMLibError::TruncatedEntry {
+            field,
+            offset: self.offset,
+            needed: needed as u64,
+            available: self.available,
+        }
+    }
+
+    /// Check the stream holds a header prefix of `len` bytes, to be read
+    /// next.
+    pub(super) fn check_prefix(&self, len: usize) -> Result<()> {
+        if self.available < len as u64 {
+            return Err(self.truncated(Field::Header, len));
+        }
+        Ok(())
+    }
+
+    /// Header of `prefix`, its tail to be read next. `None` for an all
+    /// zero prefix, padding whose length only the reader can measure,
+    /// see `padding`.
+    pub(super) fn prefix(&self, prefix: [u8; HEADER_SIZE]) -> Result<Option<Header>> {
+        if prefix.iter().all(|b| *b == 0) {
+            return Ok(None);
+        }
+        let header = Header::try_from(prefix)?;
+        if self.available < header.size() as u64 {
+            return Err(self.truncated(Field::Header, header.size()));
+        }
+        Ok(Some(header))
+    }
+
+    /// Check the whole `header` before its key and value are read.
+    pub(super) fn finish(&self, header: &Header) -> Result<()> {
+        header.check_fits(self.offset, self.available)?;
+        if !header.is_plausible(self.available) {
+            return Err(LSMLibError::ImplausibleHeader {
+                offset: self.offset,
+            });
+        }
+        Ok(())
+    }
+
+    /// Baseline reading of the flagged `header` if its entry fits the
+    /// stream too, see `Header::baseline_alias`. Readers take it over
+    /// `header` only if `header` is implausible or fails its crc while
+    /// the alias is followed by an entry, see `alias_followed`, and
+    /// passes its crc.
+    pub(super) fn alias(&self, header: &Header) -> Option<Header> {
+        header
+            .baseline_alias()
+            .filter(|alias| alias.check_fits(self.offset, self.available).is_ok())
+    }
+
+    /// Whether `next`, up to `HEADER_SIZE` bytes following the entry of
+    /// `alias`, start an entry or padding, or are a torn tail: only then
+    /// is the alias worth a crc check.
+    pub(super) fn alias_followed(&self, alias: &Header, next: &[u8]) -> bool {
+        let Ok(prefix) = <[u8; HEADER_SIZE]>::try_from(next) else {
+            return true;
+        };
+        let remaining = self.available - alias.entry_len();
+        prefix.iter().all(|b| *b == 0)
+            || Header::try_from(prefix).is_ok_and(|next| next.is_plausible(remaining))
+    }
+
+    /// Offset of the entry.
+    pub(super) fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    /// Same as `prefix`, for the header of a hint.
+    pub(super) fn hint_prefix(&self, prefix: [u8; HINT_HEADER_SIZE]) -> Result<HintHeader> {
+        let header = HintHeader::try_from(prefix)?;
+        if self.available < header.size() as u64 {
+            return Err(self.truncated(Field::Header, header.size()));
+        }
+        Ok(header)
+    }
+
+    /// Same as `finish`, for the header of a hint.
+    pub(super) fn finish_hint(&self, header: &HintHeader) -> Result<()> {
+        header.check_fits(self.offset, self.available)
+    }
+}
+
+/// Decoded fields of a `Header`, freely constructable.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HeaderFields {
+    /// Only the lower half is stored without the crc64 flag.
+    pub crc: u64,
+    pub timestamp: u32,
+    pub key_sz: u64,
+    pub value_sz: u64,
+    pub flags: u8,
+    /// Setting it also sets the seq flag.
+    pub seq: Option<u64>,
+    /// Setting it also sets the expire flag.
+    pub expire_at: Option<u32>,
+}
+
+impl HeaderFields {
+    pub fn from_header(header: &Header) -> Self {
+        Self {
+            crc: header.crc(),
+            timestamp: header.timestamp(),
+            key_sz: header.key_sz(),
+            value_sz: header.value_sz(),
+            flags: header.flag_bits(),
+            seq: header.seq(),
+            expire_at: header.expire_at(),
+        }
+    }
+
+    pub fn to_header(self) -> Header {
+        Header::from_fields(&self)
+    }
+}
+
+impl AsRef<[u8]> for Header {
+    fn as_ref(&self) -> &[u8] {
+        &self.0[..self.size()]
+    }
+}
+
+impl TryFrom<[u8; HEADER_SIZE]> for Header {
+    type Error = LSMLibError;
+
+    fn try_from(prefix: [u8; HEADER_SIZE]) -> Result<Self> {
+        if !layout::is_baseline(&prefix) && !layout::entry::is_marker(&prefix) {
+            FormatVersion::try_from(prefix[VERSION_POS])?;
+        }
+
+        let mut buf = [0u8; HEADER_BUF_SIZE];
+        buf[..HEADER_SIZE].copy_from_slice(&prefix);
+        Ok(Self(buf))
+    }
+}
+
+/// Disk Entry
+#[derive(Debug, Clone)]
+pub struct DiskEntry {
+    /// header of the disk entry.
+    header: Header,
+
+    /// key of the disk entry.
+    pub key: EntryBuf,
+
+    /// value of the disk entry, decompressed if it is stored compressed.
+    pub value: EntryBuf,
+
+    /// value bytes as stored on disk, when they differ from `value`.
+    stored: Option<EntryBuf>,
+
+    /// offset of the disk entry in the disk file.
+    pub offset: Option<u64>,
+
+    /// file id of the disk entry may stored.
+    pub file_id: Option<u64>,
+}
+
+impl DiskEntry {
+    /// Create an entry of `key` and `value`. An empty key is stored and
+    /// read back as is, `DiskEntryBuilder::build` rejects it.
+    pub fn new(key: impl Into<EntryBuf>, value: impl Into<EntryBuf>) -> Self {
+        Self::with_checksum::<DefaultChecksum>(key, value)
+    }
+
+    /// Start building an entry with explicit header fields.
+    pub fn builder(key: impl Into<EntryBuf>, value: impl Into<EntryBuf>) -> DiskEntryBuilder {
+        DiskEntryBuilder::new(key, value)
+    }
+
+    /// Create an entry whose crc is computed with checksum `C`, readers
+    /// have to validate it with the same checksum.
+    pub fn with_checksum<C: Checksum>(
+        key: impl Into<EntryBuf>,
+        value: impl Into<EntryBuf>,
+    ) -> Self {
+        DiskEntryBuilder::new(key, value).assemble::<C>(None)
+    }
+
+    /// Create a batch marker of `value`, see `batch`.
+    pub(super) fn batch_marker(value: Vec<u8>) -> Self {
+        let mut builder = DiskEntryBuilder::new(EntryBuf::new(), value);
+        builder.marker = true;
+        builder.assemble::<DefaultChecksum>(None)
+    }
+
+    /// Create a deletion marker for `key`.
+    pub fn tombstone(key: impl Into<EntryBuf>) -> Self {
+        DiskEntryBuilder::new(key, EntryBuf::new())
+            .tombstone()
+            .assemble::<DefaultChecksum>(None)
+    }
+
+    /// Create an entry within the size limits of `opts`, its value
+    /// compressed if `opts` asks for it.
+    pub fn new_with_options(
+        key: impl Into<EntryBuf>,
+        value: impl Into<EntryBuf>,
+        opts: &WriteOptions,
+    ) -> Result<Self> {
+        DiskEntryBuilder::new(key, value).options(opts).build()
+    }
+
+    /// Create an entry whose value is stored compressed with `codec`, crc
+    /// and `value_sz` cover the compressed bytes.
+    pub fn new_compressed(
+        key: impl Into<EntryBuf>,
+        value: impl Into<EntryBuf>,
+        codec: Codec,
+    ) -> Result<Self> {
+        DiskEntryBuilder::new(key, value).compressed(codec).build()
+    }
+
+    /// Create an entry whose value is sealed with `cipher`, the key stays
+    /// in the clear and authenticates with it. Crc and `value_sz` cover
+    /// the sealed bytes, see `decrypt` to read it back.
+    #[cfg(feature = "encryption")]
+    pub fn new_encrypted(
+        key: impl Into<EntryBuf>,
+        value: impl Into<EntryBuf>,
+        cipher: &Cipher,
+    ) -> Result<Self> {
+        DiskEntryBuilder::new(key, value).encrypted(cipher).build()
+    }
+
+    /// Create an entry carrying sequence number `seq`, which orders it
+    /// against other writes of the same key.
+    pub fn with_seq(key: impl Into<EntryBuf>, value: impl Into<EntryBuf>, seq: u64) -> Self {
+        DiskEntryBuilder::new(key, value)
+            .seq(seq)
+            .assemble::<DefaultChecksum>(None)
+    }
+
+    /// Create a deletion marker for `key` carrying sequence number `seq`.
+    pub fn tombstone_with_seq(key: impl Into<EntryBuf>, seq: u64) -> Self {
+        DiskEntryBuilder::new(key, EntryBuf::new())
+            .tombstone()
+            .seq(seq)
+            .assemble::<DefaultChecksum>(None)
+    }
+
+    /// Create an entry timestamped by `clock` instead of the system clock.
+    pub fn new_with_clock(
+        key: impl Into<EntryBuf>,
+        value: impl Into<EntryBuf>,
+        clock: &impl Clock,
+    ) -> Self {
+        DiskEntryBuilder::new(key, value)
+            .clock(clock)
+            .assemble::<DefaultChecksum>(None)
+    }
+
+    /// Create an entry expiring `ttl` after now, see `is_expired`.
+    pub fn with_ttl(key: impl Into<EntryBuf>, value: impl Into<EntryBuf>, ttl: Duration) -> Self {
+        DiskEntryBuilder::new(key, value)
+            .ttl(ttl)
+            .assemble::<DefaultChecksum>(None)
+    }
+
+    /// Entry of a separated data file, holding `ptr` to the value of
+    /// `key` in a value log instead of the value, see `vlog`.
+    pub fn with_value_pointer(key: impl Into<EntryBuf>, ptr: &ValuePointer) -> Self {
+        Self::new(key, ptr.encode().to_vec())
+    }
+
+    /// Deletion marker for the key of this entry, with the same
+    /// timestamp and seq so it orders the same against other writes.
+    pub fn to_tombstone(&self) -> Self {
+        let mut builder = DiskEntryBuilder::new(self.key.clone(), EntryBuf::new())
+            .tombstone()
+            .timestamp(self.timestamp());
+        if let Some(seq) = self.seq() {
+            builder = builder.seq(seq);
+        }
+        builder.assemble::<DefaultChecksum>(None)
+    }
+
+    /// Entry located by `hint`, such as one found merging with hint files
+    /// as the index, with `value` read separately, see
+    /// `HintEntry::read_value`.
+    ///
+    /// Timestamp, seq, expiry time and tombstone are taken from the hint,
+    /// the crc is recomputed with the checksum the hint records. The
+    /// value is stored uncompressed, so the size can differ from the one
+    /// of the hint.
+    pub fn from_hint(hint: &HintEntry, value: Vec<u8>) -> Self {
+        let mut builder =
+            DiskEntryBuilder::new(hint.key.clone(), value).timestamp(hint.timestamp());
+        if hint.is_tombstone() {
+            builder = builder.tombstone();
+        }
+        if let Some(seq) = hint.seq() {
+            builder = builder.seq(seq);
+        }
+        if let Some(expire_at) = hint.expire_at() {
+            builder = builder.expire_at(expire_at);
+        }
+
+        let mut entry = if hint.header.flag_bits() & FLAG_CRC64 != 0 {
+            builder.assemble::<Crc64>(None)
+        } else {
+            builder.assemble::<Crc32>(None)
+        };
+        entry.offset = Some(hint.offset());
+        entry.file_id = hint.file_id;
+        entry
+    }
+
+    /// Build an entry from what was read off disk, decompressing the
+    /// value if needed. A value failing to decompress is kept as stored,
+    /// so the crc check reports the corruption. Encrypted values are kept
+    /// sealed, see `decrypt`.
+    pub(super) fn decode(header: Header, key: Vec<u8>, value: Vec<u8>) -> Self {
+        let mut entry = Self {
+            header,
+            key: from_vec(key),
+            value: from_vec(value),
+            stored: None,
+            offset: None,
+            file_id: None,
+        };
+
+        if entry.header.is_compressed() && !entry.header.is_encrypted() {
+            if let Ok(value) = decompress(&entry.value) {
+                entry.stored = Some(std::mem::replace(&mut entry.value, from_vec(value)));
+            }
+        }
+
+        entry
+    }
+
+    pub(super) fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Value bytes as written to disk.
+    pub(super) fn stored_value(&self) -> &[u8] {
+        self.stored.as_deref().unwrap_or(&self.value[..])
+    }
+
+    /// Append the encoded entry to `buf`.
+    pub(super) fn encode_into(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(self.header.as_ref());
+        buf.extend_from_slice(&self.key);
+        buf.extend_from_slice(self.stored_value());
+    }
+
+    pub fn crc(&self) -> u64 {
+        self.header.crc()
+    }
+
+    pub fn flags(&self) -> EntryFlags {
+        self.header.flags()
+    }
+
+    /// Write time in seconds since the unix epoch.
+    pub fn timestamp(&self) -> u32 {
+        self.header.timestamp()
+    }
+
+    /// Write time, see `timestamp`.
+    pub fn datetime(&self) -> DateTime<Utc> {
+        to_datetime(self.timestamp())
+    }
+
+    pub fn is_tombstone(&self) -> bool {
+        self.header.is_tombstone()
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.header.is_compressed()
+    }
+
+    /// Whether this is a batch marker, see `batch::is_marker`.
+    pub(super) fn is_batch_marker(&self) -> bool {
+        self.header.is_batch_marker()
+    }
+
+    /// Whether the value is sealed on disk. Entries read back hold the
+    /// sealed bytes as value until `decrypt` is called.
+    pub fn is_encrypted(&self) -> bool {
+        self.header.is_encrypted()
+    }
+
+    /// Open the value sealed by `cipher`, decompressing it if needed.
+    /// Entries not encrypted, or already decrypted, are returned as is.
+    /// A wrong cipher or a tampered value is a `DecryptionFailed` error.
+    #[cfg(feature = "encryption")]
+    pub fn decrypt(mut self, cipher: &Cipher) -> Result<Self> {
+        if !self.is_encrypted() || self.stored.is_some() {
+            return Ok(self);
+        }
+        let mut value =
+            cipher
+                .open(&self.key, &self.value)
+                .ok_or_else(|| LSMLibError::DecryptionFailed {
+                    key: self.key.to_vec(),
+                })?;
+        if self.is_compressed() {
+            value = decompress(&value)?;
+        }
+        self.stored = Some(std::mem::replace(&mut self.value, from_vec(value)));
+        Ok(self)
+    }
+
+    pub fn seq(&self) -> Option<u64> {
+        self.header.seq()
+    }
+
+    /// Expiry time, seconds since the unix epoch.
+    pub fn expire_at(&self) -> Option<u32> {
+        self.header.expire_at()
+    }
+
+    /// Whether the entry expired at `now`, seconds since the unix epoch.
+    /// Entries without expiry time never do.
+    pub fn is_expired(&self, now: u32) -> bool {
+        self.expire_at().is_some_and(|expire_at| expire_at <= now)
+    }
+
+    /// Ordering of writes to the same key, the greater one is newer.
+    ///
+    /// Sequence numbers decide when present, entries without one are
+    /// older than any entry with one, and fall back to their timestamp.
+    pub fn recency(&self) -> Recency {
+        (self.seq(), self.timestamp())
+    }
+
+    pub fn size(&self) -> u64 {
+        (self.header.size() + self.key.len() + self.stored_value().len()) as u64
+    }
+
+    /// Pointer held by an entry of a separated data file.
+    pub fn value_pointer(&self) -> Result<ValuePointer> {
+        ValuePointer::decode(&self.value)
+    }
+
+    /// Value of an entry of a separated data file, read from `vlog` only
+    /// now: entries are read with their pointer, scans that skip values
+    /// never touch the value log. Tombstones have no value.
+    pub fn resolve_value<L: ValueLogReader>(&self, vlog: &mut L) -> Result<Vec<u8>> {
+        if self.is_tombstone() {
+            return Ok(Vec::new());
+        }
+        vlog.read_value(&self.key, &self.value_pointer()?)
+    }
+
+    /// Move key and value out of the entry, the value decompressed.
+    pub fn into_kv(self) -> (Vec<u8>, Vec<u8>) {
+        (into_vec(self.key), into_vec(self.value))
+    }
+
+    /// Bytes of the entry as written to disk, header, key then value.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(self.size() as usize);
+        self.encode_into(&mut buf);
+        buf
+    }
+
+    /// `to_bytes` in the classic offset, hex and ascii layout, 16 bytes
+    /// per line.
+    pub fn hexdump(&self) -> String {
+        hexdump(&self.to_bytes())
+    }
+
+    pub fn entry_size(k: &[u8], v: &[u8]) -> u64 {
+        let (key_sz, value_sz) = (k.len() as u64, v.len() as u64);
+        let header_sz = Header::encoded_len_with(
+            FormatVersion::for_sizes(key_sz, value_sz),
+            EntryFlags::from_bits(checksum_flags::<DefaultChecksum>()),
+        );
+        header_sz as u64 + key_sz + value_sz
+    }
+
+    /// Total `entry_size` of `pairs`, the size of a data file holding
+    /// them as written by `DiskEntry::new`.
+    pub fn batch_size(pairs: &[(Vec<u8>, Vec<u8>)]) -> u64 {
+        pairs.iter().map(|(k, v)| Self::entry_size(k, v)).sum()
+    }
+
+    /// Same as `batch_size`, also returns the size of the hint file
+    /// indexing them, see `HintEntry::hint_size_of`.
+    pub fn batch_size_with_hints(pairs: &[(Vec<u8>, Vec<u8>)]) -> (u64, u64) {
+        pairs.iter().fold((0, 0), |(data, hint), (k, v)| {
+            (
+                data + Self::entry_size(k, v),
+                hint + HintEntry::hint_size_of(k, v),
+            )
+        })
+    }
+
+    /// Exact number of bytes written for an entry of `k` and `v` built
+    /// and written with `opts`. A compressed value has to be compressed
+    /// to know its size. With alignment, the entry is assumed to start
+    /// on a multiple of it, as every entry of an aligned file does.
+    pub fn estimate_on_disk_size(k: &[u8], v: &[u8], opts: &WriteOptions) -> Result<u64> {
+        let value_sz = match opts.codec {
+            Some(codec) => codec.compress(v)?.len() as u64,
+            None => v.len() as u64,
+        };
+        let key_sz = k.len() as u64;
+
+        let mut flags = checksum_flags::<DefaultChecksum>();
+        if opts.seq {
+            flags |= FLAG_SEQ;
+        }
+        if opts.expire {
+            flags |= FLAG_EXPIRE;
+        }
+        let header_sz = Header::encoded_len_with(
+            FormatVersion::for_sizes(key_sz, value_sz),
+            EntryFlags::from_bits(flags),
+        ) as u64;
+
+        Ok(align_up(header_sz + key_sz + value_sz, opts.align))
+    }
+
+    pub fn offset(mut self, offset: u64) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    pub fn file_id(mut self, file_id: u64) -> Self {
+        self.file_id = Some(file_id);
+        self
+    }
+
+    pub fn is_validate(&self) -> bool {
+        self.is_validate_with::<DefaultChecksum>()
+    }
+
+    pub fn is_validate_with<C: Checksum>(&self) -> bool {
+        self.header.crc() == self.crc_with::<C>()
+    }
+
+    /// Check the crc, a mismatch is reported as a `CrcMismatch` error
+    /// telling which entry is corrupted.
+    pub fn verify(&self) -> Result<()> {
+        self.verify_with::<DefaultChecksum>()
+    }
+
+    pub fn verify_with<C: Checksum>(&self) -> Result<()> {
+        self.verify_at::<C>(self.offset)
+    }
+
+    fn verify_at<C: Checksum>(&self, offset: Option<u64>) -> Result<()> {
+        let actual = self.crc_with::<C>();
+        if actual == self.header.crc() {
+            return Ok(());
+        }
+
+        Err(LSMLibError::CrcMismatch {
+            key: self.key.to_vec(),
+            file_id: self.file_id,
+            offset,
+            expected: self.header.crc(),
+            actual,
+        })
+    }
+
+    pub fn crc_expected(&self) -> u64 {
+        self.header.crc()
+    }
+
+    pub fn crc_actual(&self) -> u64 {
+        self.crc_with::<DefaultChecksum>()
+    }
+
+    fn crc_with<C: Checksum>(&self) -> u64 {
+        entry_crc::<C>(&self.header, &self.key, self.stored_value())
+    }
+
+    /// Read the entry at the current position of `r`, leaving `r` right
+    /// after it, with `offset` set to where it starts.
+    ///
+    /// Unlike `read_from` it doesn't seek, so the stream length is not
+    /// known up front: key and value are read as they come instead of
+    /// being allocated from the header sizes.
+    pub fn read_next<R>(r: &mut R) -> Result<Option<Self>>
+    where
+        R: Read + Seek,
+    {
+        let offset = r.stream_position()?;
+
+        let mut buf = [0u8; HEADER_SIZE];
+        match read_full(r, &mut buf)? {
+            0 => return Ok(None),
+            HEADER_SIZE => {}
+            n => {
+                return Err(LSMLibError::TruncatedEntry {
+                    field: Field::Header,
+                    offset,
+                    needed: HEADER_SIZE as u64,
+                    available: n as u64,
+                })
+            }
+        }
+
+        let mut header = Header::try_from(buf)?;
+        let body_sz = header.key_sz().saturating_add(header.value_sz());
+        let needed = (header.size() as u64).saturating_add(body_sz);
+        if let Err(e) = r.read_exact(header.tail_mut()) {
+            return Err(short_read(
+                e,
+                Field::Header,
+                offset,
+                needed,
+                HEADER_SIZE as u64,
+            ));
+        }
+
+        let mut body = Vec::new();
+        let read = r.by_ref().take(body_sz).read_to_end(&mut body)? as u64;
+        if read < body_sz {
+            let available = header.size() as u64 + read;
+            return Err(LSMLibError::TruncatedEntry {
+                field: Field::short_of(available, header.size(), header.key_sz()),
+                offset,
+                needed,
+                available,
+            });
+        }
+        let value = body.split_off(header.key_sz() as usize);
+
+        // the baseline reading is only told apart with the stream length.
+        if header.baseline_alias().is_some() && !header.matches(&body, &value) {
+            return Self::read_from(r, offset);
+        }
+        Ok(Some(Self::decode(header, body, value).offset(offset)))
+    }
+
+    /// Read only `range` of the value of the entry at `offset`.
+    ///
+    /// A compressed value has to be read and decompressed whole, the
+    /// range then applies to the decompressed value. The range of an
+    /// encrypted value applies to its sealed bytes.
+    pub fn read_value_range<R>(r: &mut R, offset: u64, range: Range<usize>) -> Result<Vec<u8>>
+    where
+        R: Read + Seek,
+    {
+        let header = Self::read_header(r, offset)?;
+
+        let value_pos = offset + header.size() as u64 + header.key_sz();
+        let out_of_bounds = |len: u64| LSMLibError::RangeOutOfBounds {
+            start: range.start,
+            end: range.end,
+            len,
+        };
+
+        if header.is_compressed() && !header.is_encrypted() {
+            let mut stored = vec![0u8; header.value_sz() as usize];
+            r.seek(SeekFrom::Start(value_pos))?;
+            r.read_exact(&mut stored)?;
+
+            let value = decompress(&stored)?;
+            return value
+                .get(range.clone())
+                .map(<[u8]>::to_vec)
+                .ok_or_else(|| out_of_bounds(value.len() as u64));
+        }
+
+        if range.start > range.end || range.end as u64 > header.value_sz() {
+            return Err(out_of_bounds(header.value_sz()));
+        }
+
+        let mut value = vec![0u8; range.len()];
+        r.seek(SeekFrom::Start(value_pos + range.start as u64))?;
+        r.read_exact(&mut value)?;
+
+        Ok(value)
+    }
+
+    /// Read only the key of the entry at `offset`, the value is skipped.
+    /// The crc can't be checked without the value.
+    pub fn read_key_only<R>(r: &mut R, offset: u64) -> Result<Vec<u8>>
+    where
+        R: Read + Seek,
+    {
+        let header = Self::read_header(r, offset)?;
+
+        let mut key = vec![0u8; header.key_sz() as usize];
+        r.read_exact(&mut key)?;
+
+        Ok(key)
+    }
+
+    /// Whether the entry at `offset` has key `target`, reading the key in
+    /// small pieces without allocating and never the value. The crc
+    /// can't be checked without the value.
+    pub fn key_matches<R>(r: &mut R, offset: u64, target: &[u8]) -> Result<bool>
+    where
+        R: Read + Seek,
+    {
+        let header = Self::read_header(r, offset)?;
+        if header.key_sz() != target.len() as u64 {
+            return Ok(false);
+        }
+
+        let mut buf = [0u8; 64];
+        for chunk in target.chunks(buf.len()) {
+            let buf = &mut buf[..chunk.len()];
+            r.read_exact(buf)?;
+            if buf != chunk {
+                return Ok(false);
+            }
+        }
+
+        Ok(true)
+    }
+
+    /// Read the header of the entry at `offset`, leaving `r` at its key.
+    fn read_header<R>(r: &mut R, offset: u64) -> Result<Header>
+    where
+        R: Read + Seek,
+    {
+        let available = stream_len(r)?.saturating_sub(offset);
+        r.seek(SeekFrom::Start(offset))?;
+        let short =
+            |needed: usize| move |e| short_read(e, Field::Header, offset, needed as u64, available);
+
+        let decoder = HeaderDecoder::new(offset, available);
+        let mut buf = [0u8; HEADER_SIZE];
+        r.read_exact(&mut buf).map_err(short(HEADER_SIZE))?;
+        let Some(mut header) = decoder.prefix(buf)? else {
+            return Err(padding(r, offset)?);
+        };
+        let header_sz = header.size();
+        r.read_exact(header.tail_mut()).map_err(short(header_sz))?;
+        if let Some(alias) = decoder.alias(&header) {
+            header = resolve_alias(r, &decoder, header, alias)?;
+        }
+        decoder.finish(&header)?;
+
+        Ok(header)
+    }
+
+    /// Write all `entries` back to back at the current position of `w`
+    /// with a single write, returns the offset of each entry.
+    pub fn write_batch<W>(entries: &[DiskEntry], w: &mut W) -> Result<Vec<u64>>
+    where
+        W: Write + Seek,
+    {
+        let start = w.stream_position()?;
+
+        let len = entries.iter().map(|e| e.size() as usize).sum();
+        let mut buf = Vec::with_capacity(len);
+        let mut offsets = Vec::with_capacity(entries.len());
+        for entry in entries {
+            offsets.push(start + buf.len() as u64);
+            entry.encode_into(&mut buf);
+        }
+        w.write_all(&buf)?;
+
+        Ok(offsets)
+    }
+
+    /// Copy the entry at `offset` of `r` to the current position of `w`
+    /// byte for byte, crc and timestamp untouched, returns the offset it
+    /// is copied to. Nothing is written for an entry failing the crc
+    /// check, a `CrcMismatch` error, or for EOF, a `TruncatedEntry` error.
+    pub fn copy_entry<R, W>(r: &mut R, offset: u64, w: &mut W) -> Result<u64>
+    where
+        R: Read + Seek,
+        W: Write + Seek,
+    {
+        let header = Self::read_header(r, offset)?;
+        let key_sz = header.key_sz() as usize;
+        let mut body = vec![0u8; key_sz + header.value_sz() as usize];
+        r.read_exact(&mut body)?;
+
+        let (key, value) = body.split_at(key_sz);
+        let actual = entry_crc::<DefaultChecksum>(&header, key, value);
+        if actual != header.crc() {
+            return Err(LSMLibError::CrcMismatch {
+                key: key.to_vec(),
+                file_id: None,
+                offset: Some(offset),
+                expected: header.crc(),
+                actual,
+            });
+        }
+
+        let dest = w.stream_position()?;
+        w.write_all(header.as_ref())?;
+        w.write_all(&body)?;
+        Ok(dest)
+    }
+
+    /// Write the entry at the current position of `w`, returns the
+    /// offset it starts at along with its hint, pointing there.
+    pub fn write_and_hint<W>(self, w: &mut W) -> Result<(u64, HintEntry)>
+    where
+        W: Write + Seek,
+    {
+        let (offset, _) = self.write_to(w)?;
+        let hint = HintEntry::placed_at(&self, offset);
+        Ok((offset, hint))
+    }
+
+    /// Same as `write_to`, but returns only once the entry reached the
+    /// storage device. For batched durability, write with `write_to` or
+    /// `write_batch` and call `Syncable::sync_data` once at the end.
+    ///
+    /// An entry torn by a crash during the write is skipped by
+    /// `recover_entries`.
+    pub fn write_to_durable<W>(&self, w: &mut W) -> Result<(u64, u64)>
+    where
+        W: Write + Seek + Syncable,
+    {
+        let written = self.write_to(w)?;
+        w.sync_data()?;
+
+        Ok(written)
+    }
+
+    /// Write the entry at the current position of `w`, then pad with
+    /// zeros so the next entry starts on a multiple of `align`. Returns
+    /// the offset of the entry and the number of bytes written, padding
+    /// included.
+    pub fn write_to_aligned<W>(&self, w: &mut W, align: u64) -> Result<(u64, u64)>
+    where
+        W: Write + Seek,
+    {
+        let offset = w.stream_position()?;
+
+        let end = align_up(offset + self.size(), align);
+        let mut buf = Vec::with_capacity((end - offset) as usize);
+        self.encode_into(&mut buf);
+        buf.resize((end - offset) as usize, 0);
+        w.write_all(&buf)?;
+
+        Ok((offset, buf.len() as u64))
+    }
+
+    /// Read the first entry at or after `offset` in a file written with
+    /// `write_to_aligned`, with its offset set. Entries are looked for on
+    /// multiples of `align` only, all-zero regions are padding and
+    /// skipped: a valid header never is all zero, as its crc is not. With
+    /// an `align` of 0 or 1 this is `read_from_checked`.
+    ///
+    /// An entry failing the crc check is returned as a `CrcMismatch`
+    /// error.
+    pub fn read_aligned<R>(r: &mut R, offset: u64, align: u64) -> Result<Option<Self>>
+    where
+        R: Read + Seek,
+    {
+        let mut offset = align_up(offset, align);
+        if align > 1 {
+            loop {
+                r.seek(SeekFrom::Start(offset))?;
+                let mut buf = Vec::with_capacity(HEADER_SIZE);
+                r.by_ref().take(HEADER_SIZE as u64).read_to_end(&mut buf)?;
+                if buf.is_empty() {
+                    return Ok(None);
+                }
+                if buf.iter().any(|b| *b != 0) {
+                    break;
+                }
+                offset = align_up(offset + 1, align);
+            }
+        }
+
+        Self::read_from_checked(r, offset)
+    }
+
+    /// Write the entry as a frame to a stream that can't seek, such as a
+    /// socket, returns the number of bytes written.
+    ///
+    /// The frame prefix holds `FRAME_MAGIC`, the entry length as a
+    /// little endian u64 and the crc of that length.
+    pub fn write_frame<W: Write>(&self, w: &mut W) -> Result<u64> {
+        let mut buf = Vec::with_capacity(FRAME_PREFIX_SIZE + self.size() as usize);
+        buf.extend_from_slice(&FRAME_MAGIC);
+        buf.extend_from_slice(&self.size().to_le_bytes());
+        buf.extend_from_slice(&hash_batch_len(self.size() as usize).to_le_bytes());
+        self.encode_into(&mut buf);
+        w.write_all(&buf)?;
+
+        Ok(buf.len() as u64)
+    }
+
+    /// Read the next frame written by `write_frame`, `None` at the end of
+    /// the stream.
+    ///
+    /// Bytes not starting a frame with a valid length crc, such as the
+    /// rest of a partially read frame, are skipped until the next frame.
+    /// An entry failing the crc check is returned as a `CrcMismatch`
+    /// error.
+    pub fn read_frame<R: Read>(r: &mut R) -> Result<Option<Self>> {
+        let mut prefix = [0u8; FRAME_PREFIX_SIZE];
+        let read = r
+            .by_ref()
+            .take(FRAME_PREFIX_SIZE as u64)
+            .read(&mut prefix)?;
+        if read == 0 {
+            return Ok(None);
+        }
+        r.read_exact(&mut prefix[read..])?;
+
+        let mut skipped = 0u64;
+        loop {
+            let len = u64::from_le_bytes(prefix[4..12].try_into().unwrap());
+            let crc = u32::from_le_bytes(prefix[12..16].try_into().unwrap());
+            if prefix[..4] == FRAME_MAGIC && crc == hash_batch_len(len as usize) {
+                break;
+            }
+
+            // shift in one byte at a time until a frame prefix shows up.
+            prefix.copy_within(1.., 0);
+            if r.read(&mut prefix[FRAME_PREFIX_SIZE - 1..])? == 0 {
+                log::warn!("skipped {} bytes before end of frame stream", skipped + 1);
+                return Ok(None);
+            }
+            skipped += 1;
+        }
+        if skipped > 0 {
+            log::warn!("skipped {} bytes to resync frame stream", skipped);
+        }
+
+        let len = u64::from_le_bytes(prefix[4..12].try_into().unwrap());
+        let mut buf = Vec::new();
+        r.by_ref().take(len).read_to_end(&mut buf)?;
+        if (buf.len() as u64) < len {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+
+        let entry = match Self::read_from_buf(&buf, 0)? {
+            Some(entry) if entry.size() == len => entry.to_entry(),
+            entry => {
+                return Err(LSMLibError::FrameSizeMismatch {
+                    frame: len,
+                    entry: entry.map_or(0, |e| e.size()),
+                })
+            }
+        };
+        let entry = Self {
+            offset: None,
+            ..entry
+        };
+        entry.verify()?;
+
+        Ok(Some(entry))
+    }
+
+    /// Write an entry of `key` and the `value_sz` bytes read from `value`
+    /// at the current position of `w`, streaming the value instead of
+    /// holding it in memory. Returns the offset the entry starts at and
+    /// the number of bytes written.
+    ///
+    /// The crc is computed while streaming and patched into the header
+    /// once the value is written, a value shorter than `value_sz` leaves
+    /// a torn entry and an `UnexpectedEof` error.
+    pub fn write_streamed<R, W>(
+        key: &[u8],
+        value: &mut R,
+        value_sz: u64,
+        w: &mut W,
+    ) -> Result<(u64, u64)>
+    where
+        R: Read,
+        W: Write + Seek,
+    {
+        let offset = w.stream_position()?;
+
+        let mut header = Header::new(
+            0,
+            now_timestamp(),
+            key.len() as u64,
+            value_sz,
+            checksum_flags::<DefaultChecksum>(),
+        );
+        w.write_all(header.as_ref())?;
+        w.write_all(key)?;
+
+        let mut hasher = EntryHasher::<DefaultChecksum>::new();
+        hasher.update(key);
+        let mut buf = vec![0u8; 64 * 1024];
+        let mut remaining = value_sz;
+        while remaining > 0 {
+            let len = remaining.min(buf.len() as u64) as usize;
+            let n = value.read(&mut buf[..len])?;
+            if n == 0 {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            }
+            hasher.update(&buf[..n]);
+            w.write_all(&buf[..n])?;
+            remaining -= n as u64;
+        }
+
+        header.set_crc(hasher.finalize_with(header.flag_bits(), header.covered()));
+        let end = w.stream_position()?;
+        w.seek(SeekFrom::Start(offset))?;
+        w.write_all(header.as_ref())?;
+        w.seek(SeekFrom::Start(end))?;
+
+        Ok((offset, end - offset))
+    }
+
+    /// Same as `read_from`, also returns the offset right after the entry,
+    /// where the reader stopped, to chain reads.
+    pub fn read_at<R>(r: &mut R, offset: u64) -> Result<Option<(Self, u64)>>
+    where
+        R: Read + Seek,
+    {
+        match Self::read_from(r, offset)? {
+            Some(entry) => Ok(Some((entry, r.stream_position()?))),
+            None => Ok(None),
+        }
+    }
+
+    /// Same as `read_from`, the entry returned with `file_id` set, as read
+    /// from that data file.
+    pub fn read_from_file<R>(r: &mut R, offset: u64, file_id: u64) -> Result<Option<Self>>
+    where
+        R: Read + Seek,
+    {
+        Ok(Self::read_from(r, offset)?.map(|entry| entry.file_id(file_id)))
+    }
+
+    /// Same as `read_from`, but an entry failing the crc check is
+    /// returned as a `CrcMismatch` error.
+    pub fn read_from_checked<R>(r: &mut R, offset: u64) -> Result<Option<Self>>
+    where
+        R: Read + Seek,
+    {
+        let entry = Self::read_from(r, offset)?;
+        if let Some(entry) = &entry {
+            entry.verify_at::<DefaultChecksum>(Some(offset))?;
+        }
+        Ok(entry)
+    }
+
+    /// Same as `read_from_checked`, the value then decrypted with
+    /// `cipher`, see `decrypt`.
+    #[cfg(feature = "encryption")]
+    pub fn read_from_encrypted<R>(r: &mut R, offset: u64, cipher: &Cipher) -> Result<Option<Self>>
+    where
+        R: Read + Seek,
+    {
+        Self::read_from_checked(r, offset)?
+            .map(|entry| entry.decrypt(cipher))
+            .transpose()
+    }
+
+    /// Read the entry at `offset` of `buf` without copying, key and
+    /// value borrow from `buf`.
+    pub fn read_from_buf(buf: &[u8], offset: usize) -> Result<Option<DiskEntryRef<'_>>> {
+        if offset >= buf.len() {
+            return Ok(None);
+        }
+
+        let rest = &buf[offset..];
+        let decoder = HeaderDecoder::new(offset as u64, rest.len() as u64);
+        decoder.check_prefix(HEADER_SIZE)?;
+
+        let prefix = <[u8; HEADER_SIZE]>::try_from(&rest[..HEADER_SIZE]).unwrap();
+        let Some(mut header) = decoder.prefix(prefix)? else {
+            let len = rest.iter().position(|b| *b != 0).unwrap_or(rest.len());
+            return Err(LSMLibError::Padding {
+                offset: offset as u64,
+                len: len as u64,
+            });
+        };
+        let header_sz = header.size();
+        header
+            .tail_mut()
+            .copy_from_slice(&rest[HEADER_SIZE..header_sz]);
+        if let Some(alias) = decoder.alias(&header) {
+            header = resolve_alias_in(rest, &decoder, header, alias);
+        }
+        decoder.finish(&header)?;
+
+        let header_sz = header.size();
+        let key_end = header_sz + header.key_sz() as usize;
+        let value_end = key_end + header.value_sz() as usize;
+
+        Ok(Some(DiskEntryRef {
+            header,
+            key: &rest[header_sz..key_end],
+            value: &rest[key_end..value_end],
+            offset: offset as u64,
+        }))
+    }
+
+    /// Read the small entry at `offset` of `r` into `buf`, key and value
+    /// borrowing from it, `None` at EOF. A single read and no allocation
+    /// nor length lookup, unlike `read_from`: the fast path for tiny
+    /// entries, where these dominate.
+    ///
+    /// An entry larger than `SMALL_ENTRY_SIZE` is a `NotSmallEntry`
+    /// error, to be read with `read_from`. The crc is not checked.
+    pub fn read_small<'a, R>(
+        r: &mut R,
+        offset: u64,
+        buf: &'a mut [u8; SMALL_ENTRY_SIZE],
+    ) -> Result<Option<DiskEntryRef<'a>>>
+    where
+        R: Read + Seek,
+    {
+        r.seek(SeekFrom::Start(offset))?;
+        let n = read_full(r, buf)?;
+
+        let filled: &'a [u8] = &buf[..n];
+        match Self::read_from_buf(filled, 0) {
+            Ok(entry) => Ok(entry.map(|entry| DiskEntryRef { offset, ..entry })),
+            Err(LSMLibError::TruncatedEntry { needed, .. }) if needed > SMALL_ENTRY_SIZE as u64 => {
+                Err(LSMLibError::NotSmallEntry {
+                    offset,
+                    limit: SMALL_ENTRY_SIZE as u64,
+                })
+            }
+            Err(LSMLibError::TruncatedEntry {
+                field,
+                needed,
+                available,
+                ..
+            }) => Err(LSMLibError::TruncatedEntry {
+                field,
+                offset,
+                needed,
+                available,
+            }),
+            // the run may go on past the buffer.
+            Err(LSMLibError::Padding { .. }) => Err(padding(r, offset)?),
+            Err(LSMLibError::ImplausibleHeader { .. }) => {
+                Err(LSMLibError::ImplausibleHeader { offset })
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Same as `write_to_at`, but an entry of at most `SMALL_ENTRY_SIZE`
+    /// bytes is laid out in a stack buffer and written with a single
+    /// write. Larger entries are written as by `write_to_at`.
+    pub fn write_small<W>(&self, w: &mut W, offset: u64) -> Result<(u64, u64)>
+    where
+        W: Write,
+    {
+        let size = self.size() as usize;
+        if size > SMALL_ENTRY_SIZE {
+            return self.write_to_at(w, offset);
+        }
+
+        let mut buf = [0u8; SMALL_ENTRY_SIZE];
+        let header = self.header.as_ref();
+        let key_end = header.len() + self.key.len();
+        buf[..header.len()].copy_from_slice(header);
+        buf[header.len()..key_end].copy_from_slice(&self.key);
+        buf[key_end..size].copy_from_slice(self.stored_value());
+        w.write_all(&buf[..size])?;
+
+        Ok((offset, size as u64))
+    }
+
+    /// Owned entry at `offset` of `buf`, such as a memory-mapped data
+    /// file, with its offset set. See `read_from_buf` to borrow instead.
+    ///
+    /// An entry running past the end of `buf` is a `TruncatedEntry`
+    /// error.
+    pub fn from_mmap(buf: &[u8], offset: usize) -> Result<Option<Self>> {
+        Ok(Self::read_from_buf(buf, offset)?.map(DiskEntryRef::to_entry))
+    }
+}
+
+/// Entries are equal when their key, value, crc, timestamp and stored
+/// key and value sizes are. `offset` and `file_id` only tell where an
+/// entry is placed and are ignored.
+impl PartialEq for DiskEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+            && self.value == other.value
+            && self.header.crc() == other.header.crc()
+            && self.header.timestamp() == other.header.timestamp()
+            && self.header.key_sz() == other.header.key_sz()
+            && self.header.value_sz() == other.header.value_sz()
+    }
+}
+
+impl Eq for DiskEntry {}
+
+/// Rendering of keys in logs and errors, see `KeyDisplay`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum KeyEncoding {
+    /// Text keys as is, others as hex.
+    #[default]
+    Auto,
+    /// `0x` then the bytes in lowercase hex.
+    Hex,
+    /// `b64:` then the bytes in padded standard base64.
+    Base64,
+}
+
+/// Key rendered faithfully whatever its bytes: unlike
+/// `String::from_utf8_lossy`, binary keys such as hashes or integers
+/// stay told apart. See `fmt_key`.
+#[derive(Debug, Clone, Copy)]
+pub struct KeyDisplay<'a> {
+    key: &'a [u8],
+    encoding: KeyEncoding,
+}
+
+impl<'a> KeyDisplay<'a> {
+    pub fn new(key: &'a [u8], encoding: KeyEncoding) -> Self {
+        Self { key, encoding }
+    }
+}
+
+/// `key` rendered as text when it is printable UTF-8, as hex otherwise.
+pub fn fmt_key(key: &[u8]) -> KeyDisplay<'_> {
+    KeyDisplay::new(key, KeyEncoding::Auto)
+}
+
+impl Display for KeyDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const BASE64: &[u8; 64] =
+            b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+        match self.encoding {
+            KeyEncoding::Auto => match std::str::from_utf8(self.key) {
+                Ok(s) if !s.chars().any(char::is_control) => f.write_str(s),
+                _ => KeyDisplay::new(self.key, KeyEncoding::Hex).fmt(f),
+            },
+            KeyEncoding::Hex => {
+                f.write_str("0x")?;
+                self.key.iter().try_for_each(|b| write!(f, "{:02x}", b))
+            }
+            KeyEncoding::Base64 => {
+                f.write_str("b64:")?;
+                for chunk in self.key.chunks(3) {
+                    let mut group = [0u8; 3];
+                    group[..chunk.len()].copy_from_slice(chunk);
+                    let n = u32::from_be_bytes([0, group[0], group[1], group[2]]);
+                    for i in 0..4 {
+                        let c = match i <= chunk.len() {
+                            true => BASE64[(n >> (18 - 6 * i) & 0x3F) as usize],
+                            false => b'=',
+                        };
+                        write!(f, "{}", c as char)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Display for DiskEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "DiskEntry(file_id={:?}, key='{}', offset={:?}, size={}, timestamp={})",
+            self.file_id,
+            fmt_key(&self.key),
+            self.offset,
+            self.size(),
+            self.datetime().to_rfc3339_opts(SecondsFormat::Secs, true),
+        )
+    }
+}
+
+impl EntryIO for DiskEntry {
+    type Entry = Self;
+
+    fn read_from<R>(r: &mut R, offset: u64) -> Result<Option<Self::Entry>>
+    where
+        R: Read + Seek,
+    {
+        let available = available_at(r, offset)?;
+        r.seek(SeekFrom::Start(offset))?;
+        if available == 0 {
+            return Ok(None);
+        }
+
+        let decoder = HeaderDecoder::new(offset, available);
+        decoder.check_prefix(HEADER_SIZE)?;
+        let mut buf = [0u8; HEADER_SIZE];
+        r.read_exact(&mut buf)?;
+        let Some(mut header) = decoder.prefix(buf)? else {
+            return Err(padding(r, offset)?);
+        };
+        r.read_exact(header.tail_mut())?;
+        if let Some(alias) = decoder.alias(&header) {
+            header = resolve_alias(r, &decoder, header, alias)?;
+        }
+        decoder.finish(&header)?;
+
+        // the file may still shrink under us after the check.
+        let needed = header.size() as u64 + header.key_sz() + header.value_sz();
+        let short = |field| move |e| short_read(e, field, offset, needed, available);
+
+        let mut key = vec![0u8; header.key_sz() as usize];
+        r.read_exact(&mut key).map_err(short(Field::Key))?;
+
+        let mut value = vec![0u8; header.value_sz() as usize];
+        r.read_exact(&mut value).map_err(short(Field::Value))?;
+
+        Ok(Some(Self::decode(header, key, value).offset(offset)))
+    }
+
+    fn write_to_at<W>(&self, w: &mut W, offset: u64) -> Result<(u64, u64)>
+    where
+        W: Write,
+    {
+        w.write_all(self.header.as_ref())?;
+        w.write_all(self.key.as_ref())?;
+        w.write_all(self.stored_value())?;
+
+        Ok((offset, self.size()))
+    }
+}
+
+/// Write an entry of `key` and `value` at the current position of `w`
+/// straight from the borrowed slices, laid out as by `DiskEntry::new`.
+/// Returns the offset it starts at and the number of bytes written.
+pub fn write_kv<W>(key: &[u8], value: &[u8], w: &mut W) -> Result<(u64, u64)>
+where
+    W: Write + Seek,
+{
+    let offset = w.stream_position()?;
+
+    let key_sz = key.len() as u64;
+    let value_sz = value.len() as u64;
+    let flags = checksum_flags::<DefaultChecksum>();
+    let mut header = Header::new(0, now_timestamp(), key_sz, value_sz, flags);
+    header.set_crc(hash::<DefaultChecksum>(
+        key,
+        value,
+        header.flag_bits(),
+        header.covered(),
+    ));
+
+    w.write_all(header.as_ref())?;
+    w.write_all(key)?;
+    w.write_all(value)?;
+
+    Ok((offset, header.size() as u64 + key_sz + value_sz))
+}
+
+/// Layout choices affecting the on-disk size of an entry, see
+/// `DiskEntry::estimate_on_disk_size`, and limits on the sizes of its
+/// key and value, see `DiskEntry::new_with_options`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct WriteOptions {
+    codec: Option<Codec>,
+    align: u64,
+    seq: bool,
+    expire: bool,
+    max_key_size: Option<u64>,
+    max_value_size: Option<u64>,
+}
+
+impl WriteOptions {
+    /// Value compressed with `codec`, see `DiskEntryBuilder::compressed`.
+    pub fn compressed(mut self, codec: Codec) -> Self {
+        self.codec = Some(codec);
+        self
+    }
+
+    /// Entry written with `DiskEntry::write_to_aligned`.
+    pub fn aligned(mut self, align: u64) -> Self {
+        self.align = align;
+        self
+    }
+
+    /// Entry carrying a sequence number.
+    pub fn seq(mut self) -> Self {
+        self.seq = true;
+        self
+    }
+
+    /// Entry carrying an expiry time.
+    pub fn expiring(mut self) -> Self {
+        self.expire = true;
+        self
+    }
+
+    /// Largest key accepted, in bytes. Unlimited by default.
+    pub fn max_key_size(mut self, limit: u64) -> Self {
+        self.max_key_size = Some(limit);
+        self
+    }
+
+    /// Largest value accepted, in bytes before compression. Unlimited by
+    /// default.
+    pub fn max_value_size(mut self, limit: u64) -> Self {
+        self.max_value_size = Some(limit);
+        self
+    }
+}
+
+/// Builder of a `DiskEntry` with explicit header fields, such as the
+/// timestamp of an entry restored from a backup or replicated from
+/// another node.
+#[derive(Debug, Clone)]
+pub struct DiskEntryBuilder {
+    key: EntryBuf,
+    value: EntryBuf,
+    timestamp: Option<u32>,
+    crc: Option<u64>,
+    seq: Option<u64>,
+    expire_at: Option<u32>,
+    ttl: Option<Duration>,
+    tombstone: bool,
+    codec: Option<Codec>,
+    #[cfg(feature = "encryption")]
+    cipher: Option<Cipher>,
+    max_key_size: Option<u64>,
+    max_value_size: Option<u64>,
+    marker: bool,
+    // flags of a value handed over as stored, such as a sealed one.
+    stored_flags: u8,
+}
+
+impl DiskEntryBuilder {
+    pub fn new(key: impl Into<EntryBuf>, value: impl Into<EntryBuf>) -> Self {
+        Self {
+            key: key.into(),
+            value: value.into(),
+            timestamp: None,
+            crc: None,
+            seq: None,
+            expire_at: None,
+            ttl: None,
+            tombstone: false,
+            codec: None,
+            #[cfg(feature = "encryption")]
+            cipher: None,
+            max_key_size: None,
+            max_value_size: None,
+            marker: false,
+            stored_flags: 0,
+        }
+    }
+
+    /// Apply the codec and size limits of `opts`, its layout choices
+    /// are set through the other methods.
+    pub fn options(mut self, opts: &WriteOptions) -> Self {
+        if opts.codec.is_some() {
+            self.codec = opts.codec;
+        }
+        self.max_key_size = opts.max_key_size;
+        self.max_value_size = opts.max_value_size;
+        self
+    }
+
+    /// Timestamp to store instead of the current time.
+    pub fn timestamp(mut self, timestamp: u32) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Timestamp the entry with the current time of `clock`.
+    pub fn clock(self, clock: &(impl Clock + ?Sized)) -> Self {
+        self.timestamp(clock.now_secs())
+    }
+
+    /// Crc to store as is instead of computing it, the entry fails
+    /// validation if it doesn't match the content.
+    pub fn crc(mut self, crc: u64) -> Self {
+        self.crc = Some(crc);
+        self
+    }
+
+    pub fn seq(mut self, seq: u64) -> Self {
+        self.seq = Some(seq);
+        self
+    }
+
+    /// Expiry time, seconds since the unix epoch.
+    pub fn expire_at(mut self, expire_at: u32) -> Self {
+        self.expire_at = Some(expire_at);
+        self
+    }
+
+    /// Expire the entry `ttl` after its timestamp, unless an expiry time
+    /// is given.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    /// Make the entry a deletion marker, the value is dropped.
+    pub fn tombstone(mut self) -> Self {
+        self.tombstone = true;
+        self.value.clear();
+        self
+    }
+
+    /// Store the value compressed with `codec`.
+    pub fn compressed(mut self, codec: Codec) -> Self {
+        self.codec = Some(codec);
+        self
+    }
+
+    /// Store the value sealed with `cipher`, after compression if any.
+    #[cfg(feature = "encryption")]
+    pub fn encrypted(mut self, cipher: &Cipher) -> Self {
+        self.cipher = Some(cipher.clone());
+        self
+    }
+
+    pub fn build(self) -> Result<DiskEntry> {
+        self.build_with::<DefaultChecksum>()
+    }
+
+    /// Build the entry, computing its crc with checksum `C` unless one
+    /// was given. An empty key is an `EmptyKey` error.
+    pub fn build_with<C: Checksum>(self) -> Result<DiskEntry> {
+        if self.key.is_empty() {
+            return Err(LSMLibError::EmptyKey);
+        }
+        let key_sz = self.key.len() as u64;
+        if let Some(limit) = self.max_key_size.filter(|&limit| key_sz > limit) {
+            return Err(LSMLibError::KeyIsTooLarge {
+                size: key_sz,
+                limit,
+            });
+        }
+        let value_sz = self.value.len() as u64;
+        if let Some(limit) = self.max_value_size.filter(|&limit| value_sz > limit) {
+            return Err(LSMLibError::ValueIsTooLarge {
+                size: value_sz,
+                limit,
+            });
+        }
+        let stored = match self.codec {
+            Some(codec) => Some(from_vec(codec.compress(&self.value)?)),
+            None => None,
+        };
+        #[cfg(feature = "encryption")]
+        let stored = match &self.cipher {
+            Some(cipher) => {
+                let plaintext = stored.as_deref().unwrap_or(&self.value[..]);
+                Some(from_vec(cipher.seal(&self.key, plaintext)?))
+            }
+            None => stored,
+        };
+        Ok(self.assemble::<C>(stored))
+    }
+
+    /// Build the entry around the already encoded `stored` value.
+    fn assemble<C: Checksum>(self, stored: Option<EntryBuf>) -> DiskEntry {
+        let mut flags = checksum_flags::<C>() | self.stored_flags;
+        if self.tombstone {
+            flags |= FLAG_TOMBSTONE;
+        }
+        if self.codec.is_some() {
+            flags |= FLAG_COMPRESSED;
+        }
+        #[cfg(feature = "encryption")]
+        if self.cipher.is_some() {
+            flags |= FLAG_ENCRYPTED;
+        }
+
+        let stored_value = stored.as_deref().unwrap_or(&self.value[..]);
+        let timestamp = self.timestamp.unwrap_or_else(now_timestamp);
+        let key_sz = self.key.len() as u64;
+        let value_sz = stored_value.len() as u64;
+        let mut header = Header::new(0, timestamp, key_sz, value_sz, flags);
+        if self.marker {
+            header.set_batch_marker();
+        }
+        if let Some(seq) = self.seq {
+            header.set_seq(seq);
+        }
+        let expire_at = self.expire_at.or_else(|| {
+            let ttl = u32::try_from(self.ttl?.as_secs()).unwrap_or(u32::MAX);
+            Some(timestamp.saturating_add(ttl))
+        });
+        if let Some(expire_at) = expire_at {
+            header.set_expire_at(expire_at);
+        }
+        let crc = self.crc.unwrap_or_else(|| {
+            hash::<C>(
+                &self.key,
+                stored_value,
+                header.flag_bits(),
+                header.covered(),
+            )
+        });
+        header.set_crc(crc);
+
+        DiskEntry {
+            header,
+            key: self.key,
+            value: self.value,
+            stored,
+            offset: None,
+            file_id: None,
+        }
+    }
+}
+
+/// Disk entry borrowing its key and value from a buffer, such as a
+/// memory-mapped file.
+#[derive(Debug, Clone, Copy)]
+pub struct DiskEntryRef<'a> {
+    /// header of the disk entry.
+    header: Header,
+
+    /// key of the disk entry.
+    pub key: &'a [u8],
+
+    /// value of the disk entry, as stored on disk.
+    pub value: &'a [u8],
+
+    /// offset of the disk entry in the buffer.
+    pub offset: u64,
+}
+
+impl<'a> DiskEntryRef<'a> {
+    pub fn crc(&self) -> u64 {
+        self.header.crc()
+    }
+
+    pub fn flags(&self) -> EntryFlags {
+        self.header.flags()
+    }
+
+    /// Write time in seconds since the unix epoch.
+    pub fn timestamp(&self) -> u32 {
+        self.header.timestamp()
+    }
+
+    /// Write time, see `timestamp`.
+    pub fn datetime(&self) -> DateTime<Utc> {
+        to_datetime(self.timestamp())
+    }
+
+    pub fn is_tombstone(&self) -> bool {
+        self.header.is_tombstone()
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.header.is_compressed()
+    }
+
+    pub fn seq(&self) -> Option<u64> {
+        self.header.seq()
+    }
+
+    pub fn expire_at(&self) -> Option<u32> {
+        self.header.expire_at()
+    }
+
+    pub fn size(&self) -> u64 {
+        (self.header.size() + self.key.len() + self.value.len()) as u64
+    }
+
+    pub fn is_validate(&self) -> bool {
+        self.is_validate_with::<DefaultChecksum>()
+    }
+
+    pub fn is_validate_with<C: Checksum>(&self) -> bool {
+        self.header.crc() == entry_crc::<C>(&self.header, self.key, self.value)
+    }
+
+    /// Copy key and value out into an owned entry, decompressing the
+    /// value if needed.
+    pub fn to_entry(self) -> DiskEntry {
+        DiskEntry::decode(self.header, self.key.to_vec(), self.value.to_vec()).offset(self.offset)
+    }
+}
+
+/// How a sequential scan of a data file ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanEnd {
+    /// The last entry ends the file.
+    Clean { offset: u64 },
+
+    /// Fewer bytes than a header remain at `offset`, such as a header
+    /// torn by a crash.
+    PartialHeader { offset: u64, available: u64 },
+
+    /// The header at `offset` declares `needed` bytes but only
+    /// `available` remain.
+    TruncatedBody {
+        offset: u64,
+        needed: u64,
+        available: u64,
+    },
+}
+
+impl ScanEnd {
+    /// End of the last valid entry, where the file can be truncated.
+    pub fn offset(&self) -> u64 {
+        match *self {
+            ScanEnd::Clean { offset }
+            | ScanEnd::PartialHeader { offset, .. }
+            | ScanEnd::TruncatedBody { offset, .. } => offset,
+        }
+    }
+
+    pub fn is_clean(&self) -> bool {
+        matches!(self, ScanEnd::Clean { .. })
+    }
+
+    /// End of a scan stopped by error `e`, any error but a truncated
+    /// entry is handed back.
+    fn from_error(e: LSMLibError) -> Result<Self> {
+        match e {
+            LSMLibError::TruncatedEntry {
+                field: Field::Header,
+                offset,
+                available,
+                ..
+            } => Ok(ScanEnd::PartialHeader { offset, available }),
+            LSMLibError::TruncatedEntry {
+                offset,
+                needed,
+                available,
+                ..
+            } => Ok(ScanEnd::TruncatedBody {
+                offset,
+                needed,
+                available,
+            }),
+            e => Err(e),
+        }
+    }
+}
+
+/// Walk over the headers of a data file from offset 0, yielding each
+/// with the offset of its entry and the reader left at its key, zero
+/// padding skipped. Scans needing more than headers read the rest of an
+/// entry themselves through `get_mut`.
+///
+/// The walk ends at EOF or at a truncated entry, see `end`, or after the
+/// first other error, which is yielded.
+pub(super) struct HeaderWalker<R> {
+    reader: R,
+    /// length of the stream, looked up on the first step.
+    len: Option<u64>,
+    offset: u64,
+    padding: u64,
+    end: Option<ScanEnd>,
+    done: bool,
+}
+
+impl<R> HeaderWalker<R> {
+    pub(super) fn new(reader: R) -> Self {
+        Self {
+            reader,
+            len: None,
+            offset: 0,
+            padding: 0,
+            end: None,
+            done: false,
+        }
+    }
+
+    pub(super) fn get_mut(&mut self) -> &mut R {
+        &mut self.reader
+    }
+
+    /// Bytes of zero padding skipped so far.
+    pub(super) fn padding(&self) -> u64 {
+        self.padding
+    }
+
+    /// How the walk ended, `None` until it has or after an error.
+    pub(super) fn end(&self) -> Option<ScanEnd> {
+        self.end
+    }
+}
+
+impl<R> HeaderWalker<R>
+where
+    R: Read + Seek,
+{
+    fn step(&mut self) -> Result<Option<(u64, Header)>> {
+        let len = match self.len {
+            Some(len) => len,
+            None => *self.len.insert(stream_len(&mut self.reader)?),
+        };
+        while self.offset < len {
+            match DiskEntry::read_header(&mut self.reader, self.offset) {
+                Ok(header) => {
+                    let offset = self.offset;
+                    self.offset += header.size() as u64 + header.key_sz() + header.value_sz();
+                    return Ok(Some((offset, header)));
+                }
+                Err(LSMLibError::Padding { len, .. }) => {
+                    self.padding += len;
+                    self.offset += len;
+                }
+                Err(e) => {
+                    self.end = Some(ScanEnd::from_error(e)?);
+                    return Ok(None);
+                }
+            }
+        }
+        self.end = Some(ScanEnd::Clean {
+            offset: self.offset,
+        });
+        Ok(None)
+    }
+}
+
+impl<R> Iterator for HeaderWalker<R>
+where
+    R: Read + Seek,
+{
+    type Item = Result<(u64, Header)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let step = self.step();
+        // nothing after an error can be located.
+        self.done = !matches!(step, Ok(Some(_)));
+        step.transpose()
+    }
+}
+
+/// Outcome of `verify_file`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScrubReport {
+    /// number of entries checked.
+    pub entries: u64,
+
+    /// bytes taken by the checked entries.
+    pub bytes: u64,
+
+    /// offsets of the entries failing the crc check.
+    pub crc_failures: Vec<u64>,
+
+    /// how the scan ended.
+    pub end: ScanEnd,
+}
+
+impl ScrubReport {
+    /// No entry failed its crc check and the last one ends the file.
+    pub fn is_clean(&self) -> bool {
+        self.crc_failures.is_empty() && self.end.is_clean()
+    }
+}
+
+/// Header of the entry at the offset of `decoder` between the flagged
+/// `header` and its baseline `alias`, see `HeaderDecoder::alias`.
+/// Leaves `r` at the key of the header returned.
+fn resolve_alias<R>(
+    r: &mut R,
+    decoder: &HeaderDecoder,
+    header: Header,
+    alias: Header,
+) -> Result<Header>
+where
+    R: Read + Seek,
+{
+    let offset = decoder.offset();
+    let mut next = Vec::with_capacity(HEADER_SIZE);
+    r.seek(SeekFrom::Start(offset + alias.entry_len()))?;
+    r.by_ref().take(HEADER_SIZE as u64).read_to_end(&mut next)?;
+
+    let resolved = if decoder.alias_followed(&alias, &next)
+        && !(decoder.finish(&header).is_ok() && passes_crc(r, offset, &header)?)
+        && passes_crc(r, offset, &alias)?
+    {
+        alias
+    } else {
+        header
+    };
+    r.seek(SeekFrom::Start(offset + resolved.size() as u64))?;
+    Ok(resolved)
+}
+
+/// Same as `resolve_alias`, for the entry starting `buf`.
+fn resolve_alias_in(buf: &[u8], decoder: &HeaderDecoder, header: Header, alias: Header) -> Header {
+    let passes = |header: &Header| {
+        let key_end = header.size() + header.key_sz() as usize;
+        let value_end = key_end + header.value_sz() as usize;
+        header.matches(&buf[header.size()..key_end], &buf[key_end..value_end])
+    };
+    let end = alias.entry_len() as usize;
+    let next = &buf[end..buf.len().min(end + HEADER_SIZE)];
+
+    if decoder.alias_followed(&alias, next)
+        && !(decoder.finish(&header).is_ok() && passes(&header))
+        && passes(&alias)
+    {
+        alias
+    } else {
+        header
+    }
+}
+
+/// Whether the entry of `header` at `offset` of `r` passes its crc, its
+/// key and value streamed through.
+fn passes_crc<R>(r: &mut R, offset: u64, header: &Header) -> Result<bool>
+where
+    R: Read + Seek,
+{
+    r.seek(SeekFrom::Start(offset + header.size() as u64))?;
+    let mut buf = vec![0u8; 64 * 1024];
+    let len = header.key_sz() + header.value_sz();
+    let crc = if header.flag_bits() & FLAG_CRC64 != 0 {
+        stream_crc::<Crc64, _>(r, len, &mut buf, header)?
+    } else {
+        stream_crc::<Crc32, _>(r, len, &mut buf, header)?
+    };
+    Ok(crc == header.crc())
+}
+
+/// Crc of the entry with `header` whose `len` bytes of key and value are
+/// read from `r` through `buf`.
+fn stream_crc<C, R>(r: &mut R, len: u64, buf: &mut [u8], header: &Header) -> Result<u64>
+where
+    C: Checksum,
+    R: Read,
+{
+    stream_hashed::<C, R, _>(&[], r, len, buf, &mut io::sink(), header)
+}
+
+/// Crc of the entry with `header` and `key`, whose remaining `len` bytes
+/// are read from `r` through `buf` and copied to `w`.
+fn stream_hashed<C, R, W>(
+    key: &[u8],
+    r: &mut R,
+    len: u64,
+    buf: &mut [u8],
+    w: &mut W,
+    header: &Header,
+) -> Result<u64>
+where
+    C: Checksum,
+    R: Read,
+    W: Write,
+{
+    let mut hasher = EntryHasher::<C>::new();
+    hasher.update(key);
+    let mut remaining = len;
+    while remaining > 0 {
+        let n = remaining.min(buf.len() as u64) as usize;
+        r.read_exact(&mut buf[..n])?;
+        hasher.update(&buf[..n]);
+        w.write_all(&buf[..n])?;
+        remaining -= n as u64;
+    }
+    Ok(hasher.finalize_with(header.flag_bits(), header.covered()))
+}
+
+/// Answer of a `visit_entries` callback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VisitAction {
+    Continue,
+    Stop,
+}
+
+/// Call `visit` with the header, key and value of every entry of data
+/// file `r` from offset 0 until it answers `Stop`, returns the number of
+/// entries visited. Zero padding is skipped.
+///
+/// Key and value borrow a buffer reused from entry to entry, valid only
+/// during the call, so nothing is allocated per entry. The value is as
+/// stored, compressed or encrypted if the header says so. An entry
+/// failing the crc check is a `CrcMismatch` error, the scan stops at EOF
+/// or at a truncated entry.
+pub fn visit_entries<R, F>(r: &mut R, mut visit: F) -> Result<u64>
+where
+    R: Read + Seek,
+    F: FnMut(&Header, &[u8], &[u8]) -> VisitAction,
+{
+    let mut buf = Vec::new();
+    let mut visited = 0;
+    let mut walker = HeaderWalker::new(r);
+    while let Some(step) = walker.next() {
+        let (offset, header) = step?;
+        let key_sz = header.key_sz() as usize;
+        buf.resize(key_sz + header.value_sz() as usize, 0);
+        walker.get_mut().read_exact(&mut buf)?;
+        let (key, value) = buf.split_at(key_sz);
+        let actual = entry_crc::<DefaultChecksum>(&header, key, value);
+        if actual != header.crc() {
+            return Err(LSMLibError::CrcMismatch {
+                key: key.to_vec(),
+                file_id: None,
+                offset: Some(offset),
+                expected: header.crc(),
+                actual,
+            });
+        }
+
+        visited += 1;
+        if visit(&header, key, value) == VisitAction::Stop {
+            break;
+        }
+    }
+    Ok(visited)
+}
+
+/// Check the crc of every entry of data file `r` from offset 0, streaming
+/// keys and values through the checksum without keeping them.
+///
+/// An entry failing the check is recorded and the scan goes on, as its
+/// header still locates the next one. Zero padding is skipped. A header
+/// that can't be decoded is an error, nothing after it can be located.
+pub fn verify_file<R>(r: &mut R) -> Result<ScrubReport>
+where
+    R: Read + Seek,
+{
+    let mut report = ScrubReport {
+        entries: 0,
+        bytes: 0,
+        crc_failures: Vec::new(),
+        end: ScanEnd::Clean { offset: 0 },
+    };
+
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut walker = HeaderWalker::new(r);
+    while let Some(step) = walker.next() {
+        let (offset, header) = step?;
+        let len = header.key_sz() + header.value_sz();
+        let r = walker.get_mut();
+        let crc = if header.flag_bits() & FLAG_CRC64 != 0 {
+            stream_crc::<Crc64, _>(r, len, &mut buf, &header)?
+        } else {
+            stream_crc::<Crc32, _>(r, len, &mut buf, &header)?
+        };
+        if crc != header.crc() {
+            report.crc_failures.push(offset);
+        }
+
+        report.entries += 1;
+        report.bytes += header.size() as u64 + len;
+    }
+
+    report.end = walker.end().unwrap_or(report.end);
+    Ok(report)
+}
+
+/// Copy data file `r` from offset 0 to `w`, rewriting the crc of every
+/// entry failing the check with the crc of its key and value, returns
+/// the number of entries fixed. Keys and values are streamed through.
+///
+/// Anything else is copied byte for byte, zero padding included, so
+/// valid entries come out identical and at the same offsets, keeping
+/// hints valid. Meant to recover from a bug writing wrong crcs: a crc
+/// failing because of corrupted data is "fixed" all the same. An
+/// unreadable entry ends the repair with its error, the entries before
+/// it being written.
+pub fn repair_crcs<R, W>(r: &mut R, w: &mut W) -> Result<usize>
+where
+    R: Read + Seek,
+    W: Write + Seek,
+{
+    let len = stream_len(r)?;
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut fixed = 0;
+    let mut offset = 0;
+    while offset < len {
+        let mut header = match DiskEntry::read_header(r, offset) {
+            Ok(header) => header,
+            Err(LSMLibError::Padding { len, .. }) => {
+                io::copy(&mut io::repeat(0).take(len), w)?;
+                offset += len;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        let dest = w.stream_position()?;
+        w.write_all(header.as_ref())?;
+        let len = header.key_sz() + header.value_sz();
+        let crc = if header.flag_bits() & FLAG_CRC64 != 0 {
+            stream_hashed::<Crc64, _, _>(&[], r, len, &mut buf, w, &header)?
+        } else {
+            stream_hashed::<Crc32, _, _>(&[], r, len, &mut buf, w, &header)?
+        };
+        if crc != header.crc() {
+            let end = w.stream_position()?;
+            header.set_crc(crc);
+            w.seek(SeekFrom::Start(dest))?;
+            w.write_all(header.as_ref())?;
+            w.seek(SeekFrom::Start(end))?;
+            fixed += 1;
+        }
+
+        offset += header.size() as u64 + len;
+    }
+    Ok(fixed)
+}
+
+/// Outcome of `file_stats`. Value sizes are as stored, after compression
+/// or encryption if any.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileStats {
+    /// number of entries, tombstones included.
+    pub entries: u64,
+
+    pub tombstones: u64,
+
+    /// bytes taken by the entries, headers included.
+    pub bytes: u64,
+
+    pub key_bytes: u64,
+
+    pub value_bytes: u64,
+
+    pub max_key_sz: u64,
+
+    pub max_value_sz: u64,
+
+    /// number of values by size, bucket 0 counting empty values and
+    /// bucket `i` values of `2^(i-1)` to `2^i - 1` bytes. Ends at the
+    /// largest bucket used.
+    pub value_histogram: Vec<u64>,
+}
+
+impl FileStats {
+    pub fn avg_key_sz(&self) -> f64 {
+        self.key_bytes as f64 / self.entries.max(1) as f64
+    }
+
+    pub fn avg_value_sz(&self) -> f64 {
+        self.value_bytes as f64 / self.entries.max(1) as f64
+    }
+
+    fn add(&mut self, header: &Header) {
+        let (key_sz, value_sz) = (header.key_sz(), header.value_sz());
+        self.entries += 1;
+        self.tombstones += header.is_tombstone() as u64;
+        self.bytes += header.size() as u64 + key_sz + value_sz;
+        self.key_bytes += key_sz;
+        self.value_bytes += value_sz;
+        self.max_key_sz = self.max_key_sz.max(key_sz);
+        self.max_value_sz = self.max_value_sz.max(value_sz);
+
+        let bucket = (u64::BITS - value_sz.leading_zeros()) as usize;
+        if self.value_histogram.len() <= bucket {
+            self.value_histogram.resize(bucket + 1, 0);
+        }
+        self.value_histogram[bucket] += 1;
+    }
+}
+
+/// Sizes of the entries of data file `r` from offset 0, walking their
+/// headers only as `entry_offsets` does, keys and values are skipped.
+pub fn file_stats<R>(r: &mut R) -> Result<FileStats>
+where
+    R: Read + Seek,
+{
+    let mut stats = FileStats::default();
+    for step in HeaderWalker::new(r) {
+        stats.add(&step?.1);
+    }
+    Ok(stats)
+}
+
+/// Bytes of data file `r` a compaction would reclaim: the size of every
+/// entry whose key and offset are not in `live`, the keydir of the
+/// store, tombstones and superseded entries alike, plus zero padding.
+/// Only headers and keys are read.
+pub fn dead_bytes<R>(r: &mut R, live: &HashSet<(Vec<u8>, u64)>) -> Result<u64>
+where
+    R: Read + Seek,
+{
+    let mut dead = 0;
+    let mut key = Vec::new();
+    let mut walker = HeaderWalker::new(r);
+    while let Some(step) = walker.next() {
+        let (offset, header) = step?;
+        key.resize(header.key_sz() as usize, 0);
+        walker.get_mut().read_exact(&mut key)?;
+        // the key moves into the probe and back, saving a copy per entry.
+        let probe = (std::mem::take(&mut key), offset);
+        if !live.contains(&probe) {
+            dead += header.size() as u64 + header.key_sz() + header.value_sz();
+        }
+        key = probe.0;
+    }
+    Ok(dead + walker.padding())
+}
+
+/// Checksum algorithm of data file `r`, the one of its first entry. An
+/// empty file, or one written before the crc64 flag, is `Crc32`.
+///
+/// Entries are checked with the algorithm of their own header whatever
+/// this says, a file switching algorithms midway stays readable.
+pub fn detect_checksum<R>(r: &mut R) -> Result<ChecksumKind>
+where
+    R: Read + Seek,
+{
+    match HeaderWalker::new(r).next().transpose()? {
+        Some((_, header)) => Ok(header.checksum_kind()),
+        None => Ok(ChecksumKind::Crc32),
+    }
+}
+
+/// Offsets of the entries of data file `r` from offset 0, walking their
+/// headers only, skipping zero padding. The walk stops at EOF or at a
+/// truncated entry.
+pub fn entry_offsets<R>(r: &mut R) -> Result<Vec<u64>>
+where
+    R: Read + Seek,
+{
+    HeaderWalker::new(r)
+        .map(|step| step.map(|(offset, _)| offset))
+        .collect()
+}
+
+/// Read the last `n` entries of data file `r`, oldest first, such as the
+/// most recent writes to recover after a crash.
+///
+/// Entries aren't linked backwards, their offsets are found walking the
+/// headers forward once, see `entry_offsets`, and only the last `n` are
+/// read whole. An entry failing the crc check is a `CrcMismatch` error.
+pub fn last_entries<R>(r: &mut R, n: usize) -> Result<Vec<DiskEntry>>
+where
+    R: Read + Seek,
+{
+    let offsets = entry_offsets(r)?;
+    let tail = &offsets[offsets.len().saturating_sub(n)..];
+
+    let mut entries = Vec::with_capacity(tail.len());
+    for &offset in tail {
+        if let Some(entry) = DiskEntry::read_from_checked(r, offset)? {
+            entries.push(entry);
+        }
+    }
+    Ok(entries)
+}
+
+/// Writer appending entries to `W` through an in-memory buffer, written
+/// out once it holds `capacity` bytes or more, or on `flush`.
+///
+/// Entries are buffered whole and written out with a single `write_all`,
+/// so no entry is split across two writes. Buffered entries are written
+/// out on drop, errors then ignored, `flush` or `into_inner` report them.
+#[derive(Debug)]
+pub struct BufferedEntryWriter<W: Write> {
+    inner: Option<W>,
+    buf: Vec<u8>,
+    capacity: usize,
+    offset: u64,
+}
+
+impl<W> BufferedEntryWriter<W>
+where
+    W: Write + Seek,
+{
+    pub const DEFAULT_CAPACITY: usize = 64 * 1024;
+
+    /// Writer appending at the current position of `inner`.
+    pub fn new(inner: W) -> Result<Self> {
+        Self::with_capacity(inner, Self::DEFAULT_CAPACITY)
+    }
+
+    /// Same as `new`, writing out once `capacity` bytes are buffered.
+    pub fn with_capacity(mut inner: W, capacity: usize) -> Result<Self> {
+        let offset = inner.stream_position()?;
+        Ok(Self {
+            inner: Some(inner),
+            buf: Vec::with_capacity(capacity),
+            capacity,
+            offset,
+        })
+    }
+
+    /// Buffer `entry`, returns the offset it is written at.
+    pub fn write(&mut self, entry: &DiskEntry) -> Result<u64> {
+        let offset = self.position();
+        entry.encode_into(&mut self.buf);
+        if self.buf.len() >= self.capacity {
+            self.flush()?;
+        }
+        Ok(offset)
+    }
+
+    /// Write out the buffered entries.
+    pub fn flush(&mut self) -> Result<()> {
+        if self.buf.is_empty() {
+            return Ok(());
+        }
+        let inner = self.inner.as_mut().expect("writer taken");
+        inner.write_all(&self.buf)?;
+        inner.flush()?;
+        self.offset += self.buf.len() as u64;
+        self.buf.clear();
+        Ok(())
+    }
+
+    /// Offset the next entry is written at.
+    pub fn position(&self) -> u64 {
+        self.offset + self.buf.len() as u64
+    }
+
+    /// Number of bytes buffered, not yet written out.
+    pub fn buffered(&self) -> usize {
+        self.buf.len()
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.inner.as_ref().expect("writer taken")
+    }
+
+    /// Write out the buffered entries and return the inner writer.
+    pub fn into_inner(mut self) -> Result<W> {
+        self.flush()?;
+        Ok(self.inner.take().expect("writer taken"))
+    }
+}
+
+impl<W: Write> Drop for BufferedEntryWriter<W> {
+    fn drop(&mut self) {
+        if let Some(inner) = self.inner.as_mut() {
+            if !self.buf.is_empty() {
+                let _ = inner.write_all(&self.buf).and_then(|_| inner.flush());
+            }
+        }
+    }
+}
+
+/// Sequential reader over all disk entries of a data file.
+///
+/// Entries are read back to back from offset 0, each yielded with its
+/// offset set, zero padding and batch markers between them skipped.
+/// Iteration ends at EOF or at a truncated entry, see `end`, or after
+/// the first other error, which includes entries failing the crc check.
+pub struct DiskEntryReader<R> {
+    reader: R,
+    offset: u64,
+    align: u64,
+    markers: bool,
+    end: Option<ScanEnd>,
+    done: bool,
+}
+
+impl<R> DiskEntryReader<R>
+where
+    R: Read + Seek,
+{
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            offset: 0,
+            align: 1,
+            markers: false,
+            end: None,
+            done: false,
+        }
+    }
+
+    /// How the scan ended, `None` while entries remain or after an error
+    /// other than a truncated entry.
+    pub fn end(&self) -> Option<ScanEnd> {
+        self.end
+    }
+
+    /// Read a file written with `write_to_aligned`, skipping padding.
+    pub fn aligned(mut self, align: u64) -> Self {
+        self.align = align;
+        self
+    }
+
+    /// Yield the batch markers too, skipped otherwise, see `batch`.
+    pub fn with_markers(mut self) -> Self {
+        self.markers = true;
+        self
+    }
+
+    /// Offset of the next entry to read.
+    pub fn position(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R> Iterator for DiskEntryReader<R>
+where
+    R: Read + Seek,
+{
+    type Item = Result<DiskEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        loop {
+            return match DiskEntry::read_aligned(&mut self.reader, self.offset, self.align) {
+                Ok(Some(entry)) => {
+                    let end = entry.offset.unwrap_or(self.offset) + entry.size();
+                    self.offset = align_up(end, self.align);
+                    if !self.markers && batch::is_marker(&entry) {
+                        continue;
+                    }
+                    Some(Ok(entry))
+                }
+                Ok(None) => {
+                    self.done = true;
+                    self.end = Some(ScanEnd::Clean {
+                        offset: self.offset,
+                    });
+                    None
+                }
+                Err(LSMLibError::Padding { offset, len }) => {
+                    self.offset = offset + len;
+                    continue;
+                }
+                Err(e) => {
+                    self.done = true;
+                    match ScanEnd::from_error(e) {
+                        Ok(end) => {
+                            self.end = Some(end);
+                            None
+                        }
+                        Err(e) => Some(Err(e)),
+                    }
+                }
+            };
+        }
+    }
+}
+
+/// Read the keys of all disk entries of `r` from offset 0, tombstones
+/// included, see `KeysOnly`.
+pub fn keys_only<R>(r: R) -> KeysOnly<R>
+where
+    R: Read + Seek,
+{
+    KeysOnly {
+        walker: HeaderWalker::new(r),
+        done: false,
+    }
+}
+
+/// Sequential reader over the keys of a data file, seeking past values
+/// without reading them. Ends as `DiskEntryReader` does, but keys are not
+/// checked against the crc, which needs the value.
+pub struct KeysOnly<R> {
+    walker: HeaderWalker<R>,
+    done: bool,
+}
+
+impl<R> KeysOnly<R> {
+    /// How the scan ended, see `DiskEntryReader::end`.
+    pub fn end(&self) -> Option<ScanEnd> {
+        self.walker.end()
+    }
+}
+
+impl<R> Iterator for KeysOnly<R>
+where
+    R: Read + Seek,
+{
+    type Item = Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            let (_, header) = match self.walker.next()? {
+                Ok(step) => step,
+                Err(e) => return Some(Err(e)),
+            };
+
+            if header.is_batch_marker() {
+                continue;
+            }
+            let mut key = vec![0u8; header.key_sz() as usize];
+            if let Err(e) = self.walker.get_mut().read_exact(&mut key) {
+                self.done = true;
+                return Some(Err(e.into()));
+            }
+            return Some(Ok(key));
+        }
+        None
+    }
+}
+
+/// Sequential reader over all disk entries of a data file, yielding the
+/// same as `DiskEntryReader` but parsing entries out of large blocks.
+///
+/// Where `DiskEntryReader` seeks and reads each entry, here reads are
+/// only issued as the block buffer runs dry, and back to back: a few
+/// syscalls for many small entries, and a pattern the kernel detects to
+/// read ahead. Entries larger than the block grow the buffer.
+///
+/// `reader` is read from its current position, taken as offset 0.
+pub struct SequentialScanner<R> {
+    reader: R,
+    buf: Vec<u8>,
+    start: usize,
+    filled: usize,
+    offset: u64,
+    padding: bool,
+    eof: bool,
+    end: Option<ScanEnd>,
+    done: bool,
+}
+
+impl<R> SequentialScanner<R>
+where
+    R: Read,
+{
+    /// Size of the blocks read, unless asked otherwise.
+    pub const DEFAULT_BLOCK_SIZE: usize = 256 * 1024;
+
+    pub fn new(reader: R) -> Self {
+        Self::with_block_size(reader, Self::DEFAULT_BLOCK_SIZE)
+    }
+
+    pub fn with_block_size(reader: R, block_size: usize) -> Self {
+        Self {
+            reader,
+            buf: vec![0u8; block_size.max(HEADER_SIZE)],
+            start: 0,
+            filled: 0,
+            offset: 0,
+            padding: false,
+            eof: false,
+            end: None,
+            done: false,
+        }
+    }
+
+    /// How the scan ended, see `DiskEntryReader::end`.
+    pub fn end(&self) -> Option<ScanEnd> {
+        self.end
+    }
+
+    /// Offset of the next entry to read.
+    pub fn position(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.start += n;
+        self.offset += n as u64;
+    }
+
+    /// Move the unparsed bytes to the front and read until the buffer,
+    /// grown to `needed` bytes if smaller, is full or EOF is reached.
+    fn refill(&mut self, needed: usize) -> Result<()> {
+        self.buf.copy_within(self.start..self.filled, 0);
+        self.filled -= self.start;
+        self.start = 0;
+        if self.buf.len() < needed {
+            self.buf.resize(needed, 0);
+        }
+
+        while self.filled < self.buf.len() {
+            match self.reader.read(&mut self.buf[self.filled..]) {
+                Ok(0) => {
+                    self.eof = true;
+                    break;
+                }
+                Ok(n) => self.filled += n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e.into()),
+            }
+        }
+        Ok(())
+    }
+
+    fn next_entry(&mut self) -> Result<Option<DiskEntry>> {
+        loop {
+            // zero padding, as skipped by `read_from`: a run of zeros
+            // starting with an all-zero header, possibly continued past
+            // the end of the buffer.
+            let rest = &self.buf[self.start..self.filled];
+            let zeros = rest.iter().take_while(|b| **b == 0).count();
+            if zeros > 0 && (self.padding || zeros >= HEADER_SIZE) {
+                self.padding = zeros == rest.len();
+                self.consume(zeros);
+                continue;
+            }
+            if !rest.is_empty() {
+                self.padding = false;
+            }
+
+            let buffered = rest.len();
+            match DiskEntry::read_from_buf(rest, 0) {
+                Ok(Some(entry)) => {
+                    let size = entry.size() as usize;
+                    let entry = entry.to_entry().offset(self.offset);
+                    if let Err(e) = entry.verify_at::<DefaultChecksum>(Some(self.offset)) {
+                        // maybe a baseline entry, only told apart once
+                        // buffered whole along with the entry following
+                        // it, see `Header::baseline_alias`.
+                        let needed = entry.header.baseline_alias().map(|alias| {
+                            let end = alias.entry_len() as usize;
+                            let next = rest
+                                .get(end..end.saturating_add(HEADER_SIZE))
+                                .and_then(|next| <[u8; HEADER_SIZE]>::try_from(next).ok())
+                                .and_then(|next| Header::try_from(next).ok())
+                                .map_or(HEADER_SIZE as u64, |next| next.entry_len());
+                            end.saturating_add(next as usize)
+                        });
+                        match needed {
+                            Some(needed) if !self.eof && buffered < needed => {
+                                self.refill(needed.min(2 * self.buf.len()))?;
+                                continue;
+                            }
+                            _ => return Err(e),
+                        }
+                    }
+                    self.consume(size);
+                    return Ok(Some(entry));
+                }
+                Ok(None) if self.eof => {
+                    self.end = Some(ScanEnd::Clean {
+                        offset: self.offset,
+                    });
+                    return Ok(None);
+                }
+                Ok(None) => self.refill(self.buf.len())?,
+                Err(LSMLibError::TruncatedEntry { needed, .. }) if !self.eof => {
+                    self.refill(needed as usize)?
+                }
+                Err(LSMLibError::TruncatedEntry {
+                    field,
+                    needed,
+                    available,
+                    ..
+                }) => {
+                    self.end = Some(ScanEnd::from_error(LSMLibError::TruncatedEntry {
+                        field,
+                        offset: self.offset,
+                        needed,
+                        available,
+                    })?);
+                    return Ok(None);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+impl<R> Iterator for SequentialScanner<R>
+where
+    R: Read,
+{
+    type Item = Result<DiskEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        loop {
+            let next = self.next_entry().transpose();
+            match next {
+                Some(Ok(entry)) if batch::is_marker(&entry) => continue,
+                Some(Ok(_)) => {}
+                _ => self.done = true,
+            }
+            return next;
+        }
+    }
+}
+
+/// Read the entries of `r` still live now, see `LiveEntries`.
+pub fn live_entries<R>(r: R) -> LiveEntries<DiskEntryReader<R>>
+where
+    R: Read + Seek,
+{
+    LiveEntries::new(DiskEntryReader::new(r), now_timestamp())
+}
+
+/// Adapter over an iterator of entries dropping tombstones and entries
+/// expired at `now`, errors are passed through.
+///
+/// Merging still needs the dropped entries to shadow older writes, it
+/// reads the inner iterator instead.
+pub struct LiveEntries<I> {
+    inner: I,
+    now: u32,
+}
+
+impl<I> LiveEntries<I>
+where
+    I: Iterator<Item = Result<DiskEntry>>,
+{
+    pub fn new(inner: I, now: u32) -> Self {
+        Self { inner, now }
+    }
+
+    pub fn get_ref(&self) -> &I {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> I {
+        self.inner
+    }
+}
+
+impl<I> Iterator for LiveEntries<I>
+where
+    I: Iterator<Item = Result<DiskEntry>>,
+{
+    type Item = Result<DiskEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let now = self.now;
+        self.inner.find(|entry| match entry {
+            Ok(entry) => !entry.is_tombstone() && !entry.is_expired(now),
+            Err(_) => true,
+        })
+    }
+}
+
+/// Read all valid disk entries of `r` from offset 0, skipping corrupted
+/// ones instead of stopping at them.
+pub fn recover_entries<R>(r: R) -> RecoveringReader<R>
+where
+    R: Read + Seek,
+{
+    RecoveringReader {
+        reader: r,
+        offset: 0,
+        skipped: 0,
+        open: None,
+        committed: VecDeque::new(),
+        valid_len: 0,
+        done: false,
+    }
+}
+
+/// Truncate data file `f` right after its last crc-valid entry, found by
+/// the scan of `recover_entries`, dropping a torn tail before appending
+/// again. Returns the new length, `f` is left positioned there.
+///
+/// Corrupted bytes between valid entries are kept, only the tail past
+/// the last valid entry goes, trailing padding and the entries of a
+/// batch never committed included.
+pub fn truncate_to_last_valid<F>(f: &mut F) -> Result<u64>
+where
+    F: Read + Seek + Write + SetLen,
+{
+    let mut entries = recover_entries(&mut *f);
+    entries.by_ref().for_each(drop);
+    let len = entries.valid_len();
+    f.set_len(len)?;
+    f.seek(SeekFrom::Start(len))?;
+    Ok(len)
+}
+
+/// Corruption tolerant reader over the disk entries of a data file.
+///
+/// An entry failing to decode or the crc check is skipped by scanning
+/// forward byte by byte until a valid entry starts again, or EOF.
+///
+/// The entries of a batch are held back until its commit marker, and
+/// dropped if the batch is never committed or lost some of its entries
+/// to corruption, see `batch`.
+pub struct RecoveringReader<R> {
+    reader: R,
+    offset: u64,
+    skipped: u64,
+    // count and entries read so far of the open batch.
+    open: Option<(u64, Vec<DiskEntry>)>,
+    committed: VecDeque<DiskEntry>,
+    valid_len: u64,
+    done: bool,
+}
+
+impl<R> RecoveringReader<R> {
+    /// Number of bytes skipped so far.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    /// End of the last entry yielded or batch committed so far, where the
+    /// valid data ends once the scan is over.
+    pub fn valid_len(&self) -> u64 {
+        self.valid_len
+    }
+
+    /// Hold the entries of an open batch until its commit marker, returns
+    /// the next entry to yield if any.
+    fn batch_step(&mut self, entry: DiskEntry) -> Option<DiskEntry> {
+        let end = entry.offset.unwrap_or_default() + entry.size();
+        if let Some(count) = batch::begin_count(&entry) {
+            self.drop_open_batch();
+            self.open = Some((count, Vec::new()));
+        } else if let Some(count) = batch::commit_count(&entry) {
+            match self.open.take() {
+                Some((expected, entries)) if expected == count && entries.len() as u64 == count => {
+                    self.valid_len = end;
+                    self.committed.extend(entries);
+                }
+                open => {
+                    self.open = open;
+                    self.drop_open_batch();
+                }
+            }
+        } else if let Some((_, entries)) = self.open.as_mut() {
+            entries.push(entry);
+        } else {
+            self.valid_len = end;
+            return Some(entry);
+        }
+        self.committed.pop_front()
+    }
+
+    fn drop_open_batch(&mut self) {
+        if let Some((count, entries)) = self.open.take() {
+            log::warn!(
+                "dropped {} of the {} entries of a batch never committed",
+                entries.len(),
+                count
+            );
+        }
+    }
+
+    /// Offset of the next entry to read.
+    pub fn position(&self) -> u64 {
+        self.offset
+    }
+}
+
+impl<R> Iterator for RecoveringReader<R>
+where
+    R: Read + Seek,
+{
+    type Item = DiskEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(entry) = self.committed.pop_front() {
+            return Some(entry);
+        }
+        let mut resync_from = self.offset;
+        while !self.done {
+            match DiskEntry::read_from_checked(&mut self.reader, self.offset) {
+                Ok(Some(entry)) => {
+                    if self.offset > resync_from {
+                        log::warn!(
+                            "skipped {} corrupted bytes from offset {}",
+                            self.offset - resync_from,
+                            resync_from
+                        );
+                    }
+                    let entry = entry.offset(self.offset);
+                    self.offset += entry.size();
+                    resync_from = self.offset;
+                    if let Some(entry) = self.batch_step(entry) {
+                        return Some(entry);
+                    }
+                }
+                Ok(None) => self.done = true,
+                Err(LSMLibError::Padding { len, .. }) => {
+                    let clean = self.offset == resync_from;
+                    self.offset += len;
+                    if clean {
+                        resync_from = self.offset;
+                    }
+                }
+                Err(_) => {
+                    self.offset += 1;
+                    self.skipped += 1;
+                }
+            }
+        }
+
+        if self.offset > resync_from {
+            log::warn!(
+                "skipped {} corrupted bytes from offset {} up to EOF",
+                self.offset - resync_from,
+                resync_from
+            );
+        }
+        self.drop_open_batch();
+        None
+    }
+}
+
+/// Hint Entry Header Structure.
+///
+/// The version and flags bytes sit at the same positions as in the
+/// disk entry header, and the version always matches the layout of the
+/// disk entry the hint points at.
+///
+/// # compact fields:
+/// - offset: u64
+/// - key_sz: u24
+/// - version: u8
+/// - value_sz: u24
+/// - flags: u8, same as the flags of the disk entry.
+/// - timestamp: u32
+///
+/// # wide fields:
+/// - offset: u64
+/// - reserved: [u8; 3]
+/// - version: u8
+/// - reserved: [u8; 3]
+/// - flags: u8, same as the flags of the disk entry.
+/// - timestamp: u32
+/// - key_sz: u64
+/// - value_sz: u64
+///
+/// # optional fields, following the layout fields:
+/// - seq: u64, same as the seq of the disk entry.
+/// - expire_at: u32, same as the expiry time of the disk entry.
+/// - crc_hi: u32, same as the upper half of the crc of the disk entry.
+/// - crc: u32, if the hint crc flag is set, covering the hint header
+///   before it and the key. Set on all new hints and filled in when the
+///   hint is encoded.
+///
+/// Fields are little endian on every host, as in `Header`.
+///
+#[derive(Debug)]
+pub struct HintHeader([u8; MAX_HINT_HEADER_SIZE]);
+
+impl HintHeader {
+    pub fn new(offset: u64, key_sz: u64, value_sz: u64, timestamp: u32, flags: u8) -> Self {
+        let version = FormatVersion::for_sizes(key_sz, value_sz);
+        Self::with_version(version, offset, key_sz, value_sz, timestamp, flags)
+    }
+
+    fn with_version(
+        version: FormatVersion,
+        offset: u64,
+        key_sz: u64,
+        value_sz: u64,
+        timestamp: u32,
+        flags: u8,
+    ) -> Self {
+        let mut buf = [0u8; MAX_HINT_HEADER_SIZE];
+        let wide = version == FormatVersion::Wide;
+        layout::hint::init(&mut buf, wide, offset, key_sz, value_sz, timestamp, flags);
+        Self(buf)
+    }
+
+    pub fn offset(&self) -> u64 {
+        layout::hint::offset(&self.0)
+    }
+
+    fn set_offset(&mut self, offset: u64) {
+        layout::hint::set_offset(&mut self.0, offset)
+    }
+
+    pub fn key_sz(&self) -> usize {
+        layout::hint::key_sz(&self.0) as usize
+    }
+
+    pub fn value_sz(&self) -> usize {
+        layout::hint::value_sz(&self.0) as usize
+    }
+
+    pub fn timestamp(&self) -> u32 {
+        layout::hint::timestamp(&self.0)
+    }
+
+    pub fn seq(&self) -> Option<u64> {
+        layout::hint::seq(&self.0)
+    }
+
+    /// Hint header pointing at disk entry `v`.
+    fn for_entry(v: &DiskEntry, offset: u64) -> Self {
+        let mut header = Self::with_version(
+            v.header.version(),
+            offset,
+            v.header.key_sz(),
+            v.header.value_sz(),
+            v.timestamp(),
+            v.header.flag_bits() & !(FLAG_SEQ | FLAG_EXPIRE),
+        );
+        if let Some(seq) = v.seq() {
+            header.set_seq(seq);
+        }
+        if let Some(expire_at) = v.expire_at() {
+            header.set_expire_at(expire_at);
+        }
+        if v.header.flag_bits() & FLAG_CRC64 != 0 {
+            header.set_crc_hi((v.crc() >> 32) as u32);
+        }
+        header
+    }
+
+    /// Upper half of the crc of the disk entry, if 64 bits wide.
+    fn crc_hi(&self) -> Option<u32> {
+        layout::hint::crc_hi(&self.0)
+    }
+
+    /// Must be set after the sequence number and the expiry time.
+    fn set_crc_hi(&mut self, crc_hi: u32) {
+        layout::hint::set_crc_hi(&mut self.0, crc_hi)
+    }
+
+    /// Must be set before the expiry time.
+    fn set_seq(&mut self, seq: u64) {
+        layout::hint::set_seq(&mut self.0, seq)
+    }
+
+    pub fn expire_at(&self) -> Option<u32> {
+        layout::hint::expire_at(&self.0)
+    }
+
+    fn set_expire_at(&mut self, expire_at: u32) {
+        layout::hint::set_expire_at(&mut self.0, expire_at)
+    }
+
+    /// Position of the crc, after all other fields.
+    fn crc_pos(&self) -> usize {
+        layout::hint::crc_pos(&self.0)
+    }
+
+    /// Crc stored in the header, `None` for hints written before hints
+    /// had one.
+    pub fn crc(&self) -> Option<u32> {
+        layout::hint::crc(&self.0)
+    }
+
+    /// Encoded size of this hint header.
+    pub fn size(&self) -> usize {
+        layout::hint::size(&self.0)
+    }
+
+    /// Encoded size of the header of the disk entry this hint points at.
+    fn data_header_size(&self) -> usize {
+        Header::encoded_len_with(self.version(), EntryFlags::from_bits(self.flag_bits()))
+    }
+
+    pub fn version(&self) -> FormatVersion {
+        if layout::is_wide(&self.0) {
+            FormatVersion::Wide
+        } else {
+            FormatVersion::Compact
+        }
+    }
+
+    fn flag_bits(&self) -> u8 {
+        layout::flags(&self.0)
+    }
+
+    pub fn is_tombstone(&self) -> bool {
+        self.flag_bits() & FLAG_TOMBSTONE != 0
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.flag_bits() & FLAG_COMPRESSED != 0
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.flag_bits() & FLAG_ENCRYPTED != 0
+    }
+
+    /// Bytes following the shared 20 bytes prefix, to be filled by readers.
+    pub(super) fn tail_mut(&mut self) -> &mut [u8] {
+        let size = self.size();
+        &mut self.0[HINT_HEADER_SIZE..size]
+    }
+
+    /// Check the hint of this header fits in the `available` bytes from
+    /// `offset`.
+    pub(super) fn check_fits(&self, offset: u64, available: u64) -> Result<()> {
+        let needed = (self.size() as u64).saturating_add(self.key_sz() as u64);
+        if needed > available {
+            let field = if available < self.size() as u64 {
+                Field::Header
+            } else {
+                Field::Key
+            };
+            return Err(LSMLibError::TruncatedEntry {
+                field,
+                offset,
+                needed,
+                available,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl AsRef<[u8]> for HintHeader {
+    fn as_ref(&self) -> &[u8] {
+        &self.0[..self.size()]
+    }
+}
+
+impl TryFrom<[u8; HINT_HEADER_SIZE]> for HintHeader {
+    type Error = LSMLibError;
+
+    fn try_from(prefix: [u8; HINT_HEADER_SIZE]) -> Result<Self> {
+        if !layout::is_baseline(&prefix) {
+            FormatVersion::try_from(prefix[VERSION_POS])?;
+        }
+
+        let mut buf = [0u8; MAX_HINT_HEADER_SIZE];
+        buf[..HINT_HEADER_SIZE].copy_from_slice(&prefix);
+        Ok(Self(buf))
+    }
+}
+
+/// Entry in the hint file.
+#[derive(Debug)]
+pub struct HintEntry {
+    /// header of hint entry.
+    header: HintHeader,
+
+    /// key of disk entry.
+    pub key: Vec<u8>,
+
+    /// file_id of hint entry, also is disk entry.
+    pub file_id: Option<u64>,
+}
+
+impl HintEntry {
+    /// Hint for the disk entry of `key` spanning `size` bytes at `offset`,
+    /// `size` being the whole entry, header included, laid out as by
+    /// `DiskEntry::new`. The upper half of a 64 bits crc is unknown and
+    /// left zero.
+    pub fn new(key: Vec<u8>, offset: u64, size: u64, timestamp: u32) -> Result<Self> {
+        let flags = checksum_flags::<DefaultChecksum>();
+        let key_sz = key.len() as u64;
+        let value_sz_after = |header_size: usize| {
+            let min = (header_size + ext_size(flags)) as u64 + key_sz;
+            size.checked_sub(min)
+                .ok_or(LSMLibError::InvalidEntrySize { size, min })
+        };
+
+        let mut value_sz = value_sz_after(HEADER_SIZE)?;
+        if FormatVersion::for_sizes(key_sz, value_sz) == FormatVersion::Wide {
+            value_sz = value_sz_after(WIDE_HEADER_SIZE)?;
+        }
+        let header = HintHeader::new(offset, key_sz, value_sz, timestamp, flags);
+        Ok(Self {
+            header,
+            key,
+            file_id: None,
+        })
+    }
+
+    pub fn offset(&self) -> u64 {
+        self.header.offset()
+    }
+
+    pub fn size(&self) -> u64 {
+        (self.header.data_header_size() + self.header.key_sz() + self.header.value_sz()) as u64
+    }
+
+    /// Write time in seconds since the unix epoch.
+    pub fn timestamp(&self) -> u32 {
+        self.header.timestamp()
+    }
+
+    /// Write time, see `timestamp`.
+    pub fn datetime(&self) -> DateTime<Utc> {
+        to_datetime(self.timestamp())
+    }
+
+    pub fn hint_size(&self) -> u64 {
+        (self.header.size() + self.key.len()) as u64
+    }
+
+    /// Order by offset alone, for hints of the same data file.
+    pub fn by_offset(a: &Self, b: &Self) -> Ordering {
+        a.offset().cmp(&b.offset())
+    }
+
+    /// Order by write time, such as across data files.
+    pub fn by_timestamp(a: &Self, b: &Self) -> Ordering {
+        a.timestamp().cmp(&b.timestamp())
+    }
+
+    /// Order by key, then by location, see `Ord`.
+    pub fn by_key(a: &Self, b: &Self) -> Ordering {
+        a.key.cmp(&b.key).then_with(|| a.cmp(b))
+    }
+
+    /// `hint_size` of the hint of an entry of `k` and `v` built with
+    /// `DiskEntry::new`, see `DiskEntry::entry_size`.
+    pub fn hint_size_of(k: &[u8], v: &[u8]) -> u64 {
+        let (key_sz, value_sz) = (k.len() as u64, v.len() as u64);
+        let header_sz = FormatVersion::for_sizes(key_sz, value_sz).hint_header_size()
+            + ext_size(checksum_flags::<DefaultChecksum>())
+            + HINT_CRC_SIZE;
+        header_sz as u64 + key_sz
+    }
+
+    pub fn file_id(mut self, file_id: u64) -> Self {
+        self.file_id = Some(file_id);
+        self
+    }
+
+    /// Point the hint at data relocated to `offset`, patching the header
+    /// in place.
+    pub fn set_offset(&mut self, offset: u64) {
+        self.header.set_offset(offset);
+    }
+
+    pub fn set_file_id(&mut self, file_id: u64) {
+        self.file_id = Some(file_id);
+    }
+
+    pub fn key_sz(&self) -> usize {
+        self.header.key_sz()
+    }
+
+    pub fn value_sz(&self) -> usize {
+        self.header.value_sz()
+    }
+
+    pub fn is_tombstone(&self) -> bool {
+        self.header.is_tombstone()
+    }
+
+    pub fn seq(&self) -> Option<u64> {
+        self.header.seq()
+    }
+
+    /// Ordering of the entries pointed at, see `DiskEntry::recency`.
+    pub fn recency(&self) -> Recency {
+        (self.seq(), self.timestamp())
+    }
+
+    pub fn expire_at(&self) -> Option<u32> {
+        self.header.expire_at()
+    }
+
+    /// Whether the entry pointed at expired at `now`, see
+    /// `DiskEntry::is_expired`.
+    pub fn is_expired(&self, now: u32) -> bool {
+        self.expire_at().is_some_and(|expire_at| expire_at <= now)
+    }
+
+    /// Read the value of the entry this hint points at in its data file
+    /// `r`, skipping its header and key. The crc can't be checked without
+    /// them, see `read_value_checked`. Encrypted values are returned
+    /// sealed.
+    pub fn read_value<R>(&self, r: &mut R) -> Result<Vec<u8>>
+    where
+        R: Read + Seek,
+    {
+        let offset = self.offset();
+        let available = stream_len(r)?.saturating_sub(offset);
+        let value_pos = offset + (self.header.data_header_size() + self.key_sz()) as u64;
+        r.seek(SeekFrom::Start(value_pos))?;
+
+        let mut value = vec![0u8; self.value_sz()];
+        r.read_exact(&mut value)
+            .map_err(|e| short_read(e, Field::Value, offset, self.size(), available))?;
+
+        if self.header.is_compressed() && !self.header.is_encrypted() {
+            return decompress(&value);
+        }
+        Ok(value)
+    }
+
+    /// Same as `read_value`, but the whole entry is read back, it has to
+    /// pass its crc check and carry the key of the hint.
+    pub fn read_value_checked<R>(&self, r: &mut R) -> Result<Vec<u8>>
+    where
+        R: Read + Seek,
+    {
+        let offset = self.offset();
+        let entry =
+            DiskEntry::read_from_checked(r, offset)?.ok_or(LSMLibError::TruncatedEntry {
+                field: Field::Header,
+                offset,
+                needed: self.size(),
+                available: 0,
+            })?;
+
+        let (key, value) = entry.into_kv();
+        if key != self.key {
+            return Err(LSMLibError::KeyMismatch {
+                offset,
+                expected: self.key.clone(),
+                actual: key,
+            });
+        }
+        Ok(value)
+    }
+
+    /// Check the entry this hint points at in its data file `r` agrees
+    /// with it, such as for hints restored from a backup or replicated:
+    /// sizes, timestamp, seq, expiry time and key. Only the header and
+    /// key are read, a disagreement is a `HintMismatch` or `KeyMismatch`
+    /// error.
+    pub fn verify_against<R>(&self, r: &mut R) -> Result<()>
+    where
+        R: Read + Seek,
+    {
+        let offset = self.offset();
+        let header = DiskEntry::read_header(r, offset)?;
+        let mismatch = |field| Err(LSMLibError::HintMismatch { offset, field });
+
+        if header.key_sz() != self.key_sz() as u64 {
+            return mismatch("key size");
+        }
+        if header.value_sz() != self.value_sz() as u64 {
+            return mismatch("value size");
+        }
+        if header.timestamp() != self.timestamp() {
+            return mismatch("timestamp");
+        }
+        if header.seq() != self.seq() {
+            return mismatch("seq");
+        }
+        if header.expire_at() != self.expire_at() {
+            return mismatch("expiry time");
+        }
+        if header.flag_bits() != self.header.flag_bits() & !FLAG_HINT_CRC {
+            return mismatch("flags");
+        }
+
+        let mut key = vec![0u8; self.key_sz()];
         r.read_exact(&mut key)?;
+        if key != self.key {
+            return Err(LSMLibError::KeyMismatch {
+                offset,
+                expected: self.key.clone(),
+                actual: key,
+            });
+        }
+        Ok(())
+    }
+
+    /// Copy the value of the entry this hint points at from its data file
+    /// `r` to `w` without holding it in memory whole, returns the number
+    /// of bytes copied. The value is copied as stored, compressed or
+    /// sealed if the entry is. See `stream_value_checked`.
+    pub fn stream_value<R, W>(&self, r: &mut R, w: &mut W) -> Result<u64>
+    where
+        R: Read + Seek,
+        W: Write,
+    {
+        let offset = self.offset();
+        let available = stream_len(r)?.saturating_sub(offset);
+        let value_pos = offset + (self.header.data_header_size() + self.key_sz()) as u64;
+        r.seek(SeekFrom::Start(value_pos))?;
+
+        let value_sz = self.value_sz() as u64;
+        let copied = io::copy(&mut r.take(value_sz), w)?;
+        if copied < value_sz {
+            return Err(LSMLibError::TruncatedEntry {
+                field: Field::Value,
+                offset,
+                needed: self.size(),
+                available,
+            });
+        }
+        Ok(copied)
+    }
+
+    /// Same as `stream_value`, the crc checked while copying, reading the
+    /// header and key of the entry back for it. A `CrcMismatch` is only
+    /// known once the whole value is copied, `w` has to discard it then.
+    pub fn stream_value_checked<R, W>(&self, r: &mut R, w: &mut W) -> Result<u64>
+    where
+        R: Read + Seek,
+        W: Write,
+    {
+        let offset = self.offset();
+        let available = stream_len(r)?.saturating_sub(offset);
+        let header = DiskEntry::read_header(r, offset)?;
+        let needed = header.size() as u64 + header.key_sz() + header.value_sz();
+        let short = |field| move |e| short_read(e, field, offset, needed, available);
+
+        let mut key = vec![0u8; header.key_sz() as usize];
+        r.read_exact(&mut key).map_err(short(Field::Key))?;
+        if key != self.key {
+            return Err(LSMLibError::KeyMismatch {
+                offset,
+                expected: self.key.clone(),
+                actual: key,
+            });
+        }
+
+        let mut buf = vec![0u8; 64 * 1024];
+        let value_sz = header.value_sz();
+        let actual = if header.flag_bits() & FLAG_CRC64 != 0 {
+            stream_hashed::<Crc64, _, _>(&key, r, value_sz, &mut buf, w, &header)
+        } else {
+            stream_hashed::<Crc32, _, _>(&key, r, value_sz, &mut buf, w, &header)
+        };
+        let actual = match actual {
+            Err(LSMLibError::Io(e)) => return Err(short(Field::Value)(e)),
+            actual => actual?,
+        };
+        if actual != header.crc() {
+            return Err(LSMLibError::CrcMismatch {
+                key,
+                file_id: self.file_id,
+                offset: Some(offset),
+                expected: header.crc(),
+                actual,
+            });
+        }
+        Ok(value_sz)
+    }
+
+    /// Read all hints of a hint file back to back from offset 0, each
+    /// with its `file_id` set to `file_id`, the id of the data file the
+    /// hints point into.
+    pub fn load_index<R>(r: &mut R, file_id: u64) -> Result<Vec<HintEntry>>
+    where
+        R: Read + Seek,
+    {
+        HintEntryReader::new(r)
+            .map(|hint| hint.map(|(_, hint)| hint.file_id(file_id)))
+            .collect()
+    }
+
+    /// Build a hint from what was read off disk at `offset`, a hint
+    /// failing its crc check is a `CrcMismatch` error.
+    pub(super) fn decode(header: HintHeader, key: Vec<u8>, offset: u64) -> Result<Self> {
+        let hint = Self {
+            header,
+            key,
+            file_id: None,
+        };
+
+        if let Some(expected) = hint.header.crc() {
+            let actual = hint.compute_crc();
+            if actual != expected {
+                return Err(LSMLibError::CrcMismatch {
+                    key: hint.key,
+                    file_id: None,
+                    offset: Some(offset),
+                    expected: expected.into(),
+                    actual: actual.into(),
+                });
+            }
+        }
+
+        Ok(hint)
+    }
+
+    /// Crc of the header fields before the crc and of the key.
+    fn compute_crc(&self) -> u32 {
+        hash::<Crc32>(&self.header.0[..self.header.crc_pos()], &self.key, 0, &[]) as u32
+    }
+
+    /// Append the encoded hint to `buf`, filling in its crc.
+    pub(super) fn encode_into(&self, buf: &mut Vec<u8>) {
+        let start = buf.len();
+        buf.extend_from_slice(self.header.as_ref());
+        buf.extend_from_slice(&self.key);
+
+        if self.header.crc().is_some() {
+            let pos = start + self.header.crc_pos();
+            buf[pos..pos + HINT_CRC_SIZE].copy_from_slice(&self.compute_crc().to_le_bytes());
+        }
+    }
+
+    /// Same as `TryFrom<&DiskEntry>`, but moves the key out of `v`
+    /// instead of cloning it.
+    pub fn from_owned(v: DiskEntry) -> Result<Self> {
+        let offset = v.offset.ok_or(LSMLibError::MissingOffset)?;
+        Ok(Self {
+            header: HintHeader::for_entry(&v, offset),
+            file_id: v.file_id,
+            key: into_vec(v.key),
+        })
+    }
+
+    /// Hint for disk entry `v` placed at `offset`, whatever the offset
+    /// of `v` says.
+    pub fn placed_at(v: &DiskEntry, offset: u64) -> Self {
+        Self {
+            header: HintHeader::for_entry(v, offset),
+            key: v.key.to_vec(),
+            file_id: v.file_id,
+        }
+    }
+}
+
+impl Display for HintEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "HintEntry(file_id={:?}, key='{}', offset={}, size={}, timestamp={})",
+            self.file_id,
+            fmt_key(&self.key),
+            self.offset(),
+            self.size(),
+            self.datetime().to_rfc3339_opts(SecondsFormat::Secs, true),
+        )
+    }
+}
+
+/// Hints are equal when they point at the same place, their file id and
+/// offset, see `Ord`.
+impl PartialEq for HintEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for HintEntry {}
+
+impl PartialOrd for HintEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Hints order by file id, hints without one first, then by offset: the
+/// order of their entries in the data files. See `HintEntry::by_key` and
+/// `HintEntry::by_timestamp` for other orders.
+impl Ord for HintEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.file_id, self.offset()).cmp(&(other.file_id, other.offset()))
+    }
+}
+
+/// Hint for a written disk entry, one without offset is a
+/// `MissingOffset` error. See also `DiskEntry::write_and_hint`.
+impl TryFrom<&DiskEntry> for HintEntry {
+    type Error = LSMLibError;
+
+    fn try_from(v: &DiskEntry) -> Result<Self> {
+        let offset = v.offset.ok_or(LSMLibError::MissingOffset)?;
+        Ok(Self::placed_at(v, offset))
+    }
+}
+
+impl EntryIO for HintEntry {
+    type Entry = Self;
+
+    fn read_from<R>(r: &mut R, offset: u64) -> Result<Option<Self::Entry>>
+    where
+        R: Read + Seek,
+    {
+        let available = available_at(r, offset)?;
+        r.seek(SeekFrom::Start(offset))?;
+        if available == 0 {
+            return Ok(None);
+        }
+        let short =
+            |field, needed: usize| move |e| short_read(e, field, offset, needed as u64, available);
+
+        let decoder = HeaderDecoder::new(offset, available);
+        decoder.check_prefix(HINT_HEADER_SIZE)?;
+        let mut buf = [0u8; HINT_HEADER_SIZE];
+        r.read_exact(&mut buf)
+            .map_err(short(Field::Header, HINT_HEADER_SIZE))?;
+
+        let mut header = decoder.hint_prefix(buf)?;
+        let header_sz = header.size();
+        r.read_exact(header.tail_mut())
+            .map_err(short(Field::Header, header_sz))?;
+        decoder.finish_hint(&header)?;
+
+        let mut key = vec![0u8; header.key_sz()];
+        r.read_exact(&mut key)
+            .map_err(short(Field::Key, header_sz + header.key_sz()))?;
+
+        Self::decode(header, key, offset).map(Some)
+    }
+
+    fn write_to_at<W>(&self, w: &mut W, offset: u64) -> Result<(u64, u64)>
+    where
+        W: Write,
+    {
+        let mut buf = Vec::with_capacity(self.hint_size() as usize);
+        self.encode_into(&mut buf);
+        w.write_all(&buf)?;
+
+        Ok((offset, buf.len() as u64))
+    }
+}
+
+/// Sequential reader over all hints of a hint file.
+///
+/// Hints are read back to back from offset 0, each yielded with its
+/// offset in the hint file, so a single stale hint can be rewritten in
+/// place. Iteration ends at EOF, or after the first error.
+pub struct HintEntryReader<R> {
+    reader: R,
+    offset: u64,
+    done: bool,
+}
+
+impl<R> HintEntryReader<R>
+where
+    R: Read + Seek,
+{
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            offset: 0,
+            done: false,
+        }
+    }
+
+    /// Offset of the next hint to read.
+    pub fn position(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R> Iterator for HintEntryReader<R>
+where
+    R: Read + Seek,
+{
+    type Item = Result<(u64, HintEntry)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        match HintEntry::read_from(&mut self.reader, self.offset) {
+            Ok(Some(hint)) => {
+                let offset = self.offset;
+                self.offset += hint.hint_size();
+                Some(Ok((offset, hint)))
+            }
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Serialized form of a `DiskEntry`, made of its logical fields only so
+/// it doesn't depend on the header layout. Compression is not kept, and
+/// the crc is recomputed on deserialize.
+///
+/// Encrypted values are kept sealed, `value` holding the bytes as stored
+/// and `compressed` whether the sealed plaintext is compressed, so they
+/// are opened after deserialize as after a read, see `DiskEntry::decrypt`.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct DiskEntryRepr<'a> {
+    key: Cow<'a, [u8]>,
+    value: Cow<'a, [u8]>,
+    timestamp: u32,
+    #[serde(default)]
+    encrypted: bool,
+    #[serde(default)]
+    compressed: bool,
+    #[serde(default)]
+    tombstone: bool,
+    #[serde(default)]
+    seq: Option<u64>,
+    #[serde(default)]
+    expire_at: Option<u32>,
+    #[serde(default)]
+    offset: Option<u64>,
+    #[serde(default)]
+    file_id: Option<u64>,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for DiskEntry {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
+        let encrypted = self.is_encrypted();
+        let value = if encrypted {
+            self.stored_value()
+        } else {
+            &self.value[..]
+        };
+        DiskEntryRepr {
+            key: Cow::Borrowed(&self.key),
+            value: Cow::Borrowed(value),
+            timestamp: self.timestamp(),
+            encrypted,
+            compressed: encrypted && self.is_compressed(),
+            tombstone: self.is_tombstone(),
+            seq: self.seq(),
+            expire_at: self.expire_at(),
+            offset: self.offset,
+            file_id: self.file_id,
+        }
+        .serialize(s)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for DiskEntry {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+        let repr = DiskEntryRepr::deserialize(d)?;
+
+        let mut builder = DiskEntryBuilder::new(repr.key.into_owned(), repr.value.into_owned())
+            .timestamp(repr.timestamp);
+        if repr.tombstone {
+            builder = builder.tombstone();
+        }
+        if repr.encrypted {
+            builder.stored_flags = FLAG_ENCRYPTED;
+            if repr.compressed {
+                builder.stored_flags |= FLAG_COMPRESSED;
+            }
+        }
+        if let Some(seq) = repr.seq {
+            builder = builder.seq(seq);
+        }
+        if let Some(expire_at) = repr.expire_at {
+            builder = builder.expire_at(expire_at);
+        }
+
+        // whatever `DiskEntry::new` accepts round-trips, so no build checks.
+        let mut entry = builder.assemble::<DefaultChecksum>(None);
+        entry.offset = repr.offset;
+        entry.file_id = repr.file_id;
+        Ok(entry)
+    }
+}
+
+/// Serialized form of a `HintEntry`, made of its logical fields only so
+/// it doesn't depend on the header layout.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct HintEntryRepr<'a> {
+    key: Cow<'a, [u8]>,
+    offset: u64,
+    value_sz: u64,
+    timestamp: u32,
+    #[serde(default)]
+    tombstone: bool,
+    /// all flags of the entry, those of the fields below included.
+    #[serde(default)]
+    flags: u8,
+    #[serde(default)]
+    seq: Option<u64>,
+    #[serde(default)]
+    expire_at: Option<u32>,
+    #[serde(default)]
+    crc_hi: Option<u32>,
+    #[serde(default)]
+    file_id: Option<u64>,
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for HintEntry {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> std::result::Result<S::Ok, S::Error> {
+        HintEntryRepr {
+            key: Cow::Borrowed(&self.key),
+            offset: self.offset(),
+            value_sz: self.value_sz() as u64,
+            timestamp: self.timestamp(),
+            tombstone: self.is_tombstone(),
+            flags: self.header.flag_bits(),
+            seq: self.seq(),
+            expire_at: self.expire_at(),
+            crc_hi: self.header.crc_hi(),
+            file_id: self.file_id,
+        }
+        .serialize(s)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for HintEntry {
+    fn deserialize<D: serde::Deserializer<'de>>(d: D) -> std::result::Result<Self, D::Error> {
+        let repr = HintEntryRepr::deserialize(d)?;
+
+        // flags of the optional fields are set along with them.
+        let mut flags = repr.flags & !(FLAG_SEQ | FLAG_EXPIRE | FLAG_CRC64 | FLAG_HINT_CRC);
+        if repr.tombstone {
+            flags |= FLAG_TOMBSTONE;
+        }
+        let key_sz = repr.key.len() as u64;
+        let mut header = HintHeader::new(repr.offset, key_sz, repr.value_sz, repr.timestamp, flags);
+        if let Some(seq) = repr.seq {
+            header.set_seq(seq);
+        }
+        if let Some(expire_at) = repr.expire_at {
+            header.set_expire_at(expire_at);
+        }
+        if let Some(crc_hi) = repr.crc_hi {
+            header.set_crc_hi(crc_hi);
+        }
+
+        Ok(Self {
+            header,
+            key: repr.key.into_owned(),
+            file_id: repr.file_id,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disk::layout::{CRC_HI_SIZE, EXPIRE_SIZE, SEQ_SIZE};
+
+    use std::io::Cursor;
+
+    use crate::clock::FixedClock;
+
+    /// Size of the optional fields the default checksum adds to every
+    /// new entry.
+    fn crc_ext() -> usize {
+        ext_size(checksum_flags::<DefaultChecksum>())
+    }
+
+    /// Exact bytes of known entries and hints, a change here breaks every
+    /// file written so far.
+    #[test]
+    fn test_golden_layout() {
+        let entry = DiskEntryBuilder::new(b"hello".to_vec(), b"world".to_vec())
+            .timestamp(1_600_000_000)
+            .build_with::<Crc32>()
+            .unwrap();
+        #[rustfmt::skip]
+        let data = [
+            0x37, 0x49, 0x16, 0x24, // crc
+            0x00, 0x10, 0x5e, 0x5f, // timestamp
+            0x05, 0x00, 0x00,       // key_sz
+            0x00,                   // version
+            0x05, 0x00, 0x00,       // value_sz
+            0x08,                   // flags: header crc
+            b'h', b'e', b'l', b'l', b'o',
+            b'w', b'o', b'r', b'l', b'd',
+        ];
+        assert_eq!(entry.to_bytes(), data);
+        let e = DiskEntry::read_from_checked(&mut Cursor::new(&data), 0)
+            .unwrap()
+            .unwrap();
+        assert_eq!(e, entry);
+
+        let mut hint = Vec::new();
+        HintEntry::try_from(&entry.offset(0))
+            .unwrap()
+            .write_to(&mut Cursor::new(&mut hint))
+            .unwrap();
+        #[rustfmt::skip]
+        let expected = [
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // offset
+            0x05, 0x00, 0x00,       // key_sz
+            0x00,                   // version
+            0x05, 0x00, 0x00,       // value_sz
+            0x88,                   // flags: hint crc, header crc
+            0x00, 0x10, 0x5e, 0x5f, // timestamp
+            0x62, 0x50, 0x27, 0xf7, // hint crc
+            b'h', b'e', b'l', b'l', b'o',
+        ];
+        assert_eq!(hint, expected);
+
+        let entry = DiskEntryBuilder::new(b"hello".to_vec(), b"world".to_vec())
+            .timestamp(1_600_000_000)
+            .seq(7)
+            .expire_at(1_700_000_000)
+            .build_with::<Crc32>()
+            .unwrap();
+        #[rustfmt::skip]
+        let data = [
+            0xe5, 0xa7, 0x11, 0xce, // crc
+            0x00, 0x10, 0x5e, 0x5f, // timestamp
+            0x05, 0x00, 0x00,       // key_sz
+            0x00,                   // version
+            0x05, 0x00, 0x00,       // value_sz
+            0x1c,                   // flags: expire, header crc, seq
+            0x07, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // seq
+            0x00, 0xf1, 0x53, 0x65, // expire_at
+            b'h', b'e', b'l', b'l', b'o',
+            b'w', b'o', b'r', b'l', b'd',
+        ];
+        assert_eq!(entry.to_bytes(), data);
+    }
+
+    #[test]
+    fn test_disk_entry_io() {
+        let entry = DiskEntry::new(b"hello".to_vec(), b"world".to_vec());
+
+        let mut buf = Vec::new();
+        let mut cursor = Cursor::new(&mut buf);
+
+        let (offset, size) = entry.write_to(&mut cursor).unwrap();
+        assert_eq!(offset, 0);
+        assert_eq!(size, entry.size());
+
+        let entry1 = DiskEntry::read_from(&mut cursor, offset).unwrap();
+        assert!(entry1.is_some());
+
+        let e = entry1.unwrap();
+        assert_eq!(e.key, b"hello".to_vec());
+    }
+
+    #[test]
+    fn test_copy_entry() {
+        let entries = [
+            DiskEntryBuilder::new(b"a".to_vec(), b"old".to_vec())
+                .timestamp(1000)
+                .build()
+                .unwrap(),
+            DiskEntryBuilder::new(b"b".to_vec(), b"kept".to_vec())
+                .seq(7)
+                .timestamp(2000)
+                .build()
+                .unwrap(),
+        ];
+        let mut src = Vec::new();
+        let offsets = DiskEntry::write_batch(&entries, &mut Cursor::new(&mut src)).unwrap();
+
+        let mut dst = Cursor::new(b"xy".to_vec());
+        dst.seek(SeekFrom::End(0)).unwrap();
+        let copied = DiskEntry::copy_entry(&mut Cursor::new(&src), offsets[1], &mut dst).unwrap();
+        assert_eq!(copied, 2);
+        let dst = dst.into_inner();
+        assert_eq!(&dst[2..], &src[offsets[1] as usize..]);
+
+        let e = DiskEntry::read_from_checked(&mut Cursor::new(&dst), 2)
+            .unwrap()
+            .unwrap();
+        assert_eq!(e, entries[1]);
+        assert_eq!((e.seq(), e.timestamp()), (Some(7), 2000));
+
+        // a corrupted entry isn't copied.
+        let last = src.len() - 1;
+        src[last] ^= 0xFF;
+        let mut dst = Cursor::new(Vec::new());
+        assert!(matches!(
+            DiskEntry::copy_entry(&mut Cursor::new(&src), offsets[1], &mut dst),
+            Err(LSMLibError::CrcMismatch { .. })
+        ));
+        assert!(dst.into_inner().is_empty());
+    }
+
+    #[test]
+    fn test_padding() {
+        let entries = [
+            DiskEntry::new(b"a".to_vec(), b"one".to_vec()),
+            DiskEntry::new(b"b".to_vec(), b"two".to_vec()),
+        ];
+        let mut buf = Vec::new();
+        entries[0].write_to(&mut Cursor::new(&mut buf)).unwrap();
+        let pad_at = buf.len() as u64;
+        buf.resize(buf.len() + 100, 0);
+        let mut w = Cursor::new(&mut buf);
+        w.seek(SeekFrom::End(0)).unwrap();
+        let (second, _) = entries[1].write_to(&mut w).unwrap();
+        // preallocated tail.
+        buf.resize(buf.len() + 50, 0);
+
+        let mut r = Cursor::new(&buf);
+        assert!(matches!(
+            DiskEntry::read_from(&mut r, pad_at),
+            Err(LSMLibError::Padding { offset, len: 100 }) if offset == pad_at
+        ));
+        assert!(matches!(
+            DiskEntry::read_from(&mut r, pad_at + 60),
+            Err(LSMLibError::Padding { len: 40, .. })
+        ));
+        assert!(matches!(
+            DiskEntry::read_from_buf(&buf, pad_at as usize),
+            Err(LSMLibError::Padding { offset, len: 100 }) if offset == pad_at
+        ));
+        assert!(matches!(
+            DiskEntry::from_mmap(&buf, buf.len() - 50),
+            Err(LSMLibError::Padding { len: 50, .. })
+        ));
+
+        let read: Vec<_> = DiskEntryReader::new(&mut r).map(|e| e.unwrap()).collect();
+        assert_eq!(read, entries);
+        assert_eq!(read[1].offset, Some(second));
+        assert_eq!(entry_offsets(&mut r).unwrap(), vec![0, second]);
+        let report = verify_file(&mut r).unwrap();
+        assert_eq!(report.entries, 2);
+        assert!(report.is_clean());
+
+        let mut recovering = recover_entries(Cursor::new(&buf));
+        assert_eq!(recovering.by_ref().count(), 2);
+        assert_eq!(recovering.skipped(), 0);
+    }
+
+    #[test]
+    fn test_header_walker() {
+        let entries = [
+            DiskEntry::new(b"a".to_vec(), b"one".to_vec()),
+            DiskEntry::new(b"b".to_vec(), b"two".to_vec()),
+        ];
+        let mut buf = Vec::new();
+        entries[0].write_to(&mut Cursor::new(&mut buf)).unwrap();
+        buf.resize(buf.len() + 100, 0);
+        let mut w = Cursor::new(&mut buf);
+        w.seek(SeekFrom::End(0)).unwrap();
+        let (second, _) = entries[1].write_to(&mut w).unwrap();
+
+        let mut walker = HeaderWalker::new(Cursor::new(&buf));
+        let (offset, header) = walker.next().unwrap().unwrap();
+        let mut key = vec![0u8; header.key_sz() as usize];
+        walker.get_mut().read_exact(&mut key).unwrap();
+        assert_eq!((offset, key), (0, b"a".to_vec()));
+        assert_eq!(walker.next().unwrap().unwrap().0, second);
+        assert!(walker.next().is_none());
+        assert_eq!(walker.padding(), 100);
+        assert_eq!(
+            walker.end(),
+            Some(ScanEnd::Clean {
+                offset: buf.len() as u64
+            })
+        );
+
+        // a torn tail ends the walk without an error.
+        buf.truncate(buf.len() - 2);
+        let mut walker = HeaderWalker::new(Cursor::new(&buf));
+        assert_eq!(walker.by_ref().count(), 1);
+        assert!(matches!(
+            walker.end(),
+            Some(ScanEnd::TruncatedBody { offset, .. }) if offset == second
+        ));
+    }
+
+    #[test]
+    fn test_batch_size() {
+        let pairs = vec![
+            (b"hello".to_vec(), b"world".to_vec()),
+            (b"k".to_vec(), vec![b'x'; u16::MAX as usize + 1]),
+            (b"gone".to_vec(), Vec::new()),
+        ];
+        let mut data = Cursor::new(Vec::new());
+        let mut hints = Cursor::new(Vec::new());
+        for (k, v) in &pairs {
+            let e = DiskEntry::new(k.clone(), v.clone());
+            let (offset, _) = e.write_to(&mut data).unwrap();
+            HintEntry::try_from(&e.offset(offset))
+                .unwrap()
+                .write_to(&mut hints)
+                .unwrap();
+        }
+        let (data, hints) = (data.into_inner().len(), hints.into_inner().len());
+
+        assert_eq!(DiskEntry::batch_size(&pairs), data as u64);
+        assert_eq!(
+            DiskEntry::batch_size_with_hints(&pairs),
+            (data as u64, hints as u64)
+        );
+        assert_eq!(DiskEntry::batch_size(&[]), 0);
+    }
+
+    #[test]
+    fn test_read_at() {
+        let entries: Vec<_> = (0..3u8)
+            .map(|i| DiskEntry::new(vec![b'k', i], vec![i; 5 * i as usize]))
+            .collect();
+        let mut buf = Vec::new();
+        let offsets = DiskEntry::write_batch(&entries, &mut Cursor::new(&mut buf)).unwrap();
+
+        let mut r = Cursor::new(&buf);
+        let mut offset = 0;
+        let mut read = Vec::new();
+        while let Some((entry, next)) = DiskEntry::read_at(&mut r, offset).unwrap() {
+            assert_eq!(entry.offset, Some(offset));
+            assert_eq!(next, offset + entry.size());
+            read.push(entry);
+            offset = next;
+        }
+        assert_eq!(read, entries);
+        assert_eq!(offset, buf.len() as u64);
+        assert_eq!(read[2].offset, Some(offsets[2]));
+    }
+
+    #[test]
+    fn test_read_from_file() {
+        let entry = DiskEntry::new(b"hello".to_vec(), b"world".to_vec());
+        let mut cursor = Cursor::new(Vec::new());
+        entry.write_to(&mut cursor).unwrap();
+
+        let e = DiskEntry::read_from_file(&mut cursor, 0, 3)
+            .unwrap()
+            .unwrap();
+        assert_eq!(e, entry);
+        assert_eq!((e.offset, e.file_id), (Some(0), Some(3)));
+        assert!(e.to_string().starts_with("DiskEntry(file_id=Some(3), "));
+        assert!(DiskEntry::read_from_file(&mut cursor, e.size(), 3)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_read_sets_offset() {
+        let entries: Vec<_> = (0..4u8)
+            .map(|i| DiskEntry::with_seq(vec![b'k', i], vec![i; 10 * i as usize], i as u64))
+            .collect();
+        let mut cursor = Cursor::new(Vec::new());
+        let offsets: Vec<_> = entries
+            .iter()
+            .map(|e| e.write_to(&mut cursor).unwrap().0)
+            .collect();
+
+        for (entry, &offset) in entries.iter().zip(&offsets) {
+            let e = DiskEntry::read_from(&mut cursor, offset).unwrap().unwrap();
+            assert_eq!(&e, entry);
+            assert_eq!(e.offset, Some(offset));
+            let e = DiskEntry::read_from_checked(&mut cursor, offset)
+                .unwrap()
+                .unwrap();
+            assert_eq!(e.offset, Some(offset));
+        }
+
+        let read: Vec<_> = DiskEntryReader::new(&mut cursor)
+            .map(|e| e.unwrap().offset)
+            .collect();
+        let expected: Vec<_> = offsets.iter().copied().map(Some).collect();
+        assert_eq!(read, expected);
+    }
+
+    #[test]
+    fn test_crc_check() {
+        let mut entry = DiskEntry::new(b"hello".to_vec(), b"world".to_vec());
+
+        assert!(entry.is_validate());
+
+        entry.value = EntryBuf::from(&b"hello"[..]);
+        assert!(!entry.is_validate());
+    }
+
+    #[derive(Default)]
+    struct Adler(u32, u32);
+
+    impl Checksum for Adler {
+        fn update(&mut self, bytes: &[u8]) {
+            for b in bytes {
+                self.0 = (self.0 + *b as u32) % 65521;
+                self.1 = (self.1 + self.0) % 65521;
+            }
+        }
+
+        fn finalize(self) -> u64 {
+            ((self.1 << 16) | (self.0 + 1)) as u64
+        }
+    }
+
+    #[test]
+    fn test_custom_checksum() {
+        let entry = DiskEntry::with_checksum::<Adler>(b"hello".to_vec(), b"world".to_vec());
+        assert!(entry.is_validate_with::<Adler>());
+        assert!(!entry.is_validate());
+
+        let entry = DiskEntry::with_checksum::<Crc32>(b"hello".to_vec(), b"world".to_vec());
+        assert!(entry.is_validate_with::<Crc32>());
+        assert!(!entry.is_validate_with::<Adler>());
+    }
+
+    #[test]
+    fn test_crc64_entries() {
+        let narrow = DiskEntry::with_checksum::<Crc32>(b"a".to_vec(), b"1".to_vec());
+        let wide = DiskEntryBuilder::new(b"b".to_vec(), b"22".to_vec())
+            .seq(7)
+            .expire_at(u32::MAX)
+            .build_with::<Crc64>()
+            .unwrap();
+        assert!(wide.crc() > u32::MAX as u64);
+        assert_eq!(
+            wide.size(),
+            narrow.size() + 1 + (SEQ_SIZE + EXPIRE_SIZE + CRC_HI_SIZE) as u64
+        );
+        assert_eq!(wide.seq(), Some(7));
+        assert_eq!(wide.expire_at(), Some(u32::MAX));
+
+        // both read back and validate whatever the default checksum.
+        let mut buf = Vec::new();
+        let offsets =
+            DiskEntry::write_batch(&[narrow.clone(), wide.clone()], &mut Cursor::new(&mut buf))
+                .unwrap();
+        let entries: Vec<_> = DiskEntryReader::new(Cursor::new(&buf))
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(
+            entries,
+            vec![narrow.clone().offset(0), wide.clone().offset(offsets[1])]
+        );
+        assert!(entries.iter().all(|e| e.is_validate_with::<Crc32>()));
+        assert!(verify_file(&mut Cursor::new(&buf)).unwrap().is_clean());
+
+        let hint = HintEntry::try_from(&entries[1]).unwrap();
+        assert_eq!(hint.size(), wide.size());
+        assert_eq!(
+            hint.read_value_checked(&mut Cursor::new(&buf)).unwrap(),
+            b"22"
+        );
+
+        // the upper half of the crc is checked too.
+        let hi_pos = offsets[1] as usize + layout::crc_hi_pos(HEADER_SIZE, wide.header.flag_bits());
+        buf[hi_pos + 3] ^= 0x80;
+        let e = DiskEntry::read_from(&mut Cursor::new(&buf), offsets[1])
+            .unwrap()
+            .unwrap();
+        assert!(!e.is_validate());
+        assert_eq!(
+            verify_file(&mut Cursor::new(&buf)).unwrap().crc_failures,
+            vec![offsets[1]]
+        );
+    }
+
+    #[test]
+    fn test_read_from_checked() {
+        let entry = DiskEntry::new(b"hello".to_vec(), b"world".to_vec());
+
+        let mut buf = Vec::new();
+        entry.write_to(&mut Cursor::new(&mut buf)).unwrap();
+
+        let e = DiskEntry::read_from_checked(&mut Cursor::new(&buf), 0).unwrap();
+        assert_eq!(e.unwrap().value, b"world".to_vec());
+
+        let last = buf.len() - 1;
+        buf[last] = b'x';
+        let err = DiskEntry::read_from_checked(&mut Cursor::new(&buf), 0).unwrap_err();
+        assert!(matches!(
+            err,
+            LSMLibError::CrcMismatch { ref key, offset: Some(0), expected, actual, .. }
+                if key == b"hello" && expected == entry.crc() && actual != expected
+        ));
+
+        // the unchecked path still hands the entry out.
+        let e = DiskEntry::read_from(&mut Cursor::new(&buf), 0).unwrap();
+        assert!(!e.unwrap().is_validate());
+    }
+
+    #[test]
+    fn test_tombstone() {
+        let entry = DiskEntry::tombstone(b"hello".to_vec());
+        assert!(entry.is_tombstone());
+        assert!(entry.is_validate());
+
+        // an empty value is not a deletion.
+        let empty = DiskEntry::new(b"hello".to_vec(), Vec::new());
+        assert!(!empty.is_tombstone());
+        assert_ne!(entry.crc(), empty.crc());
+
+        let mut buf = Vec::new();
+        let mut cursor = Cursor::new(&mut buf);
+
+        let (offset, _) = entry.write_to(&mut cursor).unwrap();
+        let e = DiskEntry::read_from(&mut cursor, offset).unwrap().unwrap();
+        assert!(e.is_tombstone());
+        assert!(e.is_validate());
+        assert!(e.value.is_empty());
+
+        let hint = HintEntry::try_from(&e.offset(offset)).unwrap();
+        assert!(hint.is_tombstone());
+
+        // flipping the flag must be caught by the crc.
+        buf[FLAGS_POS] = 0;
+        let e = DiskEntry::read_from(&mut Cursor::new(&buf), 0)
+            .unwrap()
+            .unwrap();
+        assert!(!e.is_tombstone());
+        assert!(!e.is_validate());
+    }
+
+    #[test]
+    fn test_compressed_entry_io() {
+        let value = b"abcd".repeat(1024);
+        let entry =
+            DiskEntry::new_compressed(b"hello".to_vec(), value.clone(), Codec::Zstd(3)).unwrap();
+        assert!(entry.is_compressed());
+        assert!(entry.is_validate());
+        assert_eq!(entry.value, value);
+        assert!(entry.size() < (HEADER_SIZE + 5 + value.len()) as u64);
+
+        let mut buf = Vec::new();
+        let mut cursor = Cursor::new(&mut buf);
+        let (offset, size) = entry.write_to(&mut cursor).unwrap();
+        assert_eq!(size, entry.size());
+        assert_eq!(cursor.get_ref().len() as u64, size);
+
+        let e = DiskEntry::read_from_checked(&mut cursor, offset)
+            .unwrap()
+            .unwrap();
+        assert!(e.is_compressed());
+        assert_eq!(e.value, value);
+        assert_eq!(e.size(), size);
+
+        let h = HintEntry::try_from(&e.offset(offset)).unwrap();
+        assert_eq!(h.size(), size);
+
+        // zero-copy reads hand out the stored bytes.
+        let r = DiskEntry::read_from_buf(&buf, 0).unwrap().unwrap();
+        assert!(r.is_validate());
+        assert_ne!(r.value, &value[..]);
+        assert_eq!(r.to_entry().value, value);
+    }
+
+    #[test]
+    fn test_disk_entry_builder() {
+        let entry = DiskEntry::builder(b"hello".to_vec(), b"world".to_vec())
+            .timestamp(1_600_000_000)
+            .seq(7)
+            .build()
+            .unwrap();
+        assert_eq!(entry.timestamp(), 1_600_000_000);
+        assert_eq!(entry.seq(), Some(7));
+        assert!(entry.is_validate());
+
+        let mut buf = Vec::new();
+        let mut cursor = Cursor::new(&mut buf);
+        let (offset, _) = entry.write_to(&mut cursor).unwrap();
+        let e = DiskEntry::read_from(&mut cursor, offset).unwrap().unwrap();
+        assert_eq!(e.timestamp(), 1_600_000_000);
+
+        // a replicated entry keeps the crc it was sent with.
+        let replica = DiskEntry::builder(e.key.clone(), e.value.clone())
+            .timestamp(e.timestamp())
+            .seq(7)
+            .crc(e.crc())
+            .build()
+            .unwrap();
+        assert_eq!(replica.crc(), entry.crc());
+        assert!(replica.is_validate());
+
+        let bad = DiskEntry::builder(b"hello".to_vec(), b"world".to_vec())
+            .crc(0xdead_beef)
+            .build()
+            .unwrap();
+        assert_eq!(bad.crc(), 0xdead_beef);
+        assert!(!bad.is_validate());
+    }
+
+    #[test]
+    fn test_header_crc() {
+        // shift one byte from key to value, the concatenation stays the
+        // same so only a crc over the sizes notices.
+        let shift = |buf: &mut Vec<u8>| {
+            buf[8] -= 1;
+            buf[12] += 1;
+        };
+
+        let legacy = Header::new(hash::<Crc32>(b"hello", b"world", 0, &[]), 0, 5, 5, 0);
+        let mut buf = legacy.as_ref().to_vec();
+        buf.extend_from_slice(b"helloworld");
+        shift(&mut buf);
+        let e = DiskEntry::read_from(&mut Cursor::new(&buf), 0)
+            .unwrap()
+            .unwrap();
+        assert_eq!(e.key, b"hell".to_vec());
+        assert!(e.is_validate());
+
+        let entry = DiskEntry::new(b"hello".to_vec(), b"world".to_vec());
+        let mut buf = Vec::new();
+        entry.write_to(&mut Cursor::new(&mut buf)).unwrap();
+        shift(&mut buf);
+        let e = DiskEntry::read_from(&mut Cursor::new(&buf), 0)
+            .unwrap()
+            .unwrap();
+        assert_eq!(e.key, b"hell".to_vec());
+        assert!(!e.is_validate());
+
+        // the timestamp is covered too.
+        let mut buf = Vec::new();
+        entry.write_to(&mut Cursor::new(&mut buf)).unwrap();
+        buf[4] ^= 1;
+        let e = DiskEntry::read_from(&mut Cursor::new(&buf), 0)
+            .unwrap()
+            .unwrap();
+        assert!(!e.is_validate());
+    }
+
+    #[test]
+    fn test_read_next() {
+        let entries = vec![
+            DiskEntry::new(b"a".to_vec(), b"1".to_vec()),
+            DiskEntry::new(b"bb".to_vec(), b"22".to_vec()),
+        ];
+
+        let mut buf = Vec::new();
+        let offsets = DiskEntry::write_batch(&entries, &mut Cursor::new(&mut buf)).unwrap();
+
+        let mut cursor = Cursor::new(&buf);
+        for (entry, offset) in entries.iter().zip(offsets.iter().copied()) {
+            let e = DiskEntry::read_next(&mut cursor).unwrap().unwrap();
+            assert_eq!(e.offset, Some(offset));
+            assert_eq!(e.key, entry.key);
+            assert_eq!(e.value, entry.value);
+            assert_eq!(cursor.position(), offset + e.size());
+        }
+        assert!(DiskEntry::read_next(&mut cursor).unwrap().is_none());
+
+        buf.pop();
+        let mut cursor = Cursor::new(&buf);
+        DiskEntry::read_next(&mut cursor).unwrap().unwrap();
+        assert!(matches!(
+            DiskEntry::read_next(&mut cursor),
+            Err(LSMLibError::TruncatedEntry {
+                field: Field::Value,
+                needed,
+                available,
+                ..
+            }) if needed == 20 + crc_ext() as u64 && available == needed - 1
+        ));
+
+        // a header cut short is not read as a whole one.
+        let mut cursor = Cursor::new(&buf[..offsets[1] as usize + 5]);
+        DiskEntry::read_next(&mut cursor).unwrap().unwrap();
+        assert!(matches!(
+            DiskEntry::read_next(&mut cursor),
+            Err(LSMLibError::TruncatedEntry {
+                field: Field::Header,
+                available: 5,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_write_to_aligned() {
+        let entries = vec![
+            DiskEntry::new(b"a".to_vec(), b"1".to_vec()),
+            DiskEntry::new(b"b".to_vec(), vec![7u8; 5000]),
+            DiskEntry::tombstone(b"c".to_vec()),
+        ];
+
+        let mut cursor = Cursor::new(Vec::new());
+        for entry in &entries {
+            let (offset, written) = entry.write_to_aligned(&mut cursor, 4096).unwrap();
+            assert_eq!(offset % 4096, 0);
+            assert_eq!(written, align_up(entry.size(), 4096));
+        }
+
+        // a zeroed block in between is skipped like padding.
+        let mut buf = cursor.into_inner();
+        buf.splice(4096..4096, vec![0u8; 4096]);
+
+        let read: Vec<_> = DiskEntryReader::new(Cursor::new(buf))
+            .aligned(4096)
+            .map(|e| e.unwrap())
+            .collect();
+        assert_eq!(read, entries);
+        let offsets: Vec<_> = read.iter().map(|e| e.offset.unwrap()).collect();
+        assert_eq!(offsets, vec![0, 8192, 16384]);
+    }
+
+    #[test]
+    fn test_frames() {
+        let entries = vec![
+            DiskEntry::new(b"a".to_vec(), b"1".to_vec()),
+            DiskEntry::with_seq(b"b".to_vec(), b"22".to_vec(), 3),
+        ];
+
+        let mut frame = Vec::new();
+        entries[0].write_frame(&mut frame).unwrap();
+
+        // the receiver joins midway through a frame, then gets two.
+        let mut stream = b"garbage".to_vec();
+        stream.extend_from_slice(&frame[5..]);
+        for entry in &entries {
+            let written = entry.write_frame(&mut stream).unwrap();
+            assert_eq!(written, FRAME_PREFIX_SIZE as u64 + entry.size());
+        }
+
+        let mut r = stream.as_slice();
+        for entry in &entries {
+            let e = DiskEntry::read_frame(&mut r).unwrap().unwrap();
+            assert_eq!(&e, entry);
+            assert_eq!(e.seq(), entry.seq());
+            assert_eq!(e.offset, None);
+        }
+        assert!(DiskEntry::read_frame(&mut r).unwrap().is_none());
+
+        // a frame cut short is an error, not a silent end.
+        let mut r = &frame[..frame.len() - 1];
+        assert!(DiskEntry::read_frame(&mut r).is_err());
+    }
+
+    #[test]
+    fn test_size_limits() {
+        let opts = WriteOptions::default().max_key_size(4).max_value_size(8);
+
+        let e = DiskEntry::new_with_options(b"key".to_vec(), b"value".to_vec(), &opts).unwrap();
+        assert!(e.is_validate());
+        assert!(matches!(
+            DiskEntry::new_with_options(b"hello".to_vec(), b"value".to_vec(), &opts),
+            Err(LSMLibError::KeyIsTooLarge { size: 5, limit: 4 })
+        ));
+        assert!(matches!(
+            DiskEntry::new_with_options(b"key".to_vec(), b"too large".to_vec(), &opts),
+            Err(LSMLibError::ValueIsTooLarge { size: 9, limit: 8 })
+        ));
+
+        // the limit applies before compression.
+        let compressed = opts.compressed(Codec::Zstd(3));
+        assert!(matches!(
+            DiskEntry::new_with_options(b"key".to_vec(), b"a".repeat(64), &compressed),
+            Err(LSMLibError::ValueIsTooLarge { size: 64, limit: 8 })
+        ));
+        let e =
+            DiskEntry::new_with_options(b"key".to_vec(), b"aaaa".to_vec(), &compressed).unwrap();
+        assert!(e.is_compressed());
+
+        let unlimited = WriteOptions::default();
+        assert!(
+            DiskEntry::new_with_options(b"k".repeat(1024), b"v".repeat(1024), &unlimited).is_ok()
+        );
+    }
+
+    #[test]
+    fn test_last_entries() {
+        let mut buf = Vec::new();
+        let mut w = Cursor::new(&mut buf);
+        let mut offsets = Vec::new();
+        for i in 0..5u8 {
+            let (offset, _) = DiskEntry::new(vec![b'k', i], vec![i; 10])
+                .write_to(&mut w)
+                .unwrap();
+            offsets.push(offset);
+        }
+        let end = buf.len();
+        // torn tail.
+        buf.extend_from_slice(&[1, 2, 3]);
+
+        let mut r = Cursor::new(&buf);
+        assert_eq!(entry_offsets(&mut r).unwrap(), offsets);
+
+        let last = last_entries(&mut r, 2).unwrap();
+        let keys: Vec<_> = last.iter().map(|e| e.key.to_vec()).collect();
+        assert_eq!(keys, vec![vec![b'k', 3], vec![b'k', 4]]);
+        assert_eq!(last[1].offset, Some(offsets[4]));
+
+        assert_eq!(last_entries(&mut r, 10).unwrap().len(), 5);
+        assert!(last_entries(&mut r, 0).unwrap().is_empty());
+        assert!(last_entries(&mut Cursor::new(&buf[..end]), 1).is_ok());
+        assert!(last_entries(&mut Cursor::new(Vec::new()), 3)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[cfg(all(feature = "serde", feature = "encryption"))]
+    #[test]
+    fn test_serde_encrypted() {
+        use crate::disk::cipher::Cipher;
+
+        let cipher = Cipher::new(&[7u8; 32]);
+        let e = DiskEntryBuilder::new(b"key".to_vec(), b"a".repeat(64))
+            .compressed(Codec::Zstd(3))
+            .encrypted(&cipher)
+            .build()
+            .unwrap();
+        let mut cursor = Cursor::new(Vec::new());
+        e.write_to(&mut cursor).unwrap();
+        let sealed = DiskEntry::read_from_checked(&mut cursor, 0)
+            .unwrap()
+            .unwrap();
+
+        // read back from disk or just built, the value is serialized sealed.
+        for entry in [sealed.clone(), e] {
+            let json = serde_json::to_string(&entry).unwrap();
+            assert!(!json.contains(&"97,".repeat(8)));
+            let d: DiskEntry = serde_json::from_str(&json).unwrap();
+            assert_eq!(d, sealed);
+            assert!(d.is_encrypted() && d.is_compressed() && d.is_validate());
+            assert_eq!(d.decrypt(&cipher).unwrap().value, b"a".repeat(64));
+        }
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_encrypted_entry() {
+        use crate::disk::cipher::{Cipher, NONCE_SIZE, TAG_SIZE};
+
+        let cipher = Cipher::new(&[7u8; 32]);
+        let e = DiskEntry::new_encrypted(b"key".to_vec(), b"secret".to_vec(), &cipher).unwrap();
+        assert!(e.is_encrypted() && !e.is_compressed());
+        assert!(e.is_validate());
+        assert_eq!(&e.value[..], b"secret");
+        assert_eq!(
+            e.header.value_sz() as usize,
+            NONCE_SIZE + b"secret".len() + TAG_SIZE
+        );
+
+        let mut buf = Vec::new();
+        let (offset, _) = e.write_to(&mut Cursor::new(&mut buf)).unwrap();
+        assert!(buf.windows(3).any(|w| w == b"key"));
+        assert!(!buf.windows(6).any(|w| w == b"secret"));
+
+        // read back sealed, until decrypted.
+        let mut r = Cursor::new(&buf);
+        let sealed = DiskEntry::read_from_checked(&mut r, offset)
+            .unwrap()
+            .unwrap();
+        assert!(sealed.is_encrypted());
+        assert_ne!(&sealed.value[..], b"secret");
+        let opened = sealed.clone().decrypt(&cipher).unwrap();
+        assert_eq!(&opened.value[..], b"secret");
+        assert!(opened.is_validate());
+        let e = DiskEntry::read_from_encrypted(&mut r, offset, &cipher)
+            .unwrap()
+            .unwrap();
+        assert_eq!(&e.value[..], b"secret");
+
+        // wrong cipher.
+        assert!(matches!(
+            sealed.decrypt(&Cipher::new(&[8u8; 32])),
+            Err(LSMLibError::DecryptionFailed { .. })
+        ));
+
+        // compressed, then sealed.
+        let e = DiskEntryBuilder::new(b"key".to_vec(), b"a".repeat(64))
+            .compressed(Codec::Zstd(3))
+            .encrypted(&cipher)
+            .build()
+            .unwrap();
+        assert!(e.is_encrypted() && e.is_compressed());
+        let mut buf = Vec::new();
+        e.write_to(&mut Cursor::new(&mut buf)).unwrap();
+        let e = DiskEntry::read_from_encrypted(&mut Cursor::new(&buf), 0, &cipher)
+            .unwrap()
+            .unwrap();
+        assert_eq!(&e.value[..], b"a".repeat(64));
+    }
+
+    #[test]
+    fn test_estimate_on_disk_size() {
+        let (k, v) = (b"hello".as_slice(), vec![b'x'; 3000]);
+        let opts = WriteOptions::default();
+        assert_eq!(
+            DiskEntry::estimate_on_disk_size(k, &v, &opts).unwrap(),
+            DiskEntry::entry_size(k, &v)
+        );
+
+        let opts = WriteOptions::default().compressed(Codec::Zstd(3)).seq();
+        let entry = DiskEntryBuilder::new(k.to_vec(), v.clone())
+            .compressed(Codec::Zstd(3))
+            .seq(1)
+            .build()
+            .unwrap();
+        let mut buf = Vec::new();
+        let (_, written) = entry.write_to(&mut Cursor::new(&mut buf)).unwrap();
+        assert_eq!(
+            DiskEntry::estimate_on_disk_size(k, &v, &opts).unwrap(),
+            written
+        );
+
+        let opts = opts.aligned(4096);
+        let (_, written) = entry
+            .write_to_aligned(&mut Cursor::new(Vec::new()), 4096)
+            .unwrap();
+        assert_eq!(
+            DiskEntry::estimate_on_disk_size(k, &v, &opts).unwrap(),
+            written
+        );
+    }
+
+    #[test]
+    fn test_byte_order() {
+        let header = Header::new(0x0403_0201, 0x0807_0605, 0x0B_0A09, 0x0E_0D0C, 0);
+        assert_eq!(
+            header.as_ref(),
+            &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 0, 12, 13, 14, 0]
+        );
+
+        let hint = HintHeader::new(0x0807_0605_0403_0201, 0x0B_0A09, 0x0E_0D0C, 0x1413_1211, 0);
+        assert_eq!(
+            &hint.as_ref()[..HINT_HEADER_SIZE],
+            &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 0, 12, 13, 14, 0x80, 0x11, 0x12, 0x13, 0x14]
+        );
+    }
+
+    #[test]
+    fn test_crc_key_boundary() {
+        let entry = |k: &[u8], v: &[u8]| {
+            DiskEntryBuilder::new(k.to_vec(), v.to_vec())
+                .timestamp(1)
+                .build()
+                .unwrap()
+        };
+        let (a, b) = (entry(b"ab", b"c"), entry(b"a", b"bc"));
+        assert!(a.flags().contains(EntryFlags::HEADER_CRC));
+        assert_ne!(a.crc(), b.crc());
+
+        // without the header crc flag, as written by older versions.
+        assert_eq!(Crc32::compute(b"ab", b"c"), Crc32::compute(b"a", b"bc"));
+    }
+
+    #[test]
+    fn test_entry_flags() {
+        let mut cursor = Cursor::new(Vec::new());
+        let entry = DiskEntry::tombstone_with_seq(b"key".to_vec(), 3);
+        entry.write_to(&mut cursor).unwrap();
+
+        let read = DiskEntry::read_from(&mut cursor, 0).unwrap().unwrap();
+        assert_eq!(read.flags(), entry.flags());
+        assert!(read
+            .flags()
+            .contains(EntryFlags::TOMBSTONE | EntryFlags::SEQ | EntryFlags::HEADER_CRC));
+        assert!(!read.flags().contains(EntryFlags::COMPRESSED));
+
+        // flipping an attribute fails the crc check.
+        cursor.get_mut()[FLAGS_POS] ^= EntryFlags::TOMBSTONE.bits();
+        let flipped = DiskEntry::read_from(&mut cursor, 0).unwrap().unwrap();
+        assert!(!flipped.is_tombstone());
+        assert!(flipped.verify().is_err());
+
+        let header = Header::new(0, 1, 3, 0, FLAG_HEADER_CRC)
+            .with_flag(EntryFlags::TOMBSTONE | EntryFlags::SEQ);
+        assert_eq!(
+            header.flags(),
+            EntryFlags::TOMBSTONE | EntryFlags::HEADER_CRC
+        );
+        assert_eq!(header.size(), HEADER_SIZE);
+
+        // a baseline header has no room for flags.
+        let baseline = Header::new(0, 1, 3, 5, 0).with_flag(EntryFlags::TOMBSTONE);
+        assert_eq!((baseline.flags(), baseline.value_sz()), (EntryFlags(0), 5));
+    }
+
+    #[test]
+    fn test_repair_crcs() {
+        let mut cursor = Cursor::new(Vec::new());
+        let offsets: Vec<_> = (0..4u64)
+            .map(|i| {
+                let entry =
+                    DiskEntry::with_seq(format!("key{}", i).into_bytes(), vec![i as u8; 8], i);
+                entry.write_to(&mut cursor).unwrap().0
+            })
+            .collect();
+        cursor.write_all(&[0u8; 32]).unwrap();
+        let original = cursor.get_ref().clone();
+
+        // stale crcs on the 2nd and 4th entries.
+        for &offset in &[offsets[1], offsets[3]] {
+            cursor.get_mut()[offset as usize] ^= 0xFF;
+        }
+        let mut repaired = Cursor::new(Vec::new());
+        assert_eq!(repair_crcs(&mut cursor, &mut repaired).unwrap(), 2);
+        assert_eq!(repaired.get_ref(), &original);
+
+        let mut again = Cursor::new(Vec::new());
+        assert_eq!(repair_crcs(&mut repaired, &mut again).unwrap(), 0);
+        assert_eq!(again.get_ref(), &original);
+        assert!(verify_file(&mut again).unwrap().crc_failures.is_empty());
+    }
+
+    #[test]
+    fn test_small_entries() {
+        let mut cursor = Cursor::new(Vec::new());
+        let small = DiskEntry::with_seq(b"key".to_vec(), b"value".to_vec(), 1);
+        let large = DiskEntry::new(b"large".to_vec(), vec![1u8; SMALL_ENTRY_SIZE]);
+        let (offset, size) = small.write_small(&mut cursor, 0).unwrap();
+        assert_eq!((offset, size), (0, small.size()));
+        large.write_small(&mut cursor, size).unwrap();
+        small.write_small(&mut cursor, size + large.size()).unwrap();
+        assert_eq!(cursor.get_ref()[..size as usize], small.to_bytes()[..]);
+
+        let mut buf = [0u8; SMALL_ENTRY_SIZE];
+        let entry = DiskEntry::read_small(&mut cursor, 0, &mut buf)
+            .unwrap()
+            .unwrap();
+        assert_eq!((entry.key, entry.value), (&b"key"[..], &b"value"[..]));
+        assert!(entry.is_validate());
+        assert!(matches!(
+            DiskEntry::read_small(&mut cursor, size, &mut buf),
+            Err(LSMLibError::NotSmallEntry { .. })
+        ));
+
+        let last = size + large.size();
+        let entry = DiskEntry::read_small(&mut cursor, last, &mut buf)
+            .unwrap()
+            .unwrap();
+        assert_eq!((entry.offset, entry.to_entry().seq()), (last, Some(1)));
+
+        let end = cursor.get_ref().len() as u64;
+        assert!(DiskEntry::read_small(&mut cursor, end, &mut buf)
+            .unwrap()
+            .is_none());
+        cursor.get_mut().truncate(end as usize - 2);
+        assert!(matches!(
+            DiskEntry::read_small(&mut cursor, last, &mut buf),
+            Err(LSMLibError::TruncatedEntry { offset, .. }) if offset == last
+        ));
+    }
+
+    #[test]
+    fn test_write_and_hint() {
+        let mut cursor = Cursor::new(Vec::new());
+        DiskEntry::new(b"first".to_vec(), b"1".to_vec())
+            .write_to(&mut cursor)
+            .unwrap();
+        let entry = DiskEntry::with_seq(b"key".to_vec(), b"value".to_vec(), 9).file_id(4);
+        let (offset, hint) = entry.write_and_hint(&mut cursor).unwrap();
+
+        assert!(offset > 0);
+        assert_eq!((hint.offset(), hint.file_id), (offset, Some(4)));
+        assert_eq!((hint.key.as_slice(), hint.seq()), (&b"key"[..], Some(9)));
+        assert_eq!(hint.read_value(&mut cursor).unwrap(), b"value");
+        hint.verify_against(&mut cursor).unwrap();
+    }
+
+    #[test]
+    fn test_file_stats() {
+        let mut cursor = Cursor::new(Vec::new());
+        let entries = [
+            DiskEntry::new(b"a".to_vec(), vec![0u8; 1]),
+            DiskEntry::new(b"bb".to_vec(), vec![0u8; 3]),
+            DiskEntry::new(b"ccc".to_vec(), vec![0u8; 1000]),
+            DiskEntry::tombstone(b"dddd".to_vec()),
+        ];
+        for entry in &entries {
+            entry.write_to(&mut cursor).unwrap();
+        }
+
+        let stats = file_stats(&mut cursor).unwrap();
+        assert_eq!((stats.entries, stats.tombstones), (4, 1));
+        assert_eq!(stats.bytes, cursor.get_ref().len() as u64);
+        assert_eq!((stats.key_bytes, stats.value_bytes), (10, 1004));
+        assert_eq!((stats.max_key_sz, stats.max_value_sz), (4, 1000));
+        assert_eq!(stats.avg_key_sz(), 2.5);
+        assert_eq!(stats.value_histogram.len(), 11);
+        assert_eq!(stats.value_histogram[..3], [1, 1, 1]);
+        assert_eq!(stats.value_histogram[10], 1);
+
+        assert_eq!(
+            file_stats(&mut Cursor::new(Vec::new())).unwrap(),
+            FileStats::default()
+        );
+    }
+
+    #[test]
+    fn test_keys_only() {
+        let mut cursor = Cursor::new(Vec::new());
+        DiskEntry::new(b"a".to_vec(), vec![1u8; 100_000])
+            .write_to(&mut cursor)
+            .unwrap();
+        cursor.write_all(&[0u8; 40]).unwrap();
+        DiskEntry::tombstone(b"b".to_vec())
+            .write_to(&mut cursor)
+            .unwrap();
+        let end = cursor.get_ref().len() as u64;
+
+        let mut keys = keys_only(&mut cursor);
+        let all: Vec<_> = keys.by_ref().map(|k| k.unwrap()).collect();
+        assert_eq!(all, vec![b"a".to_vec(), b"b".to_vec()]);
+        assert_eq!(keys.end(), Some(ScanEnd::Clean { offset: end }));
+
+        // torn tail.
+        let torn = DiskEntry::new(b"c".to_vec(), b"3".to_vec()).to_bytes();
+        cursor.get_mut().extend_from_slice(&torn[..torn.len() - 1]);
+        let mut keys = keys_only(&mut cursor);
+        assert_eq!(keys.by_ref().count(), 2);
+        assert!(matches!(keys.end(), Some(ScanEnd::TruncatedBody { offset, .. }) if offset == end));
+    }
+
+    #[test]
+    fn test_dead_bytes() {
+        let entries = [
+            DiskEntry::new(b"a".to_vec(), b"1".to_vec()),
+            DiskEntry::new(b"b".to_vec(), b"2".to_vec()),
+            DiskEntry::new(b"a".to_vec(), b"3".to_vec()),
+            DiskEntry::tombstone(b"b".to_vec()),
+        ];
+        let mut cursor = Cursor::new(Vec::new());
+        let offsets: Vec<_> = entries
+            .iter()
+            .map(|e| e.write_to(&mut cursor).unwrap().0)
+            .collect();
+        cursor.write_all(&[0u8; 40]).unwrap();
+
+        let live = HashSet::from([(b"a".to_vec(), offsets[2])]);
+        let dead = entries[0].size() + entries[1].size() + entries[3].size() + 40;
+        assert_eq!(dead_bytes(&mut cursor, &live).unwrap(), dead);
+        assert_eq!(
+            dead_bytes(&mut cursor, &HashSet::new()).unwrap(),
+            cursor.get_ref().len() as u64
+        );
+    }
+
+    #[test]
+    fn test_sequential_scanner() {
+        let mut cursor = Cursor::new(Vec::new());
+        for i in 0..50u64 {
+            // a fixed timestamp keeps crcs starting with a zero byte,
+            // taken for padding, away from entries following padding.
+            let value = vec![i as u8; (i * 7) as usize];
+            DiskEntryBuilder::new(format!("key{}", i).into_bytes(), value)
+                .seq(i)
+                .timestamp(1)
+                .build()
+                .unwrap()
+                .write_to(&mut cursor)
+                .unwrap();
+            if i % 10 == 3 {
+                cursor.write_all(&vec![0u8; 40 + i as usize]).unwrap();
+            }
+        }
+        DiskEntry::new(b"big".to_vec(), vec![9u8; 1000])
+            .write_to(&mut cursor)
+            .unwrap();
+        let mut data = cursor.into_inner();
+        data.extend_from_slice(&DiskEntry::new(b"torn".to_vec(), b"x".to_vec()).to_bytes()[..10]);
+
+        let mut reader = DiskEntryReader::new(Cursor::new(data.clone()));
+        let expected: Vec<_> = reader.by_ref().map(|e| e.unwrap()).collect();
+        assert_eq!(expected.len(), 51);
+
+        // blocks smaller than some entries and padding runs.
+        for block_size in [16, 64, 4096] {
+            let mut scanner = SequentialScanner::with_block_size(data.as_slice(), block_size);
+            let scanned: Vec<_> = scanner.by_ref().map(|e| e.unwrap()).collect();
+            assert_eq!(scanned.len(), expected.len());
+            for (a, b) in scanned.iter().zip(&expected) {
+                assert_eq!((&a.key, &a.value, a.offset), (&b.key, &b.value, b.offset));
+            }
+            assert_eq!(scanner.end(), reader.end());
+        }
+
+        data[20] ^= 0xFF;
+        let mut scanner = SequentialScanner::new(data.as_slice());
+        assert!(matches!(
+            scanner.next(),
+            Some(Err(LSMLibError::CrcMismatch { .. }))
+        ));
+        assert!(scanner.next().is_none());
+    }
+
+    #[test]
+    fn test_detect_checksum() {
+        let mut cursor = Cursor::new(Vec::new());
+        assert_eq!(detect_checksum(&mut cursor).unwrap(), ChecksumKind::Crc32);
+
+        cursor.write_all(&[0u8; 32]).unwrap();
+        DiskEntry::with_checksum::<Crc64>(b"key".to_vec(), b"value".to_vec())
+            .write_to(&mut cursor)
+            .unwrap();
+        DiskEntry::with_checksum::<Crc32>(b"key".to_vec(), b"value".to_vec())
+            .write_to(&mut cursor)
+            .unwrap();
+        assert_eq!(detect_checksum(&mut cursor).unwrap(), ChecksumKind::Crc64);
+
+        // each entry is checked with its own algorithm.
+        let entries: Vec<_> = DiskEntryReader::new(&mut cursor)
+            .map(|e| e.unwrap())
+            .collect();
+        assert_eq!(entries[0].header.checksum_kind(), ChecksumKind::Crc64);
+        assert_eq!(entries[1].header.checksum_kind(), ChecksumKind::Crc32);
+        assert_eq!(
+            ChecksumKind::of::<DefaultChecksum>(),
+            DiskEntry::new(b"k".to_vec(), b"v".to_vec())
+                .header
+                .checksum_kind()
+        );
+    }
+
+    #[test]
+    fn test_visit_entries() {
+        let mut cursor = Cursor::new(Vec::new());
+        for i in 0..10u64 {
+            let key = format!("key{}", i).into_bytes();
+            let entry = if i == 4 {
+                DiskEntry::tombstone(key)
+            } else {
+                DiskEntry::new(key, vec![i as u8; i as usize])
+            };
+            entry.write_to(&mut cursor).unwrap();
+        }
+
+        let mut seen = Vec::new();
+        let visited = visit_entries(&mut cursor, |header, key, value| {
+            assert_eq!(value.len() as u64, header.value_sz());
+            if !header.is_tombstone() {
+                seen.push((key.to_vec(), value.len()));
+            }
+            VisitAction::Continue
+        })
+        .unwrap();
+        assert_eq!((visited, seen.len()), (10, 9));
+        assert_eq!(seen[4], (b"key5".to_vec(), 5));
+
+        let visited = visit_entries(&mut cursor, |_, key, _| {
+            if key == b"key2" {
+                VisitAction::Stop
+            } else {
+                VisitAction::Continue
+            }
+        })
+        .unwrap();
+        assert_eq!(visited, 3);
+
+        let offset = entry_offsets(&mut cursor).unwrap()[6] as usize;
+        cursor.get_mut()[offset + HEADER_SIZE + 1] ^= 0xFF;
+        assert!(matches!(
+            visit_entries(&mut cursor, |_, _, _| VisitAction::Continue),
+            Err(LSMLibError::CrcMismatch { offset: Some(o), .. }) if o == offset as u64
+        ));
+    }
+
+    #[test]
+    fn test_is_plausible() {
+        let entry = DiskEntry::new(b"hello".to_vec(), b"world".to_vec());
+        let size = entry.size();
+        assert!(entry.header.is_plausible(size));
+        assert!(!entry.header.is_plausible(size - 1));
+        assert!(DiskEntry::new(Vec::new(), Vec::new())
+            .header
+            .is_plausible(size));
+
+        let implausible = |flags, value_sz| {
+            HeaderFields {
+                crc: 1,
+                key_sz: 5,
+                value_sz,
+                flags: flags | FLAG_HEADER_CRC,
+                ..Default::default()
+            }
+            .to_header()
+        };
+        assert!(!implausible(FLAG_HINT_CRC, 5).is_plausible(size));
+        assert!(!implausible(FLAG_TOMBSTONE, 5).is_plausible(size));
+        assert!(implausible(FLAG_TOMBSTONE, 0).is_plausible(size));
+        assert!(!implausible(FLAG_COMPRESSED, 0).is_plausible(size));
+
+        // rejected on read, before the crc check.
+        let mut buf = entry.to_bytes();
+        buf[FLAGS_POS] |= FLAG_TOMBSTONE;
+        assert!(matches!(
+            DiskEntry::read_from(&mut Cursor::new(&buf), 0),
+            Err(LSMLibError::ImplausibleHeader { offset: 0 })
+        ));
+        assert!(matches!(
+            DiskEntry::read_from_buf(&buf, 0),
+            Err(LSMLibError::ImplausibleHeader { offset: 0 })
+        ));
+        assert!(matches!(
+            DiskEntry::read_small(&mut Cursor::new(&buf), 0, &mut [0; SMALL_ENTRY_SIZE]),
+            Err(LSMLibError::ImplausibleHeader { offset: 0 })
+        ));
+    }
+
+    #[test]
+    fn test_header_fields() {
+        let fields = HeaderFields {
+            crc: 0xDEAD_BEEF,
+            timestamp: 42,
+            key_sz: 3,
+            value_sz: 1 << 30,
+            flags: FLAG_TOMBSTONE | FLAG_HEADER_CRC,
+            seq: Some(7),
+            expire_at: None,
+        };
+        let header = fields.to_header();
+        assert_eq!(header.version(), FormatVersion::Wide);
+        assert_eq!(
+            header.flag_bits(),
+            FLAG_TOMBSTONE | FLAG_HEADER_CRC | FLAG_SEQ
+        );
+        assert_eq!(header.crc(), 0xDEAD_BEEF);
+        assert_eq!(header.size(), WIDE_HEADER_SIZE + SEQ_SIZE);
+        assert_eq!(header.size(), layout::entry::size(&header.0));
+        assert_eq!(
+            HeaderFields::from_header(&header),
+            HeaderFields {
+                flags: FLAG_TOMBSTONE | FLAG_HEADER_CRC | FLAG_SEQ,
+                ..fields
+            }
+        );
+
+        let entry = DiskEntry::new(b"hello".to_vec(), b"world".to_vec());
+        let fields = HeaderFields::from_header(&entry.header);
+        assert_eq!(fields.to_header().as_ref(), entry.header.as_ref());
+    }
+
+    #[test]
+    fn test_baseline_layout() {
+        // entries and hints written before the header crc flag hold their
+        // sizes on all 32 bits, the top byte is no version nor flags.
+        let (key, value) = (b"big".to_vec(), vec![7u8; (16 << 20) + 3]);
+        let crc = hash::<Crc32>(&key, &value, 0, &[]) as u32;
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&crc.to_le_bytes());
+        buf.extend_from_slice(&9u32.to_le_bytes());
+        buf.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&key);
+        buf.extend_from_slice(&value);
+
+        let e = DiskEntry::read_from_checked(&mut Cursor::new(&buf), 0)
+            .unwrap()
+            .unwrap();
+        assert_eq!(e.header.version(), FormatVersion::Compact);
+        assert_eq!(e.flags(), EntryFlags(0));
+        assert_eq!(e.value, value);
+        assert_eq!(e.size(), buf.len() as u64);
+
+        let mut hint = Vec::new();
+        hint.extend_from_slice(&0u64.to_le_bytes());
+        hint.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        hint.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        hint.extend_from_slice(&9u32.to_le_bytes());
+        hint.extend_from_slice(&key);
+        let h = HintEntry::read_from(&mut Cursor::new(&hint), 0)
+            .unwrap()
+            .unwrap();
+        assert_eq!((h.key.clone(), h.header.value_sz()), (key, value.len()));
+    }
+
+    #[test]
+    fn test_baseline_flag_bits() {
+        // a baseline value of 128 MiB has the header crc flag bit set in
+        // its top byte, between two flagged entries.
+        let (key, value) = (b"big".to_vec(), vec![7u8; (1 << 27) + 3]);
+        let crc = hash::<Crc32>(&key, &value, 0, &[]) as u32;
+        let first = DiskEntry::new(b"first".to_vec(), b"1".to_vec());
+        let last = DiskEntry::new(b"last".to_vec(), b"2".to_vec());
+        let mut buf = Vec::new();
+        first.encode_into(&mut buf);
+        let offset = buf.len() as u64;
+        buf.extend_from_slice(&crc.to_le_bytes());
+        buf.extend_from_slice(&9u32.to_le_bytes());
+        buf.extend_from_slice(&(key.len() as u32).to_le_bytes());
+        buf.extend_from_slice(&(value.len() as u32).to_le_bytes());
+        assert_eq!(buf[buf.len() - 1], FLAG_HEADER_CRC);
+        buf.extend_from_slice(&key);
+        buf.extend_from_slice(&value);
+        last.encode_into(&mut buf);
+
+        let e = DiskEntry::read_from_checked(&mut Cursor::new(&buf), offset)
+            .unwrap()
+            .unwrap();
+        assert_eq!(e.flags(), EntryFlags(0));
+        assert_eq!(e.key, key);
+        assert_eq!(e.value.len(), value.len());
+        let e = DiskEntry::from_mmap(&buf, offset as usize)
+            .unwrap()
+            .unwrap();
+        assert!(e.is_validate() && e.value.len() == value.len());
+
+        let mut cursor = Cursor::new(&buf);
+        cursor.set_position(offset);
+        let e = DiskEntry::read_next(&mut cursor).unwrap().unwrap();
+        assert!(e.is_validate() && e.value.len() == value.len());
+        assert_eq!(cursor.position(), offset + e.size());
+
+        // scans step over it whole, flagged entries around it read as is.
+        let keys = [b"first".to_vec(), key, b"last".to_vec()];
+        let read: Vec<_> = DiskEntryReader::new(Cursor::new(&buf))
+            .map(|e| e.unwrap().key)
+            .collect();
+        assert_eq!(read, keys);
+        let read: Vec<_> = SequentialScanner::with_block_size(Cursor::new(&buf), 4096)
+            .map(|e| e.unwrap().key)
+            .collect();
+        assert_eq!(read, keys);
+        let read: Vec<_> = keys_only(Cursor::new(&buf)).map(|k| k.unwrap()).collect();
+        assert_eq!(read, keys);
+        assert!(verify_file(&mut Cursor::new(&buf)).unwrap().is_clean());
+    }
+
+    #[test]
+    fn test_ttl() {
+        let entry = DiskEntryBuilder::new(b"hello".to_vec(), b"world".to_vec())
+            .timestamp(100)
+            .seq(3)
+            .ttl(Duration::from_secs(60))
+            .build()
+            .unwrap();
+        assert_eq!(entry.expire_at(), Some(160));
+        assert!(!entry.is_expired(159));
+        assert!(entry.is_expired(160));
+        assert!(!DiskEntry::new(b"a".to_vec(), b"1".to_vec()).is_expired(u32::MAX));
+
+        let mut cursor = Cursor::new(Vec::new());
+        let (offset, size) = entry.write_to(&mut cursor).unwrap();
+        assert_eq!(size, DiskEntry::entry_size(b"hello", b"world") + 12);
+
+        let e = DiskEntry::read_from_checked(&mut cursor, offset)
+            .unwrap()
+            .unwrap();
+        assert_eq!(e.expire_at(), Some(160));
+        assert_eq!(e.seq(), Some(3));
+
+        // the expiry time is covered by the crc.
+        let mut buf = cursor.into_inner();
+        let pos = HEADER_SIZE + SEQ_SIZE;
+        buf[pos] ^= 0xFF;
+        assert!(DiskEntry::read_from_checked(&mut Cursor::new(buf), 0).is_err());
+
+        let hint = HintEntry::try_from(&e.clone().offset(offset)).unwrap();
+        assert_eq!(hint.expire_at(), Some(160));
+        assert_eq!(hint.seq(), Some(3));
+        assert!(hint.is_expired(200));
+
+        let tombstone = e.to_tombstone();
+        assert!(tombstone.is_tombstone());
+        assert_eq!(tombstone.recency(), e.recency());
+        assert_eq!(tombstone.expire_at(), None);
+    }
+
+    #[test]
+    fn test_write_streamed() {
+        let value: Vec<u8> = (0..200_000u32).map(|i| i as u8).collect();
+
+        let mut hasher = EntryHasher::<DefaultChecksum>::new();
+        hasher.update(b"big");
+        for chunk in value.chunks(4096) {
+            hasher.update(chunk);
+        }
+        assert_eq!(hasher.finalize(), DefaultChecksum::compute(b"big", &value));
+
+        let mut cursor = Cursor::new(Vec::new());
+        DiskEntry::tombstone(b"first".to_vec())
+            .write_to(&mut cursor)
+            .unwrap();
+        let (offset, size) = DiskEntry::write_streamed(
+            b"big",
+            &mut value.as_slice(),
+            value.len() as u64,
+            &mut cursor,
+        )
+        .unwrap();
+        assert_eq!(size, DiskEntry::entry_size(b"big", &value));
+        assert_eq!(cursor.position(), offset + size);
+
+        let e = DiskEntry::read_from_checked(&mut cursor, offset)
+            .unwrap()
+            .unwrap();
+        assert_eq!(e.value, value);
+
+        let err = DiskEntry::write_streamed(b"big", &mut &value[..10], 11, &mut cursor);
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn test_live_entries() {
+        let entries = vec![
+            DiskEntry::new(b"a".to_vec(), b"1".to_vec()),
+            DiskEntry::tombstone(b"b".to_vec()),
+            DiskEntry::builder(b"c".to_vec(), b"3".to_vec())
+                .expire_at(100)
+                .build()
+                .unwrap(),
+            DiskEntry::builder(b"d".to_vec(), b"4".to_vec())
+                .expire_at(u32::MAX)
+                .build()
+                .unwrap(),
+        ];
+        let mut buf = Vec::new();
+        DiskEntry::write_batch(&entries, &mut Cursor::new(&mut buf)).unwrap();
+
+        let keys = |live: LiveEntries<_>| -> Vec<Vec<u8>> {
+            live.map(|e| e.unwrap().key.to_vec()).collect()
+        };
+        assert_eq!(
+            keys(live_entries(Cursor::new(&buf))),
+            vec![b"a".to_vec(), b"d".to_vec()]
+        );
+        let at = |now| LiveEntries::new(DiskEntryReader::new(Cursor::new(&buf)), now);
+        assert_eq!(keys(at(99)).len(), 3);
+
+        // the raw reader behind still sees everything.
+        let mut live = at(99);
+        live.by_ref().for_each(drop);
+        assert_eq!(
+            live.get_ref().end(),
+            Some(ScanEnd::Clean {
+                offset: buf.len() as u64
+            })
+        );
+        assert_eq!(DiskEntryReader::new(Cursor::new(&buf)).count(), 4);
+    }
+
+    #[test]
+    fn test_verify_file() {
+        let entries = vec![
+            DiskEntry::new(b"a".to_vec(), b"1".to_vec()),
+            DiskEntry::with_seq(b"b".to_vec(), b"v".repeat(100 * 1024), 2),
+            DiskEntry::tombstone(b"c".to_vec()),
+        ];
+        let mut buf = Vec::new();
+        let offsets = DiskEntry::write_batch(&entries, &mut Cursor::new(&mut buf)).unwrap();
+        let len = buf.len() as u64;
+
+        let report = verify_file(&mut Cursor::new(&buf)).unwrap();
+        assert!(report.is_clean());
+        assert_eq!(report.entries, 3);
+        assert_eq!(report.bytes, len);
+        assert_eq!(report.end, ScanEnd::Clean { offset: len });
+
+        let empty = verify_file(&mut Cursor::new(Vec::new())).unwrap();
+        assert!(empty.is_clean());
+        assert_eq!(empty.entries, 0);
+
+        // a corrupted value is reported and the scan goes on.
+        let mut corrupted = buf.clone();
+        corrupted[offsets[2] as usize - 1] ^= 0xFF;
+        corrupted.extend_from_slice(&buf[..5]);
+        let report = verify_file(&mut Cursor::new(&corrupted)).unwrap();
+        assert!(!report.is_clean());
+        assert_eq!(report.entries, 3);
+        assert_eq!(report.crc_failures, vec![offsets[1]]);
+        assert_eq!(
+            report.end,
+            ScanEnd::PartialHeader {
+                offset: len,
+                available: 5
+            }
+        );
+    }
+
+    #[test]
+    fn test_scan_end() {
+        let entries = vec![
+            DiskEntry::new(b"a".to_vec(), b"1".to_vec()),
+            DiskEntry::with_seq(b"b".to_vec(), b"22".to_vec(), 2),
+        ];
+        let mut buf = Vec::new();
+        DiskEntry::write_batch(&entries, &mut Cursor::new(&mut buf)).unwrap();
+        let good = buf.len() as u64;
+
+        let scan = |buf: &[u8]| {
+            let mut reader = DiskEntryReader::new(Cursor::new(buf.to_vec()));
+            let read = reader.by_ref().collect::<Result<Vec<_>>>().unwrap().len();
+            (read, reader.end().unwrap())
+        };
+
+        assert_eq!(scan(&buf), (2, ScanEnd::Clean { offset: good }));
+
+        let mut torn = buf.clone();
+        torn.extend_from_slice(&DiskEntry::new(b"c".to_vec(), b"3".to_vec()).header.as_ref()[..7]);
+        assert_eq!(
+            scan(&torn),
+            (
+                2,
+                ScanEnd::PartialHeader {
+                    offset: good,
+                    available: 7
+                }
+            )
+        );
+
+        let mut torn = buf.clone();
+        let third = DiskEntry::new(b"c".to_vec(), b"333".to_vec());
+        third.encode_into(&mut torn);
+        torn.truncate(torn.len() - 2);
+        let (read, end) = scan(&torn);
+        assert_eq!(read, 2);
+        assert_eq!(
+            end,
+            ScanEnd::TruncatedBody {
+                offset: good,
+                needed: third.size(),
+                available: third.size() - 2
+            }
+        );
+        assert_eq!(end.offset(), good);
+        assert!(!end.is_clean());
+    }
+
+    #[test]
+    fn test_recover_entries() {
+        let entries = vec![
+            DiskEntry::new(b"a".to_vec(), b"1".to_vec()),
+            DiskEntry::new(b"bb".to_vec(), b"22".to_vec()),
+            DiskEntry::new(b"ccc".to_vec(), b"333".to_vec()),
+        ];
+
+        let mut buf = Vec::new();
+        let offsets = DiskEntry::write_batch(&entries, &mut Cursor::new(&mut buf)).unwrap();
+
+        // corrupt the value of the second entry.
+        buf[offsets[2] as usize - 1] ^= 0xFF;
+        let mut reader = recover_entries(Cursor::new(&buf));
+        let keys: Vec<_> = reader.by_ref().map(|e| e.key).collect();
+        assert_eq!(keys, vec![b"a".to_vec(), b"ccc".to_vec()]);
+        assert_eq!(reader.skipped(), entries[1].size());
+
+        // torn tail.
+        buf.pop();
+        let mut reader = recover_entries(Cursor::new(&buf));
+        assert_eq!(reader.by_ref().count(), 1);
+        assert_eq!(reader.skipped(), buf.len() as u64 - entries[0].size());
+    }
 
-        Ok(Some(Self::Entry {
-            header,
-            key,
-            file_id: None,
-        }))
+    #[test]
+    fn test_offset_out_of_range() {
+        let entry = DiskEntry::new(b"k".to_vec(), b"v".to_vec());
+        let mut cursor = Cursor::new(Vec::new());
+        let end = entry.size();
+        let (_, hint) = entry.write_and_hint(&mut cursor).unwrap();
+        let mut hints = Cursor::new(Vec::new());
+        hint.write_to(&mut hints).unwrap();
+
+        assert!(DiskEntry::read_from(&mut cursor, end).unwrap().is_none());
+        assert!(matches!(
+            DiskEntry::read_from(&mut cursor, end + 1),
+            Err(LSMLibError::OffsetOutOfRange { offset, len }) if offset == end + 1 && len == end
+        ));
+        assert!(matches!(
+            HintEntry::read_from(&mut hints, 1000),
+            Err(LSMLibError::OffsetOutOfRange { .. })
+        ));
     }
 
-    fn write_to<W>(&self, w: &mut W) -> Result<u64>
-    where
-        W: Write + Seek,
-    {
-        let offset = w.stream_position()?;
+    #[test]
+    fn test_truncate_to_last_valid() {
+        let entries = vec![
+            DiskEntry::new(b"a".to_vec(), b"1".to_vec()),
+            DiskEntry::new(b"bb".to_vec(), b"22".to_vec()),
+            DiskEntry::new(b"ccc".to_vec(), b"333".to_vec()),
+        ];
+        let mut buf = Vec::new();
+        let offsets = DiskEntry::write_batch(&entries, &mut Cursor::new(&mut buf)).unwrap();
+        let end = buf.len() as u64;
 
-        w.write_all(self.header.as_ref())?;
-        w.write_all(self.key.as_ref())?;
+        // intact file, then a corrupted middle entry kept along.
+        let mut cursor = Cursor::new(buf);
+        assert_eq!(truncate_to_last_valid(&mut cursor).unwrap(), end);
+        cursor.get_mut()[offsets[2] as usize - 1] ^= 0xFF;
+        assert_eq!(truncate_to_last_valid(&mut cursor).unwrap(), end);
 
-        Ok(offset)
+        // torn tail, then appending after it.
+        cursor.get_mut().truncate(end as usize - 1);
+        assert_eq!(truncate_to_last_valid(&mut cursor).unwrap(), offsets[1]);
+        let (offset, _) = entries[2].write_to(&mut cursor).unwrap();
+        assert_eq!(offset, offsets[1]);
+        let keys: Vec<_> = recover_entries(&mut cursor).map(|e| e.key).collect();
+        assert_eq!(keys, vec![b"a".to_vec(), b"ccc".to_vec()]);
+
+        assert_eq!(
+            truncate_to_last_valid(&mut Cursor::new(Vec::new())).unwrap(),
+            0
+        );
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_read_value_range() {
+        let value: Vec<u8> = (0..=255).collect();
+        let entries = vec![
+            DiskEntry::new(b"a".to_vec(), b"1".to_vec()),
+            DiskEntry::new(b"hello".to_vec(), value.clone()),
+            DiskEntry::new_compressed(b"zipped".to_vec(), value.repeat(4), Codec::Zstd(3)).unwrap(),
+        ];
 
-    use std::io::Cursor;
+        let mut buf = Vec::new();
+        let offsets = DiskEntry::write_batch(&entries, &mut Cursor::new(&mut buf)).unwrap();
+        let mut cursor = Cursor::new(&buf);
+
+        let part = DiskEntry::read_value_range(&mut cursor, offsets[1], 10..20).unwrap();
+        assert_eq!(part, value[10..20].to_vec());
+        let all = DiskEntry::read_value_range(&mut cursor, offsets[1], 0..256).unwrap();
+        assert_eq!(all, value);
+        assert!(DiskEntry::read_value_range(&mut cursor, offsets[1], 0..0)
+            .unwrap()
+            .is_empty());
+
+        assert!(matches!(
+            DiskEntry::read_value_range(&mut cursor, offsets[1], 250..257),
+            Err(LSMLibError::RangeOutOfBounds { len: 256, .. })
+        ));
+
+        let part = DiskEntry::read_value_range(&mut cursor, offsets[2], 500..600).unwrap();
+        assert_eq!(part, value.repeat(4)[500..600].to_vec());
+        assert!(matches!(
+            DiskEntry::read_value_range(&mut cursor, offsets[2], 1000..1025),
+            Err(LSMLibError::RangeOutOfBounds { len: 1024, .. })
+        ));
+    }
 
     #[test]
-    fn test_disk_entry_io() {
+    fn test_key_matches() {
+        let long_key = vec![b'k'; 200];
+        let entries = vec![
+            DiskEntry::new(b"hello".to_vec(), vec![0u8; 10_000]),
+            DiskEntry::with_seq(long_key.clone(), b"v".to_vec(), 1),
+        ];
+        let mut cursor = Cursor::new(Vec::new());
+        let offsets = DiskEntry::write_batch(&entries, &mut cursor).unwrap();
+
+        assert!(DiskEntry::key_matches(&mut cursor, offsets[0], b"hello").unwrap());
+        assert!(!DiskEntry::key_matches(&mut cursor, offsets[0], b"hellp").unwrap());
+        assert!(!DiskEntry::key_matches(&mut cursor, offsets[0], b"hell").unwrap());
+        assert!(DiskEntry::key_matches(&mut cursor, offsets[1], &long_key).unwrap());
+
+        let mut other = long_key.clone();
+        other[150] = b'x';
+        assert!(!DiskEntry::key_matches(&mut cursor, offsets[1], &other).unwrap());
+
+        assert_eq!(
+            DiskEntry::read_key_only(&mut cursor, offsets[1]).unwrap(),
+            long_key
+        );
+    }
+
+    #[test]
+    fn test_into_kv() {
+        let entry =
+            DiskEntry::new_compressed(b"hello".to_vec(), vec![b'w'; 100], Codec::Zstd(1)).unwrap();
+        let (key, value) = entry.into_kv();
+        assert_eq!(key, b"hello".to_vec());
+        assert_eq!(value, vec![b'w'; 100]);
+    }
+
+    #[test]
+    fn test_disk_entry_eq() {
+        let entry = DiskEntry::builder(b"hello".to_vec(), b"world".to_vec())
+            .timestamp(1)
+            .build()
+            .unwrap();
+
+        let mut buf = Vec::new();
+        let (offset, _) = entry.write_to(&mut Cursor::new(&mut buf)).unwrap();
+        let e = DiskEntry::read_from(&mut Cursor::new(&buf), offset)
+            .unwrap()
+            .unwrap()
+            .offset(offset)
+            .file_id(1);
+        assert_eq!(e, entry);
+
+        let later = DiskEntry::builder(b"hello".to_vec(), b"world".to_vec())
+            .timestamp(2)
+            .build()
+            .unwrap();
+        assert_ne!(later, entry);
+        assert_ne!(DiskEntry::new(b"hello".to_vec(), b"world!".to_vec()), entry);
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let entry = DiskEntry::builder(b"hello".to_vec(), b"world".to_vec())
+            .timestamp(1)
+            .seq(2)
+            .build()
+            .unwrap()
+            .offset(3)
+            .file_id(4);
+
+        let json = serde_json::to_string(&entry).unwrap();
+        let e: DiskEntry = serde_json::from_str(&json).unwrap();
+        assert_eq!(e, entry);
+        assert_eq!(e.seq(), Some(2));
+        assert_eq!((e.offset, e.file_id), (Some(3), Some(4)));
+        assert!(e.is_validate());
+
+        // the crc is not part of the representation, it can't be forged.
+        let json = json.replace("[119,111,114,108,100]", "[119,111,114,108,101]");
+        let e: DiskEntry = serde_json::from_str(&json).unwrap();
+        assert_eq!(e.value, b"worle".to_vec());
+        assert!(e.is_validate());
+
+        let tombstone = DiskEntry::tombstone(b"hello".to_vec());
+        let e: DiskEntry =
+            serde_json::from_str(&serde_json::to_string(&tombstone).unwrap()).unwrap();
+        assert!(e.is_tombstone());
+
+        // an empty key, stored as is, round-trips too.
+        let empty = DiskEntry::new(Vec::new(), b"world".to_vec());
+        let e: DiskEntry = serde_json::from_str(&serde_json::to_string(&empty).unwrap()).unwrap();
+        assert_eq!(e, empty);
+
+        let hint = HintEntry::try_from(&entry).unwrap();
+        let h: HintEntry = serde_json::from_str(&serde_json::to_string(&hint).unwrap()).unwrap();
+        assert_eq!(h.key, hint.key);
+        assert_eq!(h.offset(), 3);
+        assert_eq!(h.size(), entry.size());
+        assert_eq!(h.timestamp(), 1);
+        assert_eq!(h.seq(), Some(2));
+        assert_eq!(h.file_id, Some(4));
+
+        // a compressed value is still read back decompressed.
+        let value = vec![7u8; 1000];
+        let compressed =
+            DiskEntry::new_compressed(b"zipped".to_vec(), value.clone(), Codec::Zstd(3)).unwrap();
+        let mut cursor = Cursor::new(Vec::new());
+        let (_, hint) = compressed.write_and_hint(&mut cursor).unwrap();
+        let h: HintEntry = serde_json::from_str(&serde_json::to_string(&hint).unwrap()).unwrap();
+        assert!(h.header.is_compressed());
+        assert_eq!(h.size(), hint.size());
+        assert_eq!(h.read_value(&mut cursor).unwrap(), value);
+        h.verify_against(&mut cursor).unwrap();
+    }
+
+    #[test]
+    fn test_verify() {
         let entry = DiskEntry::new(b"hello".to_vec(), b"world".to_vec());
+        assert!(entry.verify().is_ok());
+
+        let mut entry = entry.offset(8).file_id(3);
+        entry.value = EntryBuf::from(&b"earth"[..]);
+        let err = entry.verify().unwrap_err();
+        assert!(matches!(
+            err,
+            LSMLibError::CrcMismatch {
+                ref key,
+                file_id: Some(3),
+                offset: Some(8),
+                expected,
+                actual,
+            } if key == b"hello" && expected == entry.crc() && actual == entry.crc_actual()
+        ));
+        assert!(err
+            .to_string()
+            .contains("key 'hello' in file Some(3) at offset Some(8)"));
+    }
+
+    #[test]
+    fn test_write_batch() {
+        let entries = vec![
+            DiskEntry::new(b"a".to_vec(), b"1".to_vec()),
+            DiskEntry::tombstone(b"b".to_vec()),
+            DiskEntry::with_seq(b"c".to_vec(), b"333".to_vec(), 9),
+        ];
 
         let mut buf = Vec::new();
         let mut cursor = Cursor::new(&mut buf);
+        entries[0].write_to(&mut cursor).unwrap();
+        let start = entries[0].size();
 
-        let offset = entry.write_to(&mut cursor).unwrap();
-        assert_eq!(offset, 0);
+        let offsets = DiskEntry::write_batch(&entries, &mut cursor).unwrap();
+        assert_eq!(offsets.len(), 3);
+        assert_eq!(offsets[0], start);
 
-        let entry1 = DiskEntry::read_from(&mut cursor, offset).unwrap();
-        assert_eq!(entry1.is_some(), true);
+        for (entry, offset) in entries.iter().zip(offsets) {
+            let e = DiskEntry::read_from_checked(&mut cursor, offset)
+                .unwrap()
+                .unwrap();
+            assert_eq!(e.key, entry.key);
+            assert_eq!(e.is_tombstone(), entry.is_tombstone());
+            assert_eq!(e.seq(), entry.seq());
+        }
+    }
 
-        let e = entry1.unwrap();
+    #[test]
+    fn test_empty_key() {
+        assert!(matches!(
+            DiskEntry::builder(Vec::new(), b"value".to_vec()).build(),
+            Err(LSMLibError::EmptyKey)
+        ));
+        assert!(matches!(
+            DiskEntry::builder(Vec::new(), Vec::new())
+                .tombstone()
+                .build(),
+            Err(LSMLibError::EmptyKey)
+        ));
+
+        let entry = DiskEntry::new(Vec::new(), b"value".to_vec());
+        let mut cursor = Cursor::new(Vec::new());
+        entry.write_to(&mut cursor).unwrap();
+        entry.write_to(&mut cursor).unwrap();
+
+        let entries: Vec<_> = DiskEntryReader::new(&mut cursor)
+            .collect::<Result<_>>()
+            .unwrap();
+        assert_eq!(entries.len(), 2);
+        for e in entries {
+            assert!(e.key.is_empty());
+            assert_eq!(&e.value[..], b"value");
+            assert!(e.is_validate());
+        }
+    }
+
+    #[test]
+    fn test_to_bytes() {
+        let entry = DiskEntry::builder(b"hello".to_vec(), b"world".to_vec())
+            .timestamp(0x0403_0201)
+            .build_with::<Crc32>()
+            .unwrap();
+
+        let bytes = entry.to_bytes();
+        assert_eq!(bytes.len() as u64, entry.size());
+        assert_eq!(&bytes[4..8], &[1, 2, 3, 4]);
+        assert_eq!(&bytes[HEADER_SIZE..], b"helloworld");
+        let e = DiskEntry::read_from_checked(&mut Cursor::new(&bytes), 0)
+            .unwrap()
+            .unwrap();
+        assert_eq!(e.to_bytes(), bytes);
+
+        let dump = entry.hexdump();
+        let lines: Vec<_> = dump.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with("00000000 "));
+        assert!(lines[0].contains(" 01 02 03 04 "));
+        assert!(lines[1].starts_with("00000010 "));
+        assert!(lines[1].ends_with("|helloworld|"));
+        assert_eq!(lines[0].len(), lines[1].len() + 16 - 10);
+    }
+
+    #[test]
+    fn test_write_to_at() {
+        let entry = DiskEntry::with_seq(b"hello".to_vec(), b"world".to_vec(), 2);
+        let hint = HintEntry::try_from(&entry.clone().offset(0)).unwrap();
+
+        // a plain `Vec` can't seek.
+        let mut buf = Vec::new();
+        assert_eq!(entry.write_to_at(&mut buf, 0).unwrap(), (0, entry.size()));
+        let at = buf.len() as u64;
+        assert_eq!(entry.write_to_at(&mut buf, at).unwrap(), (at, entry.size()));
+
+        let mut cursor = Cursor::new(Vec::new());
+        entry.write_to(&mut cursor).unwrap();
+        entry.write_to(&mut cursor).unwrap();
+        assert_eq!(cursor.get_ref(), &buf);
+
+        let mut hints = Vec::new();
+        assert_eq!(
+            hint.write_to_at(&mut hints, 9).unwrap(),
+            (9, hint.hint_size())
+        );
+        let h = HintEntry::read_from(&mut Cursor::new(&hints), 0)
+            .unwrap()
+            .unwrap();
+        assert_eq!(h.key, hint.key);
+    }
+
+    #[test]
+    fn test_write_kv() {
+        let key = [7u8; 16];
+        let mut cursor = Cursor::new(Vec::new());
+        write_kv(b"first", b"", &mut cursor).unwrap();
+        let (offset, size) = write_kv(&key, b"value", &mut cursor).unwrap();
+        assert_eq!(offset, (HEADER_SIZE + crc_ext()) as u64 + 5);
+        assert_eq!(offset + size, cursor.get_ref().len() as u64);
+
+        let e = DiskEntry::read_from_checked(&mut cursor, offset)
+            .unwrap()
+            .unwrap();
+        assert_eq!(&e.key[..], &key[..]);
+        assert_eq!(&e.value[..], b"value");
+
+        let owned = DiskEntry::builder(key.to_vec(), b"value".to_vec())
+            .timestamp(e.timestamp())
+            .build()
+            .unwrap();
+        let mut expected = Vec::new();
+        owned.encode_into(&mut expected);
+        assert_eq!(&cursor.get_ref()[offset as usize..], &expected[..]);
+    }
+
+    #[test]
+    fn test_write_to_durable() {
+        let dir = tempdir::TempDir::new("format").unwrap();
+        let mut file = File::options()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(dir.path().join("durable.data"))
+            .unwrap();
+
+        let first = DiskEntry::new(b"hello".to_vec(), b"world".to_vec());
+        let second = DiskEntry::tombstone(b"hello".to_vec());
+        let (offset, size) = first.write_to_durable(&mut file).unwrap();
+        assert_eq!((offset, size), (0, first.size()));
+
+        let mut w = BufWriter::new(&mut file);
+        let (offset, _) = second.write_to_durable(&mut w).unwrap();
+        assert_eq!(offset, size);
+        drop(w);
+
+        let e = DiskEntry::read_from_checked(&mut file, offset)
+            .unwrap()
+            .unwrap();
+        assert!(e.is_tombstone());
+
+        // a torn write leaves a partial entry, recovery drops it.
+        let mut buf = Vec::new();
+        first.write_to_durable(&mut Cursor::new(&mut buf)).unwrap();
+        let torn = buf.len() - 3;
+        buf.truncate(torn);
+        let mut reader = recover_entries(Cursor::new(buf));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_clock() {
+        let clock = FixedClock(1_000);
+        let entry = DiskEntry::new_with_clock(b"hello".to_vec(), b"world".to_vec(), &clock);
+        assert_eq!(entry.timestamp(), 1_000);
+        assert!(entry.is_validate());
+
+        let entry = DiskEntryBuilder::new(b"hello".to_vec(), b"world".to_vec())
+            .clock(&clock)
+            .ttl(Duration::from_secs(10))
+            .build()
+            .unwrap();
+        assert_eq!(entry.expire_at(), Some(1_010));
+    }
+
+    #[test]
+    fn test_datetime() {
+        let before = Utc::now().timestamp();
+        let entry = DiskEntry::new(b"hello".to_vec(), b"world".to_vec()).offset(0);
+        let after = Utc::now().timestamp();
+
+        let dt = entry.datetime().timestamp();
+        assert!(before <= dt && dt <= after);
+        assert_eq!(dt, entry.timestamp() as i64);
+        assert_eq!(
+            HintEntry::try_from(&entry).unwrap().datetime(),
+            entry.datetime()
+        );
+
+        assert_eq!(
+            to_datetime(u32::MAX).to_rfc3339(),
+            "2106-02-07T06:28:15+00:00"
+        );
+    }
+
+    #[test]
+    fn test_seq_entry_io() {
+        let entry = DiskEntry::with_seq(b"hello".to_vec(), b"world".to_vec(), 42);
+        assert_eq!(entry.seq(), Some(42));
+        assert_eq!(
+            entry.size(),
+            (HEADER_SIZE + SEQ_SIZE + crc_ext() + 10) as u64
+        );
+        assert!(entry.is_validate());
+
+        let mut buf = Vec::new();
+        let mut cursor = Cursor::new(&mut buf);
+        let (offset, size) = entry.write_to(&mut cursor).unwrap();
+        assert_eq!(size, entry.size());
+
+        let e = DiskEntry::read_from_checked(&mut cursor, offset)
+            .unwrap()
+            .unwrap();
+        assert_eq!(e.seq(), Some(42));
+        assert_eq!(e.value, b"world".to_vec());
+
+        // a later write wins even within the same second, and any
+        // sequenced write wins over a legacy one.
+        let newer = DiskEntry::tombstone_with_seq(b"hello".to_vec(), 43);
+        let legacy = DiskEntry::new(b"hello".to_vec(), b"world".to_vec());
+        assert_eq!(legacy.seq(), None);
+        assert!(newer.recency() > e.recency());
+        assert!(e.recency() > legacy.recency());
+
+        let h = HintEntry::try_from(&e.offset(offset)).unwrap();
+        assert_eq!(h.seq(), Some(42));
+        assert_eq!(h.size(), size);
+
+        let mut hint_buf = Vec::new();
+        let mut hint_cursor = Cursor::new(&mut hint_buf);
+        let (hint_offset, hint_size) = h.write_to(&mut hint_cursor).unwrap();
+        assert_eq!(hint_size, h.hint_size());
+
+        let h1 = HintEntry::read_from(&mut hint_cursor, hint_offset)
+            .unwrap()
+            .unwrap();
+        assert_eq!(h1.seq(), Some(42));
+        assert_eq!(h1.size(), size);
+        assert_eq!(h1.key, b"hello".to_vec());
+    }
+
+    #[test]
+    fn test_wide_entry_io() {
+        let value = vec![7u8; layout::COMPACT_SZ_MASK as usize + 1];
+        let entry = DiskEntry::new(b"hello".to_vec(), value.clone());
+
+        assert_eq!(entry.header.size(), WIDE_HEADER_SIZE + crc_ext());
+        assert_eq!(entry.header.key_sz(), 5);
+        assert_eq!(entry.header.value_sz(), value.len() as u64);
+        assert_eq!(entry.size(), DiskEntry::entry_size(b"hello", &value));
+
+        let mut buf = Vec::new();
+        let mut cursor = Cursor::new(&mut buf);
+
+        let (offset, _) = entry.write_to(&mut cursor).unwrap();
+        let e = DiskEntry::read_from(&mut cursor, offset).unwrap().unwrap();
         assert_eq!(e.key, b"hello".to_vec());
+        assert_eq!(e.value, value);
+        assert!(e.is_validate());
+        assert_eq!(buf.len() as u64, e.size());
+
+        let hint = HintEntry::try_from(&e.offset(offset)).unwrap();
+        assert_eq!(
+            hint.hint_size(),
+            5 + (WIDE_HINT_HEADER_SIZE + crc_ext() + HINT_CRC_SIZE) as u64
+        );
+        assert_eq!(hint.size(), buf.len() as u64);
+
+        let mut hint_buf = Vec::new();
+        let mut cursor = Cursor::new(&mut hint_buf);
+        hint.write_to(&mut cursor).unwrap();
+
+        let h = HintEntry::read_from(&mut cursor, 0).unwrap().unwrap();
+        assert_eq!(h.value_sz(), value.len());
+        assert_eq!(h.size(), buf.len() as u64);
+        assert_eq!(
+            HintEntry::new(b"hello".to_vec(), 0, h.size(), 0)
+                .unwrap()
+                .value_sz(),
+            value.len()
+        );
     }
 
     #[test]
-    fn test_crc_check() {
-        let mut entry = DiskEntry::new(b"hello".to_vec(), b"world".to_vec());
+    fn test_unknown_version() {
+        let entry = DiskEntry::new(b"hello".to_vec(), b"world".to_vec());
+        assert_eq!(entry.header.version(), FormatVersion::Compact);
+
+        let mut buf = Vec::new();
+        entry.write_to(&mut Cursor::new(&mut buf)).unwrap();
+
+        buf[VERSION_POS] = 0x7F;
+        let err = DiskEntry::read_from(&mut Cursor::new(&buf), 0).unwrap_err();
+        assert!(matches!(err, LSMLibError::UnknownVersion(0x7F)));
+    }
+
+    #[test]
+    fn test_disk_entry_reader() {
+        let mut buf = Vec::new();
+        let mut cursor = Cursor::new(&mut buf);
+
+        let mut offsets = Vec::new();
+        for (k, v) in [("a", "1"), ("bb", "22"), ("ccc", "")] {
+            let entry = DiskEntry::new(k.as_bytes().to_vec(), v.as_bytes().to_vec());
+            offsets.push(entry.write_to(&mut cursor).unwrap().0);
+        }
+
+        let entries = DiskEntryReader::new(Cursor::new(&buf))
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[2].key, b"ccc".to_vec());
+        assert_eq!(
+            entries
+                .iter()
+                .map(|e| e.offset.unwrap())
+                .collect::<Vec<_>>(),
+            offsets
+        );
+
+        // corrupt the value of the second entry.
+        let last = buf.len() - HEADER_SIZE - crc_ext() - 3;
+        buf[last - 1] ^= 0xFF;
+
+        let mut reader = DiskEntryReader::new(Cursor::new(&buf));
+        assert!(reader.next().unwrap().is_ok());
+        assert!(matches!(
+            reader.next().unwrap(),
+            Err(LSMLibError::CrcMismatch { offset, .. }) if offset == Some(offsets[1])
+        ));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_read_from_buf() {
+        let mut buf = Vec::new();
+        let mut cursor = Cursor::new(&mut buf);
+
+        DiskEntry::new(b"hello".to_vec(), b"world".to_vec())
+            .write_to(&mut cursor)
+            .unwrap();
+        DiskEntry::tombstone(b"hello".to_vec())
+            .write_to(&mut cursor)
+            .unwrap();
+
+        let e = DiskEntry::read_from_buf(&buf, 0).unwrap().unwrap();
+        assert_eq!(e.key, b"hello");
+        assert_eq!(e.value, b"world");
+        assert!(e.is_validate());
+
+        let next = e.size() as usize;
+        let e = DiskEntry::read_from_buf(&buf, next).unwrap().unwrap();
+        assert!(e.is_tombstone());
+        assert!(e.is_validate());
+        assert_eq!(e.to_entry().offset, Some(next as u64));
+
+        assert!(DiskEntry::read_from_buf(&buf, buf.len()).unwrap().is_none());
+
+        let err = DiskEntry::read_from_buf(&buf[..next - 1], 0).unwrap_err();
+        assert!(matches!(
+            err,
+            LSMLibError::TruncatedEntry { needed, available, .. }
+                if needed == next as u64 && available == next as u64 - 1
+        ));
+
+        let e = DiskEntry::from_mmap(&buf, next).unwrap().unwrap();
+        assert!(e.is_tombstone());
+        assert_eq!(e.offset, Some(next as u64));
+        assert!(DiskEntry::from_mmap(&buf, buf.len()).unwrap().is_none());
+        assert!(matches!(
+            DiskEntry::from_mmap(&buf[..buf.len() - 1], next),
+            Err(LSMLibError::TruncatedEntry { .. })
+        ));
+    }
+
+    #[test]
+    fn test_corrupted_sizes() {
+        let mut buf = Vec::new();
+        DiskEntry::new(b"hello".to_vec(), b"world".to_vec())
+            .write_to(&mut Cursor::new(&mut buf))
+            .unwrap();
+
+        // declare a ~16MB value in a 26 bytes file.
+        buf[12..15].copy_from_slice(&[0xFF, 0xFF, 0xFF]);
+        let err = DiskEntry::read_from(&mut Cursor::new(&buf), 0).unwrap_err();
+        assert!(matches!(
+            err,
+            LSMLibError::TruncatedEntry {
+                field: Field::Value,
+                available,
+                ..
+            } if available == 26 + crc_ext() as u64
+        ));
+
+        let mut buf = Vec::new();
+        HintEntry::new(b"hello".to_vec(), 0, 100, 0)
+            .unwrap()
+            .write_to(&mut Cursor::new(&mut buf))
+            .unwrap();
 
-        assert_eq!(entry.is_validate(), true);
+        buf[8..11].copy_from_slice(&[0xFF, 0xFF, 0xFF]);
+        let err = HintEntry::read_from(&mut Cursor::new(&buf), 0).unwrap_err();
+        assert!(matches!(
+            err,
+            LSMLibError::TruncatedEntry {
+                field: Field::Key,
+                ..
+            }
+        ));
 
-        entry.value = b"hello".to_vec();
-        assert_eq!(entry.is_validate(), false);
+        // a key cut short by a file shrinking under the reader.
+        let mut short = Cursor::new(&buf[..HEADER_SIZE + 2]);
+        let err = short_read(
+            short.read_exact(&mut [0u8; HEADER_SIZE + 5]).unwrap_err(),
+            Field::Key,
+            0,
+            26,
+            HEADER_SIZE as u64 + 2,
+        );
+        assert!(matches!(
+            err,
+            LSMLibError::TruncatedEntry {
+                field: Field::Key,
+                ..
+            }
+        ));
     }
 
     #[test]
     fn test_hint_entry_io() {
-        let entry = HintEntry::new(b"hello".to_vec(), 0, 100, 0);
+        let entry = HintEntry::new(b"hello".to_vec(), 0, 100, 0).unwrap();
 
         assert_eq!(entry.header.key_sz(), 5);
-        assert_eq!(entry.header.value_sz(), 100 - 5 - HEADER_SIZE);
+        assert_eq!(entry.header.value_sz(), 100 - 5 - HEADER_SIZE - crc_ext());
         assert_eq!(entry.size(), 100);
-        assert_eq!(entry.hint_size(), 5 + HINT_HEADER_SIZE as u64);
+        assert_eq!(
+            entry.hint_size(),
+            5 + (HINT_HEADER_SIZE + crc_ext() + HINT_CRC_SIZE) as u64
+        );
 
         let mut buf = Vec::new();
         let mut cursor = Cursor::new(&mut buf);
 
-        let offset = entry.write_to(&mut cursor).unwrap();
+        let (offset, size) = entry.write_to(&mut cursor).unwrap();
         assert_eq!(offset, 0);
+        assert_eq!(size, entry.hint_size());
 
         let entry1 = HintEntry::read_from(&mut cursor, offset).unwrap();
-        assert_eq!(entry1.is_some(), true);
+        assert!(entry1.is_some());
 
         let e = entry1.unwrap();
         assert_eq!(e.key, b"hello".to_vec());
         assert_eq!(e.size(), 100);
-        assert_eq!(entry.hint_size(), 5 + HINT_HEADER_SIZE as u64);
+        assert_eq!(e.header.crc(), Some(e.compute_crc()));
+
+        // a corrupted offset fails the hint crc check.
+        let mut corrupted = buf.clone();
+        corrupted[0] ^= 0x01;
+        assert!(matches!(
+            HintEntry::read_from(&mut Cursor::new(&corrupted), 0),
+            Err(LSMLibError::CrcMismatch {
+                offset: Some(0),
+                ..
+            })
+        ));
+
+        // hints written before the crc existed still read.
+        let mut old = buf.clone();
+        old[FLAGS_POS] &= !FLAG_HINT_CRC;
+        let crc_pos = HINT_HEADER_SIZE + crc_ext();
+        old.drain(crc_pos..crc_pos + HINT_CRC_SIZE);
+        let e = HintEntry::read_from(&mut Cursor::new(&old), 0)
+            .unwrap()
+            .unwrap();
+        assert_eq!(e.key, b"hello".to_vec());
+        assert_eq!(e.header.crc(), None);
+        assert_eq!(e.hint_size(), 5 + crc_pos as u64);
+
+        for len in [5, HINT_HEADER_SIZE + 2] {
+            assert!(matches!(
+                HintEntry::read_from(&mut Cursor::new(&buf[..len]), 0),
+                Err(LSMLibError::TruncatedEntry { available, .. }) if available == len as u64
+            ));
+        }
+
+        // a size too small for the header and key is rejected.
+        let min = 21 + crc_ext() as u64;
+        assert!(matches!(
+            HintEntry::new(b"hello".to_vec(), 0, min - 1, 0),
+            Err(LSMLibError::InvalidEntrySize { size, min: m }) if size == min - 1 && m == min
+        ));
+        assert!(HintEntry::new(b"hello".to_vec(), 0, min, 0).is_ok());
+    }
+
+    #[test]
+    fn test_load_index() {
+        let entries = vec![
+            DiskEntry::new(b"a".to_vec(), b"1".to_vec()),
+            DiskEntry::tombstone(b"bb".to_vec()),
+            DiskEntry::with_seq(b"ccc".to_vec(), b"333".to_vec(), 5),
+        ];
+        let offsets = DiskEntry::write_batch(&entries, &mut Cursor::new(Vec::new())).unwrap();
+
+        let mut buf = Vec::new();
+        let mut cursor = Cursor::new(&mut buf);
+        for (entry, offset) in entries.iter().zip(&offsets) {
+            let hint = HintEntry::try_from(&entry.clone().offset(*offset)).unwrap();
+            hint.write_to(&mut cursor).unwrap();
+        }
+
+        let hints = HintEntry::load_index(&mut Cursor::new(&buf), 7).unwrap();
+        assert_eq!(hints.len(), 3);
+        for ((hint, entry), offset) in hints.iter().zip(&entries).zip(&offsets) {
+            assert_eq!(hint.key, entry.key);
+            assert_eq!(hint.offset(), *offset);
+            assert_eq!(hint.size(), entry.size());
+            assert_eq!(hint.file_id, Some(7));
+        }
+        assert!(hints[1].is_tombstone());
+
+        assert!(HintEntry::load_index(&mut Cursor::new(Vec::new()), 7)
+            .unwrap()
+            .is_empty());
+
+        // rewrite the second hint in place, at the offset it was read at.
+        let (hint_offset, stale) = HintEntryReader::new(Cursor::new(&buf))
+            .nth(1)
+            .unwrap()
+            .unwrap();
+        assert_eq!(hint_offset, hints[0].hint_size());
+        let fresh = HintEntry::try_from(&DiskEntry::tombstone(b"bb".to_vec()).offset(42)).unwrap();
+        assert_eq!(fresh.hint_size(), stale.hint_size());
+        let mut cursor = Cursor::new(&mut buf);
+        cursor.set_position(hint_offset);
+        fresh.write_to(&mut cursor).unwrap();
+
+        let mut reader = HintEntryReader::new(Cursor::new(&buf));
+        let offsets: Vec<_> = reader.by_ref().map(|h| h.unwrap().0).collect();
+        assert_eq!(offsets[1], hint_offset);
+        assert_eq!(reader.position(), buf.len() as u64);
+        let hints = HintEntry::load_index(&mut Cursor::new(&buf), 7).unwrap();
+        assert_eq!(hints[1].offset(), 42);
+    }
+
+    #[test]
+    fn test_hint_set_offset() {
+        let entry = DiskEntry::with_seq(b"hello".to_vec(), b"world".to_vec(), 4).offset(10);
+        let mut hint = HintEntry::try_from(&entry).unwrap().file_id(1);
+        hint.set_offset(1 << 40);
+        hint.set_file_id(2);
+        assert_eq!(hint.offset(), 1 << 40);
+        assert_eq!(hint.file_id, Some(2));
+        assert_eq!(hint.size(), entry.size());
+        assert_eq!(hint.seq(), Some(4));
+
+        let mut cursor = Cursor::new(Vec::new());
+        hint.write_to(&mut cursor).unwrap();
+        let h = HintEntry::read_from(&mut cursor, 0).unwrap().unwrap();
+        assert_eq!(h.offset(), 1 << 40);
+    }
+
+    #[test]
+    fn test_buffered_entry_writer() {
+        let entries: Vec<_> = (0..10u8)
+            .map(|i| DiskEntry::new(vec![b'k', i], vec![i; 100]))
+            .collect();
+        let entry_size = entries[0].size() as usize;
+
+        let mut w =
+            BufferedEntryWriter::with_capacity(Cursor::new(Vec::new()), 3 * entry_size).unwrap();
+        let mut offsets = Vec::new();
+        for entry in &entries[..2] {
+            offsets.push(w.write(entry).unwrap());
+        }
+        assert!(w.get_ref().get_ref().is_empty());
+        assert_eq!(w.buffered(), 2 * entry_size);
+
+        // the third entry fills the buffer, written out whole.
+        offsets.push(w.write(&entries[2]).unwrap());
+        assert_eq!(w.get_ref().get_ref().len(), 3 * entry_size);
+        assert_eq!(w.buffered(), 0);
+
+        for entry in &entries[3..] {
+            offsets.push(w.write(entry).unwrap());
+        }
+        assert_eq!(w.position(), 10 * entry_size as u64);
+        let buf = w.into_inner().unwrap().into_inner();
+
+        let mut expected = Vec::new();
+        assert_eq!(
+            DiskEntry::write_batch(&entries, &mut Cursor::new(&mut expected)).unwrap(),
+            offsets
+        );
+        assert_eq!(buf, expected);
+
+        // buffered entries are written out on drop.
+        let mut file = Vec::new();
+        {
+            let mut w = BufferedEntryWriter::new(Cursor::new(&mut file)).unwrap();
+            w.write(&entries[0]).unwrap();
+        }
+        assert_eq!(file.len(), entry_size);
+    }
+
+    #[test]
+    fn test_from_hint() {
+        let e = DiskEntryBuilder::new(b"hello".to_vec(), b"world".to_vec())
+            .seq(3)
+            .timestamp(1000)
+            .expire_at(2000)
+            .build()
+            .unwrap()
+            .offset(42)
+            .file_id(7);
+        let hint = HintEntry::try_from(&e).unwrap();
+
+        let rebuilt = DiskEntry::from_hint(&hint, b"world".to_vec());
+        assert_eq!(rebuilt, e);
+        assert_eq!(rebuilt.crc(), e.crc());
+        assert_eq!(rebuilt.offset, Some(42));
+        assert_eq!(rebuilt.file_id, Some(7));
+        assert!(rebuilt.is_validate());
+
+        let tombstone = DiskEntry::tombstone(b"gone".to_vec()).offset(0);
+        let rebuilt = DiskEntry::from_hint(&HintEntry::try_from(&tombstone).unwrap(), Vec::new());
+        assert!(rebuilt.is_tombstone());
+        assert_eq!(rebuilt.file_id, None);
+    }
+
+    #[test]
+    fn test_hint_ordering() {
+        let hint = |key: &[u8], file_id, offset, timestamp| {
+            HintEntry::new(key.to_vec(), offset, 64, timestamp)
+                .unwrap()
+                .file_id(file_id)
+        };
+        let mut hints = vec![
+            hint(b"b", 2, 0, 30),
+            hint(b"a", 1, 64, 20),
+            hint(b"c", 1, 0, 40),
+            hint(b"a", 2, 64, 10),
+        ];
+        let keys = |hints: &[HintEntry]| hints.iter().map(|h| h.key[0]).collect::<Vec<_>>();
+
+        hints.sort();
+        assert_eq!(keys(&hints), b"caba");
+        hints.sort_by(HintEntry::by_offset);
+        assert_eq!(keys(&hints), b"cbaa");
+        hints.sort_by(HintEntry::by_timestamp);
+        assert_eq!(keys(&hints), b"aabc");
+        hints.sort_by(HintEntry::by_key);
+        assert_eq!(keys(&hints), b"aabc");
+        assert_eq!(hints[0].file_id, Some(1));
+
+        // the same place, whatever the key.
+        assert_eq!(hint(b"x", 1, 0, 0), hint(b"y", 1, 0, 5));
+    }
+
+    #[test]
+    fn test_verify_against() {
+        let mut cursor = Cursor::new(Vec::new());
+        let entries = [
+            DiskEntry::with_seq(b"hello".to_vec(), b"world".to_vec(), 1),
+            DiskEntry::with_ttl(b"temp".to_vec(), b"v".to_vec(), Duration::from_secs(60)),
+        ];
+        let hints: Vec<_> = entries
+            .iter()
+            .map(|e| {
+                HintEntry::try_from(&e.clone().offset(e.write_to(&mut cursor).unwrap().0)).unwrap()
+            })
+            .collect();
+        for hint in &hints {
+            hint.verify_against(&mut cursor).unwrap();
+        }
+
+        // pointing at the wrong entry.
+        let mut hint = HintEntry::try_from(&entries[0].clone().offset(0)).unwrap();
+        hint.set_offset(entries[0].size());
+        assert!(matches!(
+            hint.verify_against(&mut cursor),
+            Err(LSMLibError::HintMismatch {
+                field: "key size",
+                ..
+            })
+        ));
+
+        // a poisoned hint.
+        let poisoned = HintEntry::try_from(
+            &DiskEntryBuilder::new(b"hello".to_vec(), b"world".to_vec())
+                .seq(1)
+                .timestamp(hints[0].timestamp() + 1)
+                .build()
+                .unwrap()
+                .offset(0),
+        )
+        .unwrap();
+        assert!(matches!(
+            poisoned.verify_against(&mut cursor),
+            Err(LSMLibError::HintMismatch {
+                field: "timestamp",
+                ..
+            })
+        ));
+        let other = HintEntry::try_from(
+            &DiskEntryBuilder::new(b"jello".to_vec(), b"world".to_vec())
+                .seq(1)
+                .timestamp(entries[0].timestamp())
+                .build()
+                .unwrap()
+                .offset(0),
+        )
+        .unwrap();
+        assert!(matches!(
+            other.verify_against(&mut cursor),
+            Err(LSMLibError::KeyMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_stream_value() {
+        let mut cursor = Cursor::new(Vec::new());
+        let value = b"0123456789".repeat(10_000);
+        let big = DiskEntry::new(b"big".to_vec(), value.clone());
+        let (offset, _) = big.write_to(&mut cursor).unwrap();
+        let hint = HintEntry::try_from(&big.offset(offset)).unwrap();
+
+        let mut out = Vec::new();
+        assert_eq!(
+            hint.stream_value(&mut cursor, &mut out).unwrap(),
+            value.len() as u64
+        );
+        assert_eq!(out, value);
+        let mut out = Vec::new();
+        assert_eq!(
+            hint.stream_value_checked(&mut cursor, &mut out).unwrap(),
+            value.len() as u64
+        );
+        assert_eq!(out, value);
+
+        // a corrupted value is only caught by the checked copy.
+        let mut buf = cursor.into_inner();
+        let last = buf.len() - 1;
+        buf[last] ^= 0xFF;
+        let mut r = Cursor::new(&buf);
+        assert!(hint.stream_value(&mut r, &mut io::sink()).is_ok());
+        assert!(matches!(
+            hint.stream_value_checked(&mut r, &mut io::sink()),
+            Err(LSMLibError::CrcMismatch { .. })
+        ));
+
+        // a value cut short.
+        let mut r = Cursor::new(&buf[..last]);
+        assert!(matches!(
+            hint.stream_value(&mut r, &mut io::sink()),
+            Err(LSMLibError::TruncatedEntry {
+                field: Field::Value,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_hint_read_value() {
+        let mut cursor = Cursor::new(Vec::new());
+        let plain = DiskEntry::with_seq(b"hello".to_vec(), b"world".to_vec(), 1);
+        let (offset, _) = plain.write_to(&mut cursor).unwrap();
+        let plain_hint = HintEntry::try_from(&plain.offset(offset)).unwrap();
+
+        let value = b"abcd".repeat(1024);
+        let packed =
+            DiskEntry::new_compressed(b"packed".to_vec(), value.clone(), Codec::Zstd(3)).unwrap();
+        let (offset, _) = packed.write_to(&mut cursor).unwrap();
+        let packed_hint = HintEntry::try_from(&packed.offset(offset)).unwrap();
+
+        assert_eq!(plain_hint.read_value(&mut cursor).unwrap(), b"world");
+        assert_eq!(
+            plain_hint.read_value_checked(&mut cursor).unwrap(),
+            b"world"
+        );
+        assert_eq!(packed_hint.read_value(&mut cursor).unwrap(), value);
+        assert_eq!(packed_hint.read_value_checked(&mut cursor).unwrap(), value);
+
+        // a hint pointing at another key.
+        let stale =
+            HintEntry::try_from(&DiskEntry::new(b"other".to_vec(), b"world".to_vec()).offset(0))
+                .unwrap();
+        assert!(matches!(
+            stale.read_value_checked(&mut cursor),
+            Err(LSMLibError::KeyMismatch { offset: 0, .. })
+        ));
+
+        // a corrupted value is only caught by the checked read.
+        let mut buf = cursor.into_inner();
+        let last = plain_hint.size() as usize - 1;
+        buf[last] ^= 0xFF;
+        let mut cursor = Cursor::new(&buf);
+        assert_eq!(
+            plain_hint.read_value(&mut cursor).unwrap(),
+            [&b"worl"[..], &[b'd' ^ 0xFF]].concat()
+        );
+        assert!(matches!(
+            plain_hint.read_value_checked(&mut cursor),
+            Err(LSMLibError::CrcMismatch { .. })
+        ));
+
+        // a hint past the end of the data file.
+        buf.truncate(last);
+        assert!(matches!(
+            plain_hint.read_value(&mut Cursor::new(&buf)),
+            Err(LSMLibError::TruncatedEntry {
+                field: Field::Value,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_display() {
+        let entry = DiskEntry::builder(b"hello".to_vec(), b"world".to_vec())
+            .timestamp(86400)
+            .build_with::<Crc32>()
+            .unwrap()
+            .offset(16)
+            .file_id(3);
+        let hint = HintEntry::try_from(&entry).unwrap();
+
+        assert_eq!(
+            entry.to_string(),
+            "DiskEntry(file_id=Some(3), key='hello', offset=Some(16), size=26, timestamp=1970-01-02T00:00:00Z)"
+        );
+        assert_eq!(
+            hint.to_string(),
+            "HintEntry(file_id=Some(3), key='hello', offset=16, size=26, timestamp=1970-01-02T00:00:00Z)"
+        );
+
+        let binary = DiskEntry::new(vec![0xff, 0x00, 0x10], Vec::new());
+        assert!(binary.to_string().contains("key='0xff0010'"));
+        assert_eq!(fmt_key(b"line\n").to_string(), "0x6c696e650a");
+        assert_eq!(fmt_key("clé".as_bytes()).to_string(), "clé");
+        for (key, encoded) in [
+            (&b""[..], "b64:"),
+            (b"f", "b64:Zg=="),
+            (b"foob", "b64:Zm9vYg=="),
+        ] {
+            assert_eq!(
+                KeyDisplay::new(key, KeyEncoding::Base64).to_string(),
+                encoded
+            );
+        }
+        assert_eq!(
+            KeyDisplay::new(b"ab", KeyEncoding::Hex).to_string(),
+            "0x6162"
+        );
+    }
+
+    #[test]
+    fn test_hint_from_owned() {
+        let entry = DiskEntry::with_seq(b"hello".to_vec(), b"world".to_vec(), 3)
+            .offset(64)
+            .file_id(2);
+        let borrowed = HintEntry::try_from(&entry).unwrap();
+        let owned = HintEntry::from_owned(entry).unwrap();
+
+        assert_eq!(owned.key, borrowed.key);
+        assert_eq!(owned.file_id, Some(2));
+        assert_eq!(owned.header.as_ref(), borrowed.header.as_ref());
+    }
+
+    #[test]
+    fn test_hint_missing_offset() {
+        let entry = DiskEntry::new(b"hello".to_vec(), b"world".to_vec());
+        assert!(matches!(
+            HintEntry::try_from(&entry),
+            Err(LSMLibError::MissingOffset)
+        ));
+        assert!(matches!(
+            HintEntry::from_owned(entry.clone()),
+            Err(LSMLibError::MissingOffset)
+        ));
+
+        let placed = HintEntry::placed_at(&entry, 128);
+        assert_eq!(placed.offset(), 128);
+        let written = HintEntry::try_from(&entry.offset(128)).unwrap();
+        assert_eq!(placed.header.as_ref(), written.header.as_ref());
     }
 }
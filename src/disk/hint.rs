@@ -1,12 +1,25 @@
 //! Hint File Module.
 
-use crate::error::Result;
+use crate::error::{LSMLibError, Result};
+use std::collections::HashMap;
 use std::fs::File;
+use std::io::{Cursor, Read, Seek, Write};
 use std::path::Path;
 
-use super::format::{EntryIO, HintEntry};
+use super::crc::hash_batch_len;
+use super::format::{DiskEntryReader, EntryIO, HintEntry, HintEntryReader};
 use super::logfile::LogFile;
 
+/// Magic starting an index snapshot.
+pub const SNAPSHOT_MAGIC: [u8; 4] = *b"LSMI";
+
+/// Size of the snapshot prefix: magic, hint count, packed length and crc
+/// of the length.
+pub const SNAPSHOT_PREFIX_SIZE: usize = 24;
+
+/// File id stored for hints without one.
+const NO_FILE_ID: u64 = u64::MAX;
+
 pub struct HintFile {
     inner: LogFile,
 }
@@ -53,17 +66,22 @@ impl HintFile {
             offset,
             size,
             timestamp,
-        ))
+        )?)
     }
 
     pub fn write_entry(&mut self, entry: HintEntry) -> Result<u64> {
         log::trace!("append {} to file {}", &entry, self.inner.path.display());
         let w = self.inner.writer().expect("hint file is not writeable");
-        let offset = entry.write_to(w)?;
+        let (offset, _) = entry.write_to(w)?;
         // self.entries_written += 1;
         Ok(offset)
     }
 
+    /// Read all hints of the file, failing on the first corrupted one.
+    pub fn load_index(&mut self) -> Result<Vec<HintEntry>> {
+        HintEntry::load_index(&mut self.inner.reader()?, self.inner.id)
+    }
+
     pub fn iter(&mut self) -> HintEntryIter {
         HintEntryIter {
             reader: self.inner.reader().unwrap(),
@@ -73,6 +91,158 @@ impl HintFile {
     }
 }
 
+/// Hints of any number of data files packed into a single blob, loaded
+/// with one read for a faster cold start than reading hint files.
+///
+/// The blob starts with `SNAPSHOT_MAGIC`, the number of hints as a u64,
+/// the length of the packed hints as a u64 and a crc of that length.
+/// Each hint follows as the file id of its data file, `u64::MAX` for
+/// none, then the hint encoded as in hint files, with its own crc.
+/// Integers are little endian.
+pub struct IndexSnapshot;
+
+impl IndexSnapshot {
+    /// Write `entries` as a snapshot with a single write, returns the
+    /// number of bytes written.
+    pub fn write_all<W: Write>(entries: &[HintEntry], w: &mut W) -> Result<u64> {
+        let mut packed = Vec::new();
+        for entry in entries {
+            packed.extend_from_slice(&entry.file_id.unwrap_or(NO_FILE_ID).to_le_bytes());
+            entry.encode_into(&mut packed);
+        }
+
+        let mut buf = Vec::with_capacity(SNAPSHOT_PREFIX_SIZE + packed.len());
+        buf.extend_from_slice(&SNAPSHOT_MAGIC);
+        buf.extend_from_slice(&(entries.len() as u64).to_le_bytes());
+        buf.extend_from_slice(&(packed.len() as u64).to_le_bytes());
+        buf.extend_from_slice(&hash_batch_len(packed.len()).to_le_bytes());
+        buf.extend_from_slice(&packed);
+        w.write_all(&buf)?;
+
+        Ok(buf.len() as u64)
+    }
+
+    /// Load a snapshot written by `write_all`, reading the packed hints
+    /// with a single read. A hint failing its crc check is a
+    /// `CrcMismatch` error, its offset relative to the packed hints.
+    pub fn read_all<R: Read>(r: &mut R) -> Result<Vec<HintEntry>> {
+        let corrupted = |reason: &str| LSMLibError::CorruptedSnapshot(reason.to_string());
+
+        let mut prefix = [0u8; SNAPSHOT_PREFIX_SIZE];
+        r.read_exact(&mut prefix)?;
+        if prefix[..4] != SNAPSHOT_MAGIC {
+            return Err(corrupted("bad magic"));
+        }
+        let count = u64::from_le_bytes(prefix[4..12].try_into().unwrap());
+        let len = u64::from_le_bytes(prefix[12..20].try_into().unwrap());
+        let crc = u32::from_le_bytes(prefix[20..24].try_into().unwrap());
+        if crc != hash_batch_len(len as usize) {
+            return Err(corrupted("length crc mismatch"));
+        }
+
+        let mut packed = Vec::new();
+        r.take(len).read_to_end(&mut packed)?;
+        if (packed.len() as u64) < len {
+            return Err(corrupted("packed hints are truncated"));
+        }
+
+        let mut cursor = Cursor::new(packed);
+        let mut entries = Vec::new();
+        let mut pos = 0;
+        while pos < len {
+            let mut file_id = [0u8; 8];
+            cursor.set_position(pos);
+            cursor.read_exact(&mut file_id)?;
+            let entry = HintEntry::read_from(&mut cursor, pos + 8)?
+                .ok_or_else(|| corrupted("hint is truncated"))?;
+            pos += 8 + entry.hint_size();
+
+            let file_id = u64::from_le_bytes(file_id);
+            entries.push(match file_id {
+                NO_FILE_ID => entry,
+                file_id => entry.file_id(file_id),
+            });
+        }
+
+        if entries.len() as u64 != count {
+            return Err(corrupted("hint count mismatch"));
+        }
+        Ok(entries)
+    }
+}
+
+/// Disagreements between a data file and its hint file, see `reconcile`.
+#[derive(Debug, Default)]
+pub struct Reconciliation {
+    /// hints for the entries of the data file no hint points at, in file
+    /// order, to extend the hint file with.
+    pub missing: Vec<HintEntry>,
+
+    /// key and offset of the hints pointing at no entry of the data file,
+    /// or at one they disagree with.
+    pub stale: Vec<(Vec<u8>, u64)>,
+
+    /// end of the last whole hint, past it the hint file is torn.
+    pub hints_len: u64,
+}
+
+impl Reconciliation {
+    /// Whether every entry has a hint and every hint is valid.
+    pub fn is_consistent(&self) -> bool {
+        self.missing.is_empty() && self.stale.is_empty()
+    }
+}
+
+/// Check hint file `hints` against data file `data`, both from offset 0,
+/// such as after a crash left the hints lagging the data. Every entry of
+/// `data` is read and checked, a hint is valid when it agrees with the
+/// entry at its offset as `HintEntry::verify_against` checks.
+///
+/// A truncated last entry or hint ends its file, a corrupted one fails
+/// the check.
+pub fn reconcile<R1, R2>(data: &mut R1, hints: &mut R2) -> Result<Reconciliation>
+where
+    R1: Read + Seek,
+    R2: Read + Seek,
+{
+    let mut unhinted = HashMap::new();
+    for entry in DiskEntryReader::new(&mut *data) {
+        let hint = HintEntry::try_from(&entry?)?;
+        unhinted.insert(hint.offset(), hint);
+    }
+
+    let mut report = Reconciliation::default();
+    let mut hinted = HashMap::new();
+    let mut reader = HintEntryReader::new(&mut *hints);
+    for hint in reader.by_ref() {
+        let hint = match hint {
+            Ok((_, hint)) => hint,
+            Err(LSMLibError::TruncatedEntry { .. }) => break,
+            Err(e) => return Err(e),
+        };
+        let offset = hint.offset();
+        let valid = match unhinted.remove(&offset) {
+            Some(entry) => {
+                hinted.insert(offset, entry);
+                true
+            }
+            None => hinted.contains_key(&offset),
+        } && match hint.verify_against(data) {
+            Ok(()) => true,
+            Err(LSMLibError::HintMismatch { .. } | LSMLibError::KeyMismatch { .. }) => false,
+            Err(e) => return Err(e),
+        };
+        if !valid {
+            report.stale.push((hint.key, offset));
+        }
+    }
+    report.hints_len = reader.position();
+
+    report.missing = unhinted.into_values().collect();
+    report.missing.sort();
+    Ok(report)
+}
+
 pub struct HintEntryIter {
     reader: File,
     offset: u64,
@@ -92,3 +262,92 @@ impl Iterator for HintEntryIter {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::disk::format::DiskEntry;
+
+    #[test]
+    fn test_reconcile() {
+        let mut data = Cursor::new(Vec::new());
+        let mut hints = Cursor::new(Vec::new());
+        for i in 0..4u8 {
+            let entry = DiskEntry::new(vec![b'k', i], vec![i; 10]);
+            let (_, hint) = entry.write_and_hint(&mut data).unwrap();
+            // the hints of the last two entries were lost.
+            if i < 2 {
+                hint.write_to(&mut hints).unwrap();
+            }
+        }
+        let bogus = DiskEntry::new(b"k0".to_vec(), b"gone".to_vec()).offset(1);
+        HintEntry::try_from(&bogus)
+            .unwrap()
+            .write_to(&mut hints)
+            .unwrap();
+        let hints_len = hints.get_ref().len() as u64;
+        hints.get_mut().extend_from_slice(&[0u8; 5]);
+
+        let report = reconcile(&mut data, &mut hints).unwrap();
+        assert!(!report.is_consistent());
+        assert_eq!(report.stale, vec![(b"k0".to_vec(), 1)]);
+        assert_eq!(report.hints_len, hints_len);
+        let missing: Vec<_> = report.missing.iter().map(|h| h.key.clone()).collect();
+        assert_eq!(missing, vec![vec![b'k', 2], vec![b'k', 3]]);
+
+        let mut healed = Cursor::new(Vec::new());
+        for i in 0..2 {
+            let hint = HintEntryReader::new(&mut hints).nth(i).unwrap().unwrap().1;
+            hint.write_to(&mut healed).unwrap();
+        }
+        for hint in report.missing {
+            hint.write_to(&mut healed).unwrap();
+        }
+        assert!(reconcile(&mut data, &mut healed).unwrap().is_consistent());
+    }
+
+    #[test]
+    fn test_index_snapshot() {
+        let entries: Vec<_> = (0..100u64)
+            .map(|i| {
+                let e = DiskEntry::with_seq(format!("key{}", i).into_bytes(), vec![0; 10], i)
+                    .offset(i * 64);
+                let hint = HintEntry::try_from(&e).unwrap();
+                if i % 10 == 0 {
+                    hint
+                } else {
+                    hint.file_id(i % 3)
+                }
+            })
+            .collect();
+
+        let mut buf = Vec::new();
+        let written = IndexSnapshot::write_all(&entries, &mut buf).unwrap();
+        assert_eq!(written, buf.len() as u64);
+
+        let loaded = IndexSnapshot::read_all(&mut buf.as_slice()).unwrap();
+        assert_eq!(loaded.len(), entries.len());
+        for (loaded, entry) in loaded.iter().zip(&entries) {
+            assert_eq!(loaded.key, entry.key);
+            assert_eq!(loaded.file_id, entry.file_id);
+            assert_eq!(loaded.offset(), entry.offset());
+            assert_eq!(loaded.seq(), entry.seq());
+        }
+
+        assert!(IndexSnapshot::read_all(&mut &buf[..buf.len() - 1]).is_err());
+        let mut corrupted = buf.clone();
+        corrupted[SNAPSHOT_PREFIX_SIZE + 20] ^= 0xFF;
+        assert!(IndexSnapshot::read_all(&mut corrupted.as_slice()).is_err());
+        assert!(matches!(
+            IndexSnapshot::read_all(&mut &b"LSMF"[..]),
+            Err(LSMLibError::Io(_))
+        ));
+
+        let mut empty = Vec::new();
+        IndexSnapshot::write_all(&[], &mut empty).unwrap();
+        assert!(IndexSnapshot::read_all(&mut empty.as_slice())
+            .unwrap()
+            .is_empty());
+    }
+}
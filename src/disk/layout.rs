@@ -0,0 +1,531 @@
+//! Layout Module.
+//!
+//! Byte layout of entry and hint headers, fields read and written in
+//! place on byte slices. Only `core` is used, no allocation nor I/O,
+//! those are left to `format`: without the `std` feature this and `crc`
+//! are all the crate builds. See `Header` and `HintHeader` for the
+//! fields of each layout.
+#![deny(
+    clippy::std_instead_of_core,
+    clippy::std_instead_of_alloc,
+    clippy::alloc_instead_of_core
+)]
+
+/// Size of the compact header, also the prefix every header starts with.
+pub const HEADER_SIZE: usize = 16;
+
+/// Size of the wide header, used when key or value size needs more
+/// than 24 bits.
+pub const WIDE_HEADER_SIZE: usize = 32;
+
+/// Size of the compact hint header, also the prefix every hint header
+/// starts with.
+pub const HINT_HEADER_SIZE: usize = 20;
+
+/// Size of the wide hint header, pointing at a disk entry with a wide
+/// header.
+pub const WIDE_HINT_HEADER_SIZE: usize = 36;
+
+/// Largest key or value size a compact header can hold.
+pub(crate) const COMPACT_SZ_MASK: u64 = 0x00FF_FFFF;
+
+/// Position of the version byte in the header, the most significant
+/// byte of the compact `key_sz` field.
+pub(crate) const VERSION_POS: usize = 11;
+
+/// Position of the flags byte in the header, the most significant
+/// byte of the compact `value_sz` field. In the baseline layout it is
+/// part of `value_sz`, see `is_baseline`.
+pub(crate) const FLAGS_POS: usize = 15;
+
+/// Version byte of the wide layout, the compact one is 0.
+pub(crate) const WIDE_VERSION: u8 = 1;
+
+/// Version byte of a batch marker, otherwise a compact header. Only the
+/// crate writes it, so user entries are never taken for markers.
+pub(crate) const MARKER_VERSION: u8 = 2;
+
+/// Entry is a deletion marker for its key.
+pub const FLAG_TOMBSTONE: u8 = 0x01;
+
+/// Entry value is stored compressed, `value_sz` is the compressed length.
+pub const FLAG_COMPRESSED: u8 = 0x02;
+
+/// Entry carries a sequence number, stored right after the layout fields.
+pub const FLAG_SEQ: u8 = 0x04;
+
+/// Entry crc also covers the header fields, so a corrupted size can't
+/// shift bytes between key and value unnoticed. Set on all new entries.
+pub const FLAG_HEADER_CRC: u8 = 0x08;
+
+/// Entry carries an expiry time, stored after the sequence number if
+/// any. Files without expiring entries are unaffected.
+pub const FLAG_EXPIRE: u8 = 0x10;
+
+/// Entry crc is a 64 bits `Crc64`, its upper half stored after the
+/// expiry time if any. Set on new entries with the `crc64` feature.
+pub const FLAG_CRC64: u8 = 0x20;
+
+/// Entry value is sealed with a `Cipher`, after compression if any.
+/// `value_sz` and the crc cover the sealed bytes.
+pub const FLAG_ENCRYPTED: u8 = 0x40;
+
+/// Hint only: the hint header ends with a crc of the hint. Never set on
+/// disk entries, whose flags hints otherwise copy.
+pub const FLAG_HINT_CRC: u8 = 0x80;
+
+/// Size of the sequence number field.
+pub(crate) const SEQ_SIZE: usize = 8;
+
+/// Size of the expiry time field.
+pub(crate) const EXPIRE_SIZE: usize = 4;
+
+/// Size of the field holding the upper half of a 64 bits crc.
+pub(crate) const CRC_HI_SIZE: usize = 4;
+
+/// Size of the hint crc field.
+pub(crate) const HINT_CRC_SIZE: usize = 4;
+
+/// Largest header any layout and flags combination encodes to.
+pub const MAX_HEADER_SIZE: usize = WIDE_HEADER_SIZE + SEQ_SIZE + EXPIRE_SIZE + CRC_HI_SIZE;
+
+/// Largest hint header any layout and flags combination encodes to.
+pub const MAX_HINT_HEADER_SIZE: usize =
+    WIDE_HINT_HEADER_SIZE + SEQ_SIZE + EXPIRE_SIZE + CRC_HI_SIZE + HINT_CRC_SIZE;
+
+/// Size of the buffer of an in-memory entry header: the largest header,
+/// then a byte never encoded marking a header read in the baseline
+/// layout although its flags byte says otherwise, see `is_baseline`.
+pub(crate) const HEADER_BUF_SIZE: usize = MAX_HEADER_SIZE + 1;
+
+/// Value of the last byte of an entry header buffer read as baseline.
+const BASELINE_MARK: u8 = 1;
+
+// the layout is an on-disk contract, changing a size breaks every file
+// written so far. See also the golden tests.
+const _: () = assert!(HEADER_SIZE == 16 && WIDE_HEADER_SIZE == 32);
+const _: () = assert!(HINT_HEADER_SIZE == 20 && WIDE_HINT_HEADER_SIZE == 36);
+const _: () = assert!(VERSION_POS == 11 && FLAGS_POS == 15);
+
+// a hint header buffer is never taken for a marked entry header one.
+const _: () = assert!(HEADER_BUF_SIZE != MAX_HINT_HEADER_SIZE);
+
+/// Size of the optional fields following the layout fields of a header.
+pub(crate) const fn ext_size(flags: u8) -> usize {
+    let mut size = 0;
+    if flags & FLAG_SEQ != 0 {
+        size += SEQ_SIZE;
+    }
+    if flags & FLAG_EXPIRE != 0 {
+        size += EXPIRE_SIZE;
+    }
+    if flags & FLAG_CRC64 != 0 {
+        size += CRC_HI_SIZE;
+    }
+    size
+}
+
+/// Position of the expiry time field, after the layout fields and the
+/// sequence number if any.
+pub(crate) fn expire_pos(layout_size: usize, flags: u8) -> usize {
+    layout_size + ext_size(flags & FLAG_SEQ)
+}
+
+/// Position of the upper half of a 64 bits crc, after the layout fields,
+/// the sequence number and the expiry time if any.
+pub(crate) fn crc_hi_pos(layout_size: usize, flags: u8) -> usize {
+    layout_size + ext_size(flags & (FLAG_SEQ | FLAG_EXPIRE))
+}
+
+fn get_u32(buf: &[u8], pos: usize) -> u32 {
+    u32::from_le_bytes(buf[pos..pos + 4].try_into().unwrap())
+}
+
+fn get_u64(buf: &[u8], pos: usize) -> u64 {
+    u64::from_le_bytes(buf[pos..pos + 8].try_into().unwrap())
+}
+
+fn put_u32(buf: &mut [u8], pos: usize, v: u32) {
+    buf[pos..pos + 4].copy_from_slice(&v.to_le_bytes());
+}
+
+fn put_u64(buf: &mut [u8], pos: usize, v: u64) {
+    buf[pos..pos + 8].copy_from_slice(&v.to_le_bytes());
+}
+
+/// Key or value size stored in a compact field, whose top byte holds the
+/// version or the flags, unless in the baseline layout.
+fn compact_sz(buf: &[u8], pos: usize) -> u64 {
+    let sz = get_u32(buf, pos) as u64;
+    if is_baseline(buf) {
+        sz
+    } else {
+        sz & COMPACT_SZ_MASK
+    }
+}
+
+/// Whether the header starting `buf` is in the baseline layout, the one
+/// written before versions and flags existed: 32 bits sizes whose top
+/// bytes are where the version and flags now are, no optional fields.
+///
+/// Every entry header written since carries the header crc flag, every
+/// hint header the hint crc flag or the header crc flag copied from its
+/// entry, so a flags byte with neither is the top byte of a baseline
+/// `value_sz`.
+///
+/// Baseline values of 128 MiB or more may have either flag bit set in
+/// that top byte. Entry readers then tell both readings apart by the crc
+/// and mark an entry header buffer read as baseline, see
+/// `entry::set_baseline`. Hint headers are not marked, a baseline hint
+/// read wrong disagrees with its entry and is rebuilt from it.
+pub fn is_baseline(buf: &[u8]) -> bool {
+    (buf.len() == HEADER_BUF_SIZE && buf[MAX_HEADER_SIZE] == BASELINE_MARK)
+        || buf[FLAGS_POS] & (FLAG_HEADER_CRC | FLAG_HINT_CRC) == 0
+}
+
+/// Whether the header starting `buf` uses the wide layout.
+pub fn is_wide(buf: &[u8]) -> bool {
+    !is_baseline(buf) && buf[VERSION_POS] == WIDE_VERSION
+}
+
+/// Whether a key and value of these sizes need the wide layout.
+pub const fn needs_wide(key_sz: u64, value_sz: u64) -> bool {
+    key_sz > COMPACT_SZ_MASK || value_sz > COMPACT_SZ_MASK
+}
+
+/// Flags of the header starting `buf`, none in the baseline layout.
+pub fn flags(buf: &[u8]) -> u8 {
+    if is_baseline(buf) {
+        0
+    } else {
+        buf[FLAGS_POS]
+    }
+}
+
+/// Optional fields following the layout fields, shared by both headers.
+fn seq(buf: &[u8], layout_size: usize) -> Option<u64> {
+    (flags(buf) & FLAG_SEQ != 0).then(|| get_u64(buf, layout_size))
+}
+
+/// Setters of the optional fields leave baseline headers as is, they
+/// have no room for them.
+fn set_seq(buf: &mut [u8], layout_size: usize, seq: u64) {
+    if is_baseline(buf) {
+        return;
+    }
+    buf[FLAGS_POS] |= FLAG_SEQ;
+    put_u64(buf, layout_size, seq);
+}
+
+fn expire_at(buf: &[u8], layout_size: usize) -> Option<u32> {
+    let pos = expire_pos(layout_size, flags(buf));
+    (flags(buf) & FLAG_EXPIRE != 0).then(|| get_u32(buf, pos))
+}
+
+fn set_expire_at(buf: &mut [u8], layout_size: usize, expire_at: u32) {
+    if is_baseline(buf) {
+        return;
+    }
+    let pos = expire_pos(layout_size, flags(buf));
+    buf[FLAGS_POS] |= FLAG_EXPIRE;
+    put_u32(buf, pos, expire_at);
+}
+
+fn crc_hi(buf: &[u8], layout_size: usize) -> Option<u32> {
+    let pos = crc_hi_pos(layout_size, flags(buf));
+    (flags(buf) & FLAG_CRC64 != 0).then(|| get_u32(buf, pos))
+}
+
+fn set_crc_hi(buf: &mut [u8], layout_size: usize, crc_hi: u32) {
+    if is_baseline(buf) {
+        return;
+    }
+    buf[FLAGS_POS] |= FLAG_CRC64;
+    let pos = crc_hi_pos(layout_size, flags(buf));
+    put_u32(buf, pos, crc_hi);
+}
+
+/// Fields of a disk entry header starting `buf`, see `Header`.
+///
+/// Optional fields have to be set in order: seq, expiry time, then crc.
+pub mod entry {
+    use super::*;
+
+    /// Size of the layout fields, before the optional ones.
+    pub fn layout_size(buf: &[u8]) -> usize {
+        if is_wide(buf) {
+            WIDE_HEADER_SIZE
+        } else {
+            HEADER_SIZE
+        }
+    }
+
+    /// Encoded size of the header.
+    pub fn size(buf: &[u8]) -> usize {
+        layout_size(buf) + ext_size(flags(buf))
+    }
+
+    /// Write the layout fields, the wide layout if the sizes need it. The
+    /// optional fields and the crc are set afterwards.
+    ///
+    /// Without the header crc flag the baseline layout is written, see
+    /// `is_baseline`, it has room for neither flags nor optional fields.
+    pub fn init(buf: &mut [u8], timestamp: u32, key_sz: u64, value_sz: u64, flags: u8) {
+        put_u32(buf, 4, timestamp);
+        if flags & (FLAG_HEADER_CRC | FLAG_HINT_CRC) == 0 {
+            put_u32(buf, 8, key_sz as u32);
+            put_u32(buf, 12, value_sz as u32);
+            return;
+        }
+        if needs_wide(key_sz, value_sz) {
+            buf[8..16].fill(0);
+            buf[VERSION_POS] = WIDE_VERSION;
+            put_u64(buf, 16, key_sz);
+            put_u64(buf, 24, value_sz);
+        } else {
+            put_u32(buf, 8, key_sz as u32);
+            put_u32(buf, 12, value_sz as u32);
+        }
+        buf[FLAGS_POS] = flags;
+    }
+
+    /// Read the entry header buffer `buf` in the baseline layout whatever
+    /// its flags byte, see `is_baseline`.
+    pub fn set_baseline(buf: &mut [u8; HEADER_BUF_SIZE]) {
+        buf[MAX_HEADER_SIZE] = BASELINE_MARK;
+    }
+
+    /// Whether the header is a batch marker's, see `MARKER_VERSION`.
+    pub fn is_marker(buf: &[u8]) -> bool {
+        !is_baseline(buf) && buf[VERSION_POS] == MARKER_VERSION
+    }
+
+    /// Mark a compact header as a batch marker's, before the crc is set.
+    pub fn set_marker(buf: &mut [u8]) {
+        if !is_baseline(buf) && !is_wide(buf) {
+            buf[VERSION_POS] = MARKER_VERSION;
+        }
+    }
+
+    /// Header bytes covered by the entry crc besides the flags, see
+    /// `crc::hash`: all but the crc itself with the header crc flag, else
+    /// only the optional fields besides the crc.
+    pub fn covered(buf: &[u8]) -> &[u8] {
+        let layout_size = layout_size(buf);
+        let end = crc_hi_pos(layout_size, flags(buf));
+        if flags(buf) & FLAG_HEADER_CRC != 0 {
+            &buf[4..end]
+        } else {
+            &buf[layout_size..end]
+        }
+    }
+
+    /// Crc of the entry, 64 bits wide with the crc64 flag.
+    pub fn crc(buf: &[u8]) -> u64 {
+        let lo = get_u32(buf, 0) as u64;
+        match super::crc_hi(buf, layout_size(buf)) {
+            Some(hi) => (hi as u64) << 32 | lo,
+            None => lo,
+        }
+    }
+
+    /// The upper half of `crc` is only stored with the crc64 flag.
+    pub fn set_crc(buf: &mut [u8], crc: u64) {
+        put_u32(buf, 0, crc as u32);
+        if flags(buf) & FLAG_CRC64 != 0 {
+            let pos = crc_hi_pos(layout_size(buf), flags(buf));
+            put_u32(buf, pos, (crc >> 32) as u32);
+        }
+    }
+
+    /// Write time in seconds since the unix epoch.
+    pub fn timestamp(buf: &[u8]) -> u32 {
+        get_u32(buf, 4)
+    }
+
+    pub fn key_sz(buf: &[u8]) -> u64 {
+        if is_wide(buf) {
+            get_u64(buf, 16)
+        } else {
+            compact_sz(buf, 8)
+        }
+    }
+
+    pub fn value_sz(buf: &[u8]) -> u64 {
+        if is_wide(buf) {
+            get_u64(buf, 24)
+        } else {
+            compact_sz(buf, 12)
+        }
+    }
+
+    pub fn seq(buf: &[u8]) -> Option<u64> {
+        super::seq(buf, layout_size(buf))
+    }
+
+    pub fn set_seq(buf: &mut [u8], seq: u64) {
+        super::set_seq(buf, layout_size(buf), seq)
+    }
+
+    /// Expiry time, seconds since the unix epoch.
+    pub fn expire_at(buf: &[u8]) -> Option<u32> {
+        super::expire_at(buf, layout_size(buf))
+    }
+
+    pub fn set_expire_at(buf: &mut [u8], expire_at: u32) {
+        super::set_expire_at(buf, layout_size(buf), expire_at)
+    }
+}
+
+/// Fields of a hint header starting `buf`, see `HintHeader`.
+///
+/// Optional fields have to be set in order: seq, expiry time, then the
+/// upper half of the entry crc. The hint crc follows them all.
+pub mod hint {
+    use super::*;
+
+    /// Size of the layout fields, before the optional ones.
+    pub fn layout_size(buf: &[u8]) -> usize {
+        if is_wide(buf) {
+            WIDE_HINT_HEADER_SIZE
+        } else {
+            HINT_HEADER_SIZE
+        }
+    }
+
+    /// Position of the hint crc, after all other fields.
+    pub fn crc_pos(buf: &[u8]) -> usize {
+        layout_size(buf) + ext_size(flags(buf))
+    }
+
+    /// Encoded size of the hint header.
+    pub fn size(buf: &[u8]) -> usize {
+        let crc_size = if flags(buf) & FLAG_HINT_CRC != 0 {
+            HINT_CRC_SIZE
+        } else {
+            0
+        };
+        crc_pos(buf) + crc_size
+    }
+
+    /// Write the layout fields in the layout of the disk entry, `wide` or
+    /// not. The hint crc flag is set, the optional fields are set
+    /// afterwards.
+    pub fn init(
+        buf: &mut [u8],
+        wide: bool,
+        offset: u64,
+        key_sz: u64,
+        value_sz: u64,
+        timestamp: u32,
+        flags: u8,
+    ) {
+        put_u64(buf, 0, offset);
+        if wide {
+            buf[8..16].fill(0);
+            buf[VERSION_POS] = WIDE_VERSION;
+            put_u64(buf, 20, key_sz);
+            put_u64(buf, 28, value_sz);
+        } else {
+            put_u32(buf, 8, key_sz as u32);
+            put_u32(buf, 12, value_sz as u32);
+        }
+        buf[FLAGS_POS] = flags | FLAG_HINT_CRC;
+        put_u32(buf, 16, timestamp);
+    }
+
+    /// Offset of the disk entry in its data file.
+    pub fn offset(buf: &[u8]) -> u64 {
+        get_u64(buf, 0)
+    }
+
+    pub fn set_offset(buf: &mut [u8], offset: u64) {
+        put_u64(buf, 0, offset);
+    }
+
+    pub fn key_sz(buf: &[u8]) -> u64 {
+        if is_wide(buf) {
+            get_u64(buf, 20)
+        } else {
+            compact_sz(buf, 8)
+        }
+    }
+
+    pub fn value_sz(buf: &[u8]) -> u64 {
+        if is_wide(buf) {
+            get_u64(buf, 28)
+        } else {
+            compact_sz(buf, 12)
+        }
+    }
+
+    /// Write time of the disk entry.
+    pub fn timestamp(buf: &[u8]) -> u32 {
+        get_u32(buf, 16)
+    }
+
+    pub fn seq(buf: &[u8]) -> Option<u64> {
+        super::seq(buf, layout_size(buf))
+    }
+
+    pub fn set_seq(buf: &mut [u8], seq: u64) {
+        super::set_seq(buf, layout_size(buf), seq)
+    }
+
+    pub fn expire_at(buf: &[u8]) -> Option<u32> {
+        super::expire_at(buf, layout_size(buf))
+    }
+
+    pub fn set_expire_at(buf: &mut [u8], expire_at: u32) {
+        super::set_expire_at(buf, layout_size(buf), expire_at)
+    }
+
+    /// Upper half of the crc of the disk entry, if 64 bits wide.
+    pub fn crc_hi(buf: &[u8]) -> Option<u32> {
+        super::crc_hi(buf, layout_size(buf))
+    }
+
+    pub fn set_crc_hi(buf: &mut [u8], crc_hi: u32) {
+        super::set_crc_hi(buf, layout_size(buf), crc_hi)
+    }
+
+    /// Crc of the hint, `None` for hints written before hints had one.
+    pub fn crc(buf: &[u8]) -> Option<u32> {
+        (flags(buf) & FLAG_HINT_CRC != 0).then(|| get_u32(buf, crc_pos(buf)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layout_fields() {
+        let mut buf = [0u8; MAX_HEADER_SIZE];
+        entry::init(&mut buf, 42, 3, 5, FLAG_HEADER_CRC | FLAG_CRC64);
+        entry::set_seq(&mut buf, 7);
+        entry::set_expire_at(&mut buf, 99);
+        entry::set_crc(&mut buf, 0x1122_3344_5566_7788);
+
+        assert!(!is_wide(&buf));
+        assert_eq!(entry::timestamp(&buf), 42);
+        assert_eq!((entry::key_sz(&buf), entry::value_sz(&buf)), (3, 5));
+        assert_eq!(entry::seq(&buf), Some(7));
+        assert_eq!(entry::expire_at(&buf), Some(99));
+        assert_eq!(entry::crc(&buf), 0x1122_3344_5566_7788);
+        assert_eq!(entry::size(&buf), MAX_HEADER_SIZE - 16);
+
+        let mut buf = [0u8; MAX_HINT_HEADER_SIZE];
+        let big = COMPACT_SZ_MASK + 1;
+        hint::init(&mut buf, true, 1024, 3, big, 42, 0);
+        hint::set_expire_at(&mut buf, 99);
+
+        assert!(is_wide(&buf));
+        assert_eq!(hint::offset(&buf), 1024);
+        assert_eq!((hint::key_sz(&buf), hint::value_sz(&buf)), (3, big));
+        assert_eq!(hint::timestamp(&buf), 42);
+        assert_eq!((hint::seq(&buf), hint::expire_at(&buf)), (None, Some(99)));
+        assert_eq!(hint::crc_pos(&buf), WIDE_HINT_HEADER_SIZE + EXPIRE_SIZE);
+        assert_eq!(hint::size(&buf), hint::crc_pos(&buf) + HINT_CRC_SIZE);
+    }
+}
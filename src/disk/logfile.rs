@@ -27,16 +27,13 @@ impl LogFile {
         let path = path.as_ref();
 
         // Data name must starts with valid file id.
-        let file_id = utils::parse_file_id(path).expect(&format!(
-            "file id not found in file path: {}",
-            path.display()
-        ));
+        let file_id = utils::parse_file_id(path)
+            .unwrap_or_else(|| panic!("file id not found in file path: {}", path.display()));
 
         let writer = if writeable {
             Some(
                 fs::OpenOptions::new()
                     .create(true)
-                    .write(true)
                     .append(true)
                     .open(path)?,
             )
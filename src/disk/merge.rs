@@ -0,0 +1,321 @@
+//! Merge Module.
+
+use std::iter::Peekable;
+
+use super::batch;
+use super::format::{DiskEntry, HintEntry};
+
+/// Outcome of weighing a candidate entry against the incumbent one for
+/// the same key, see `MergePolicy`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keep {
+    /// Keep the incumbent, the candidate is discarded.
+    Incumbent,
+
+    /// The candidate replaces the incumbent.
+    Replace,
+
+    /// The candidate replaces the incumbent, but the key is left out of
+    /// the output unless a later candidate replaces it in turn.
+    Drop,
+}
+
+/// Decides which of the entries for the same key survives a merge.
+pub trait MergePolicy {
+    /// Weigh `candidate` against the `incumbent` chosen so far, `None`
+    /// for the first entry of a key.
+    fn keep(&self, candidate: &DiskEntry, incumbent: Option<&DiskEntry>) -> Keep;
+}
+
+/// The newest write of a key wins, see `DiskEntry::recency`, ties go to
+/// the incumbent. Tombstones are dropped unless asked otherwise.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LatestWins {
+    keep_tombstones: bool,
+}
+
+impl LatestWins {
+    /// Keep winning tombstones in the output, needed when the merged
+    /// inputs are not all of the data: a dropped tombstone would let an
+    /// older write of its key, outside the inputs, come back.
+    pub fn keeping_tombstones() -> Self {
+        Self {
+            keep_tombstones: true,
+        }
+    }
+}
+
+impl MergePolicy for LatestWins {
+    fn keep(&self, candidate: &DiskEntry, incumbent: Option<&DiskEntry>) -> Keep {
+        match incumbent {
+            Some(incumbent) if candidate.recency() <= incumbent.recency() => Keep::Incumbent,
+            _ if candidate.is_tombstone() && !self.keep_tombstones => Keep::Drop,
+            _ => Keep::Replace,
+        }
+    }
+}
+
+/// Merge `inputs`, each sorted by key, into one stream sorted by key
+/// holding the entries surviving `policy`.
+///
+/// Entries for the same key are weighed in input order, batch markers
+/// are skipped.
+pub fn merge_stream<I, P>(inputs: Vec<I>, policy: P) -> MergeStream<I, P>
+where
+    I: Iterator<Item = DiskEntry>,
+    P: MergePolicy,
+{
+    MergeStream {
+        inputs: inputs.into_iter().map(Iterator::peekable).collect(),
+        policy,
+    }
+}
+
+/// Iterator returned by `merge_stream`.
+pub struct MergeStream<I: Iterator, P> {
+    inputs: Vec<Peekable<I>>,
+    policy: P,
+}
+
+impl<I, P> Iterator for MergeStream<I, P>
+where
+    I: Iterator<Item = DiskEntry>,
+    P: MergePolicy,
+{
+    type Item = DiskEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            for input in self.inputs.iter_mut() {
+                while input.next_if(batch::is_marker).is_some() {}
+            }
+            let key = self
+                .inputs
+                .iter_mut()
+                .filter_map(|input| input.peek())
+                .map(|entry| &entry.key)
+                .min()?
+                .clone();
+
+            let mut incumbent: Option<DiskEntry> = None;
+            let mut dropped = false;
+            for input in self.inputs.iter_mut() {
+                while let Some(candidate) = input.next_if(|entry| entry.key == key) {
+                    match self.policy.keep(&candidate, incumbent.as_ref()) {
+                        Keep::Incumbent => {}
+                        Keep::Replace => {
+                            incumbent = Some(candidate);
+                            dropped = false;
+                        }
+                        Keep::Drop => {
+                            incumbent = Some(candidate);
+                            dropped = true;
+                        }
+                    }
+                }
+            }
+
+            if !dropped {
+                return incumbent;
+            }
+        }
+    }
+}
+
+/// Merge hint streams `a` and `b`, each sorted by key, into one stream
+/// sorted by key holding one hint per key: the newest one, ordered by
+/// `HintEntry::recency` as `LatestWins` orders entries, ties going to `a`
+/// then to the earlier hint.
+///
+/// Inputs are assumed sorted, as hint files written from a key-sorted
+/// merge are, nothing is checked. Merging k streams is a matter of
+/// folding them pairwise.
+pub fn merge_hints<I, J>(a: I, b: J) -> MergeHints<I, J>
+where
+    I: Iterator<Item = HintEntry>,
+    J: Iterator<Item = HintEntry>,
+{
+    MergeHints {
+        a: a.peekable(),
+        b: b.peekable(),
+    }
+}
+
+/// Iterator returned by `merge_hints`.
+pub struct MergeHints<I: Iterator, J: Iterator> {
+    a: Peekable<I>,
+    b: Peekable<J>,
+}
+
+impl<I, J> Iterator for MergeHints<I, J>
+where
+    I: Iterator<Item = HintEntry>,
+    J: Iterator<Item = HintEntry>,
+{
+    type Item = HintEntry;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let key = match (self.a.peek(), self.b.peek()) {
+            (Some(a), Some(b)) => a.key.as_slice().min(b.key.as_slice()),
+            (Some(a), None) => a.key.as_slice(),
+            (None, Some(b)) => b.key.as_slice(),
+            (None, None) => return None,
+        }
+        .to_vec();
+
+        let same_key = |hint: &HintEntry| hint.key == key;
+        let mut newest: Option<HintEntry> = None;
+        while let Some(candidate) = self
+            .a
+            .next_if(same_key)
+            .or_else(|| self.b.next_if(same_key))
+        {
+            if newest
+                .as_ref()
+                .is_none_or(|newest| candidate.recency() > newest.recency())
+            {
+                newest = Some(candidate);
+            }
+        }
+        newest
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::disk::format::DiskEntryBuilder;
+
+    fn put(key: &str, value: &str, seq: u64) -> DiskEntry {
+        DiskEntry::with_seq(key.as_bytes().to_vec(), value.as_bytes().to_vec(), seq)
+    }
+
+    fn del(key: &str, seq: u64) -> DiskEntry {
+        DiskEntry::tombstone_with_seq(key.as_bytes().to_vec(), seq)
+    }
+
+    fn merged(inputs: Vec<Vec<DiskEntry>>, policy: impl MergePolicy) -> Vec<(String, u64)> {
+        let inputs = inputs.into_iter().map(Vec::into_iter).collect();
+        merge_stream(inputs, policy)
+            .map(|e| (String::from_utf8(e.key.to_vec()).unwrap(), e.seq().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_latest_wins() {
+        let inputs = || {
+            vec![
+                vec![put("a", "1", 1), put("b", "1", 2), put("d", "1", 3)],
+                vec![del("a", 4), put("c", "2", 5)],
+                // batch markers are no data.
+                vec![
+                    batch::begin_marker(3),
+                    put("a", "3", 0),
+                    put("b", "3", 6),
+                    del("c", 7),
+                    batch::commit_marker(3),
+                ],
+            ]
+        };
+
+        assert_eq!(
+            merged(inputs(), LatestWins::default()),
+            vec![("b".into(), 6), ("d".into(), 3)]
+        );
+        assert_eq!(
+            merged(inputs(), LatestWins::keeping_tombstones()),
+            vec![
+                ("a".into(), 4),
+                ("b".into(), 6),
+                ("c".into(), 7),
+                ("d".into(), 3)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_merge_hints() {
+        let hint = |key: &str, offset: u64, timestamp: u32| {
+            HintEntry::new(key.as_bytes().to_vec(), offset, 64, timestamp).unwrap()
+        };
+        let a = vec![hint("a", 0, 10), hint("c", 64, 30), hint("d", 128, 5)];
+        let b = vec![
+            hint("b", 0, 1),
+            hint("c", 64, 20),
+            hint("d", 128, 5),
+            hint("e", 192, 2),
+        ];
+
+        let merged: Vec<_> = merge_hints(a.into_iter(), b.into_iter())
+            .map(|h| (String::from_utf8(h.key.clone()).unwrap(), h.timestamp()))
+            .collect();
+        assert_eq!(
+            merged,
+            vec![
+                ("a".into(), 10),
+                ("b".into(), 1),
+                ("c".into(), 30),
+                ("d".into(), 5),
+                ("e".into(), 2)
+            ]
+        );
+
+        let newer = vec![hint("c", 256, 40)];
+        let merged: Vec<_> = merge_hints(newer.into_iter(), vec![hint("c", 64, 30)].into_iter())
+            .map(|h| h.offset())
+            .collect();
+        assert_eq!(merged, vec![256]);
+
+        // writes of the same second told apart by their seq.
+        let seq_hint = |offset: u64, seq: u64| {
+            let entry = DiskEntryBuilder::new(b"c".to_vec(), b"v".to_vec())
+                .seq(seq)
+                .timestamp(30)
+                .build()
+                .unwrap();
+            HintEntry::try_from(&entry.offset(offset)).unwrap()
+        };
+        let merged: Vec<_> = merge_hints(
+            vec![seq_hint(0, 1)].into_iter(),
+            vec![seq_hint(64, 2)].into_iter(),
+        )
+        .map(|h| h.offset())
+        .collect();
+        assert_eq!(merged, vec![64]);
+        assert_eq!(
+            merge_hints(Vec::new().into_iter(), Vec::new().into_iter()).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_custom_policy() {
+        /// Oldest timestamp wins, dropping nothing.
+        struct FirstWins;
+
+        impl MergePolicy for FirstWins {
+            fn keep(&self, candidate: &DiskEntry, incumbent: Option<&DiskEntry>) -> Keep {
+                match incumbent {
+                    Some(incumbent) if incumbent.timestamp() <= candidate.timestamp() => {
+                        Keep::Incumbent
+                    }
+                    _ => Keep::Replace,
+                }
+            }
+        }
+
+        let at = |key: &str, seq: u64, timestamp: u32| {
+            DiskEntryBuilder::new(key.as_bytes().to_vec(), b"v".to_vec())
+                .seq(seq)
+                .timestamp(timestamp)
+                .build()
+                .unwrap()
+        };
+        let inputs = vec![vec![at("a", 1, 20)], vec![at("a", 2, 10), at("b", 3, 5)]];
+        assert_eq!(
+            merged(inputs, FirstWins),
+            vec![("a".into(), 2), ("b".into(), 3)]
+        );
+    }
+}
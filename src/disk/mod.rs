@@ -1,8 +1,36 @@
 //! disk objects.
+#[cfg(feature = "tokio")]
+pub mod async_io;
+#[cfg(feature = "std")]
+pub mod batch;
+#[cfg(feature = "std")]
+pub mod cache;
+#[cfg(feature = "std")]
+pub mod chunk;
+#[cfg(feature = "encryption")]
+pub mod cipher;
+pub mod crc;
+#[cfg(feature = "std")]
 pub mod format;
+#[cfg(feature = "std")]
 pub mod hint;
+pub mod layout;
+#[cfg(feature = "std")]
+pub mod merge;
+#[cfg(feature = "std")]
+pub mod namespace;
+#[cfg(feature = "std")]
+pub mod segment;
+#[cfg(feature = "std")]
+pub mod snapshot;
+#[cfg(feature = "std")]
 pub mod sstable;
+#[cfg(feature = "std")]
+pub mod varint;
+#[cfg(feature = "std")]
+pub mod vlog;
+#[cfg(feature = "std")]
 pub mod wal;
 
-mod crc;
+#[cfg(feature = "std")]
 mod logfile;
@@ -0,0 +1,146 @@
+//! Namespace Module.
+//!
+//! Logical keyspaces sharing a data file, told apart by a prefix on the
+//! stored key. The crc and sizes of an entry cover the prefixed key, the
+//! application only ever sees its own keys.
+
+use super::format::{from_vec, DiskEntry};
+use crate::error::Result;
+
+/// Keyspace whose keys are stored prefixed by `prefix`.
+///
+/// Prefixes of the namespaces sharing a file must not be prefixes of
+/// one another, such as single bytes or all of the same length, else a
+/// scan of one namespace also sees keys of the other.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Namespace {
+    prefix: Vec<u8>,
+}
+
+impl Namespace {
+    pub fn new(prefix: impl Into<Vec<u8>>) -> Self {
+        Self {
+            prefix: prefix.into(),
+        }
+    }
+
+    pub fn prefix(&self) -> &[u8] {
+        &self.prefix
+    }
+
+    /// Stored key of `key` in this namespace.
+    pub fn key(&self, key: &[u8]) -> Vec<u8> {
+        let mut stored = Vec::with_capacity(self.prefix.len() + key.len());
+        stored.extend_from_slice(&self.prefix);
+        stored.extend_from_slice(key);
+        stored
+    }
+
+    /// Entry of `key` in this namespace.
+    pub fn entry(&self, key: &[u8], value: impl Into<Vec<u8>>) -> DiskEntry {
+        DiskEntry::new(self.key(key), value.into())
+    }
+
+    /// Deletion marker of `key` in this namespace.
+    pub fn tombstone(&self, key: &[u8]) -> DiskEntry {
+        DiskEntry::tombstone(self.key(key))
+    }
+
+    /// Application key of stored key `key`, `None` if it belongs to
+    /// another namespace.
+    pub fn strip<'k>(&self, key: &'k [u8]) -> Option<&'k [u8]> {
+        key.strip_prefix(&self.prefix[..])
+    }
+
+    /// Entries of this namespace among `entries`, such as a
+    /// `DiskEntryReader`, see `NamespaceEntries`.
+    pub fn filter<I>(&self, entries: I) -> NamespaceEntries<I>
+    where
+        I: Iterator<Item = Result<DiskEntry>>,
+    {
+        NamespaceEntries {
+            inner: entries,
+            prefix: self.prefix.clone(),
+        }
+    }
+}
+
+/// Adapter over an iterator of entries keeping those of a namespace,
+/// their keys stripped of its prefix, errors are passed through.
+///
+/// A stripped entry no longer passes the crc check, which covers the
+/// prefix: entries are to be checked before, as `DiskEntryReader` does.
+pub struct NamespaceEntries<I> {
+    inner: I,
+    prefix: Vec<u8>,
+}
+
+impl<I> NamespaceEntries<I> {
+    pub fn into_inner(self) -> I {
+        self.inner
+    }
+}
+
+impl<I> Iterator for NamespaceEntries<I>
+where
+    I: Iterator<Item = Result<DiskEntry>>,
+{
+    type Item = Result<DiskEntry>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        for entry in self.inner.by_ref() {
+            let mut entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => return Some(Err(e)),
+            };
+            if let Some(key) = entry.key.strip_prefix(&self.prefix[..]) {
+                entry.key = from_vec(key.to_vec());
+                return Some(Ok(entry));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use crate::disk::format::{DiskEntryReader, EntryIO};
+
+    #[test]
+    fn test_namespace() {
+        let (users, orders) = (Namespace::new([1u8]), Namespace::new([2u8]));
+        let mut cursor = Cursor::new(Vec::new());
+        for entry in [
+            users.entry(b"alice", b"1".to_vec()),
+            orders.entry(b"alice", b"order".to_vec()),
+            users.entry(b"bob", b"2".to_vec()),
+            users.tombstone(b"alice"),
+        ] {
+            entry.write_to(&mut cursor).unwrap();
+        }
+
+        let stored = DiskEntry::read_from(&mut cursor, 0).unwrap().unwrap();
+        assert_eq!(stored.key[..], b"\x01alice"[..]);
+        assert_eq!(users.strip(&stored.key), Some(&b"alice"[..]));
+        assert_eq!(orders.strip(&stored.key), None);
+
+        let entries: Vec<_> = users
+            .filter(DiskEntryReader::new(&mut cursor))
+            .map(|e| e.unwrap())
+            .collect();
+        let keys: Vec<_> = entries.iter().map(|e| e.key.to_vec()).collect();
+        assert_eq!(
+            keys,
+            vec![b"alice".to_vec(), b"bob".to_vec(), b"alice".to_vec()]
+        );
+        assert!(entries[2].is_tombstone());
+
+        let mut orders = orders.filter(DiskEntryReader::new(&mut cursor));
+        assert_eq!(orders.next().unwrap().unwrap().value[..], b"order"[..]);
+        assert!(orders.next().is_none());
+    }
+}
@@ -0,0 +1,142 @@
+//! Segment Module.
+//!
+//! Data files of a directory written one after the other: the active
+//! file, the one of the highest id, takes writes until the next entry
+//! would grow it past a size cap, then writes roll to a new file of the
+//! next id. An entry is never split between files.
+
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+use crate::config;
+use crate::error::Result;
+use crate::utils;
+
+use super::format::{DiskEntry, EntryIO};
+
+/// Writer of the data files of a directory, named as sstables are.
+#[derive(Debug)]
+pub struct SegmentWriter {
+    dir: PathBuf,
+    max_segment_bytes: u64,
+    file_id: u64,
+    file: File,
+    /// length of the active file.
+    len: u64,
+}
+
+impl SegmentWriter {
+    /// Writer of the data files of `dir`, appending to the one of the
+    /// highest id, or starting file 0 in an empty directory.
+    pub fn open(dir: impl AsRef<Path>, max_segment_bytes: u64) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        let pattern = format!("{}/*{}", dir.display(), config::DATA_FILE_SUFFIX);
+        let mut file_id = 0;
+        for path in glob::glob(&pattern)? {
+            if let Some(id) = utils::parse_file_id(&path?) {
+                file_id = file_id.max(id);
+            }
+        }
+
+        let file = Self::create(&dir, file_id)?;
+        let len = file.metadata()?.len();
+        Ok(Self {
+            dir,
+            max_segment_bytes,
+            file_id,
+            file,
+            len,
+        })
+    }
+
+    fn create(dir: &Path, file_id: u64) -> Result<File> {
+        let path = utils::format_sstable_path(dir, file_id);
+        Ok(fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?)
+    }
+
+    /// Append `entry` to the active file, rolling over to a new one first
+    /// when it would grow past `max_segment_bytes`. Returns the file id
+    /// and offset the entry was written at.
+    ///
+    /// An entry larger than `max_segment_bytes` is written alone in a
+    /// file of its own.
+    pub fn write_to(&mut self, entry: &DiskEntry) -> Result<(u64, u64)> {
+        if self.len > 0 && self.len + entry.size() > self.max_segment_bytes {
+            self.roll()?;
+        }
+        let (offset, size) = entry.write_to_at(&mut self.file, self.len)?;
+        self.len += size;
+        Ok((self.file_id, offset))
+    }
+
+    /// Sync the active file, then start a new one of the next id.
+    pub fn roll(&mut self) -> Result<()> {
+        self.file.sync_all()?;
+        self.file = Self::create(&self.dir, self.file_id + 1)?;
+        self.file_id += 1;
+        self.len = 0;
+        Ok(())
+    }
+
+    pub fn sync(&mut self) -> Result<()> {
+        self.file.sync_all()?;
+        Ok(())
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Id of the active file.
+    pub fn file_id(&self) -> u64 {
+        self.file_id
+    }
+
+    /// Length of the active file.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn max_segment_bytes(&self) -> u64 {
+        self.max_segment_bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_segment_writer() {
+        let dir = tempdir::TempDir::new("segment").unwrap();
+        let entry = DiskEntry::new(b"key".to_vec(), vec![0u8; 40]);
+        let size = entry.size();
+
+        let mut writer = SegmentWriter::open(dir.path(), size * 2 + 1).unwrap();
+        let written: Vec<_> = (0..5).map(|_| writer.write_to(&entry).unwrap()).collect();
+        assert_eq!(written, vec![(0, 0), (0, size), (1, 0), (1, size), (2, 0)]);
+
+        // an entry over the cap gets a file of its own.
+        let big = DiskEntry::new(b"big".to_vec(), vec![0u8; 200]);
+        assert_eq!(writer.write_to(&big).unwrap(), (3, 0));
+        assert_eq!(writer.write_to(&entry).unwrap(), (4, 0));
+        drop(writer);
+
+        for (file_id, offset) in written {
+            let mut file = File::open(utils::format_sstable_path(dir.path(), file_id)).unwrap();
+            let read = DiskEntry::read_from(&mut file, offset).unwrap().unwrap();
+            assert_eq!(read.key, entry.key);
+        }
+
+        let mut writer = SegmentWriter::open(dir.path(), size * 2 + 1).unwrap();
+        assert_eq!((writer.file_id(), writer.len()), (4, size));
+        assert_eq!(writer.write_to(&entry).unwrap(), (4, size));
+    }
+}
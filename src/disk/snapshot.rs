@@ -0,0 +1,255 @@
+//! Snapshot Module.
+//!
+//! Read-only view of an immutable data file, indexed by its hint file,
+//! for concurrent point-gets. Reads are positional, see `ReadAt`, so
+//! readers share the file without a lock or a position to fight over.
+//! A `ValueCache` can be shared by the readers of several data files.
+//! Encrypted values are opened with the cipher of the snapshot, sealed
+//! bytes are neither returned nor cached.
+
+use std::collections::HashMap;
+use std::io::{Read, Seek};
+use std::sync::Arc;
+
+use super::cache::ValueCache;
+#[cfg(feature = "encryption")]
+use super::cipher::Cipher;
+use super::format::{now_timestamp, DiskEntry, Field, HintEntry, HintEntryReader, ReadAt};
+use crate::error::{LSMLibError, Result};
+
+/// Thread-safe read-only handle over a data file and its index, cheap to
+/// clone: clones share both.
+#[derive(Debug)]
+pub struct SnapshotReader<F> {
+    data: Arc<F>,
+    /// offset and size of the newest entry of each key.
+    index: Arc<HashMap<Vec<u8>, (u64, u64)>>,
+    /// cache of values, with the file id of `data` in it.
+    cache: Option<(Arc<ValueCache>, u64)>,
+    #[cfg(feature = "encryption")]
+    cipher: Option<Cipher>,
+}
+
+impl<F> Clone for SnapshotReader<F> {
+    fn clone(&self) -> Self {
+        Self {
+            data: Arc::clone(&self.data),
+            index: Arc::clone(&self.index),
+            cache: self.cache.clone(),
+            #[cfg(feature = "encryption")]
+            cipher: self.cipher.clone(),
+        }
+    }
+}
+
+impl<F: ReadAt> SnapshotReader<F> {
+    /// Snapshot of `data` indexed by hint file `hints`, a corrupted hint
+    /// fails the load.
+    pub fn new<R: Read + Seek>(data: F, hints: &mut R) -> Result<Self> {
+        let hints = HintEntryReader::new(hints)
+            .map(|hint| hint.map(|(_, hint)| hint))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self::from_hints(data, hints))
+    }
+
+    /// Snapshot of `data` indexed by `hints`, in the order they were
+    /// written: a later hint of a key replaces an earlier one.
+    pub fn from_hints<I>(data: F, hints: I) -> Self
+    where
+        I: IntoIterator<Item = HintEntry>,
+    {
+        let index = hints
+            .into_iter()
+            .map(|hint| {
+                let slot = (hint.offset(), hint.size());
+                (hint.key, slot)
+            })
+            .collect();
+        Self {
+            data: Arc::new(data),
+            index: Arc::new(index),
+            cache: None,
+            #[cfg(feature = "encryption")]
+            cipher: None,
+        }
+    }
+
+    /// Serve values through `cache`, under file id `file_id`: a hit skips
+    /// the read and the crc check. Values of entries with an expiry time
+    /// are not cached, they would outlive it.
+    pub fn with_cache(mut self, cache: Arc<ValueCache>, file_id: u64) -> Self {
+        self.cache = Some((cache, file_id));
+        self
+    }
+
+    /// Open encrypted values with `cipher`, without it they are a
+    /// `MissingCipher` error.
+    #[cfg(feature = "encryption")]
+    pub fn with_cipher(mut self, cipher: Cipher) -> Self {
+        self.cipher = Some(cipher);
+        self
+    }
+
+    /// Value of `key`, read back whole and checked against its crc. A
+    /// deleted or expired key is `None`, as is a key not in the index.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
+        let Some(&(offset, size)) = self.index.get(key) else {
+            return Ok(None);
+        };
+        if let Some((cache, file_id)) = &self.cache {
+            if let Some(value) = cache.get(*file_id, offset) {
+                return Ok(Some(value));
+            }
+        }
+
+        let mut buf = vec![0u8; size as usize];
+        self.data.read_exact_at(&mut buf, offset)?;
+        let entry = DiskEntry::read_from_buf(&buf, 0)?
+            .ok_or(LSMLibError::TruncatedEntry {
+                field: Field::Header,
+                offset,
+                needed: size,
+                available: 0,
+            })?
+            .to_entry()
+            .offset(offset);
+        entry.verify()?;
+
+        if entry.key[..] != *key {
+            return Err(LSMLibError::KeyMismatch {
+                offset,
+                expected: key.to_vec(),
+                actual: entry.key.to_vec(),
+            });
+        }
+        if entry.is_tombstone() || entry.is_expired(now_timestamp()) {
+            return Ok(None);
+        }
+        let cacheable = entry.expire_at().is_none();
+        let value = self.open(entry)?.into_kv().1;
+        if let (Some((cache, file_id)), true) = (&self.cache, cacheable) {
+            cache.insert(*file_id, offset, value.clone());
+        }
+        Ok(Some(value))
+    }
+
+    /// Entry with its value opened if sealed, see `with_cipher`.
+    fn open(&self, entry: DiskEntry) -> Result<DiskEntry> {
+        if !entry.is_encrypted() {
+            return Ok(entry);
+        }
+        #[cfg(feature = "encryption")]
+        if let Some(cipher) = &self.cipher {
+            return entry.decrypt(cipher);
+        }
+        Err(LSMLibError::MissingCipher {
+            key: entry.key.to_vec(),
+        })
+    }
+
+    /// Number of keys indexed, deleted ones included.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.index.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+    use std::thread;
+
+    use crate::disk::format::EntryIO;
+
+    #[test]
+    fn test_snapshot_reader() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<SnapshotReader<std::fs::File>>();
+
+        let entries = vec![
+            DiskEntry::new(b"a".to_vec(), b"1".to_vec()),
+            DiskEntry::new(b"b".to_vec(), b"2".to_vec()),
+            DiskEntry::new(b"a".to_vec(), b"3".to_vec()),
+            DiskEntry::tombstone(b"b".to_vec()),
+            DiskEntry::new(b"c".to_vec(), vec![7u8; 1000]),
+        ];
+        let mut data = Cursor::new(Vec::new());
+        let mut hints = Cursor::new(Vec::new());
+        for entry in entries {
+            let (_, hint) = entry.write_and_hint(&mut data).unwrap();
+            hint.write_to(&mut hints).unwrap();
+        }
+
+        let data = data.into_inner();
+        let snapshot = SnapshotReader::new(data.clone(), &mut hints).unwrap();
+        assert_eq!(snapshot.len(), 3);
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let snapshot = snapshot.clone();
+                thread::spawn(move || {
+                    assert_eq!(snapshot.get(b"a").unwrap().unwrap(), b"3");
+                    assert_eq!(snapshot.get(b"c").unwrap().unwrap(), vec![7u8; 1000]);
+                    assert!(snapshot.get(b"b").unwrap().is_none());
+                    assert!(snapshot.get(b"missing").unwrap().is_none());
+                })
+            })
+            .collect();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+
+        // the value of "c" ends the file.
+        let mut data = data;
+        let last = data.len() - 1;
+        data[last] ^= 0xFF;
+        hints.set_position(0);
+        let snapshot = SnapshotReader::new(data.clone(), &mut hints).unwrap();
+        assert_eq!(snapshot.get(b"a").unwrap().unwrap(), b"3");
+        assert!(matches!(
+            snapshot.get(b"c"),
+            Err(LSMLibError::CrcMismatch { .. })
+        ));
+
+        // a cached value is served without reading the data.
+        let cache = Arc::new(ValueCache::new(4096));
+        hints.set_position(0);
+        let snapshot = SnapshotReader::new(data, &mut hints)
+            .unwrap()
+            .with_cache(Arc::clone(&cache), 7);
+        let (offset, _) = snapshot.index[&b"c"[..]];
+        cache.insert(7, offset, vec![7u8; 1000]);
+        assert_eq!(snapshot.get(b"c").unwrap().unwrap(), vec![7u8; 1000]);
+        assert_eq!(snapshot.get(b"a").unwrap().unwrap(), b"3");
+        assert_eq!(cache.len(), 2);
+        assert!(snapshot.get(b"b").unwrap().is_none());
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[cfg(feature = "encryption")]
+    #[test]
+    fn test_snapshot_encrypted() {
+        let cipher = Cipher::new(&[7u8; 32]);
+        let entry = DiskEntry::new_encrypted(b"a".to_vec(), b"secret".to_vec(), &cipher).unwrap();
+        let mut data = Cursor::new(Vec::new());
+        let (_, hint) = entry.write_and_hint(&mut data).unwrap();
+        let data = data.into_inner();
+
+        let cache = Arc::new(ValueCache::new(4096));
+        let snapshot = SnapshotReader::from_hints(data, [hint]).with_cache(Arc::clone(&cache), 1);
+        assert!(matches!(
+            snapshot.get(b"a"),
+            Err(LSMLibError::MissingCipher { .. })
+        ));
+        assert_eq!(cache.len(), 0);
+
+        let snapshot = snapshot.with_cipher(cipher);
+        assert_eq!(snapshot.get(b"a").unwrap().unwrap(), b"secret");
+        let (offset, _) = snapshot.index[&b"a"[..]];
+        assert_eq!(cache.get(1, offset).unwrap(), b"secret");
+    }
+}
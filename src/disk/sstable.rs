@@ -1,15 +1,15 @@
 //! SSTable Module.
 
-use std::cell::RefCell;
 use std::collections::BTreeMap;
 use std::fs::File;
-use std::iter::Peekable;
 use std::path::Path;
 
 use crate::error::Result;
 
-use super::format::{DiskEntry, EntryIO};
+use super::batch;
+use super::format::{fmt_key, DiskEntry, EntryIO};
 use super::logfile::LogFile;
+use super::merge::{merge_stream, LatestWins, MergeStream};
 
 #[derive(Debug)]
 pub struct SSTable {
@@ -69,11 +69,11 @@ impl SSTable {
 
         log::trace!(
             "append {} to segement file {}",
-            String::from_utf8_lossy(&disk_entry.key),
+            fmt_key(&disk_entry.key),
             path.display()
         );
 
-        let offset = disk_entry.write_to(w)?;
+        let (offset, _) = disk_entry.write_to(w)?;
 
         log::trace!(
             "successfully append {} to data file {}",
@@ -96,7 +96,7 @@ impl SSTable {
             return Ok(None);
         }
 
-        match DiskEntry::read_from(&mut self.reader, offset)? {
+        match DiskEntry::read_from_file(&mut self.reader, offset, self.inner.id)? {
             None => Ok(None),
             Some(entry) => {
                 log::trace!(
@@ -129,12 +129,12 @@ impl Iterator for DiskEntryIter {
     type Item = DiskEntry;
 
     fn next(&mut self) -> Option<Self::Item> {
-        match DiskEntry::read_from(&mut self.reader, self.offset).unwrap() {
-            None => None,
-            Some(entry) => {
-                let entry = entry.offset(self.offset).file_id(self.file_id);
-                self.offset += entry.size();
-                Some(entry)
+        loop {
+            let entry =
+                DiskEntry::read_from_file(&mut self.reader, self.offset, self.file_id).unwrap()?;
+            self.offset += entry.size();
+            if !batch::is_marker(&entry) {
+                return Some(entry);
             }
         }
     }
@@ -146,24 +146,22 @@ pub fn read_sstable(path: &Path) -> Result<BTreeMap<Vec<u8>, Vec<u8>>> {
     let mut items = BTreeMap::new();
 
     for entry in sst.iter() {
-        let _ = items.insert(entry.key, entry.value);
+        let (key, value) = entry.into_kv();
+        let _ = items.insert(key, value);
     }
 
     Ok(items)
 }
 
-pub struct CompactMergeIter {
-    sstables: Vec<RefCell<Peekable<DiskEntryIter>>>,
-}
+/// Merge of a run of sstables, keeping the newest write of each key.
+///
+/// Tombstones are kept, as older writes of their keys may live in
+/// sstables outside the run.
+pub struct CompactMergeIter(MergeStream<DiskEntryIter, LatestWins>);
 
 impl CompactMergeIter {
     pub fn new(iters: Vec<DiskEntryIter>) -> Self {
-        let mut sstables = Vec::new();
-        for iter in iters {
-            sstables.push(RefCell::new(iter.peekable()));
-        }
-
-        Self { sstables }
+        Self(merge_stream(iters, LatestWins::keeping_tombstones()))
     }
 }
 
@@ -171,33 +169,6 @@ impl Iterator for CompactMergeIter {
     type Item = DiskEntry;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let mut top: Option<(usize, Vec<u8>, u32)> = None;
-        for (index, iter) in self.sstables.iter().enumerate() {
-            if let Some(entry) = iter.borrow_mut().peek() {
-                match &top {
-                    None => top = Some((index, entry.key.clone(), entry.timestamp())),
-                    Some((top_index, key, timestamp)) => {
-                        if *key > entry.key {
-                            top = Some((index, entry.key.clone(), entry.timestamp()));
-                        } else if *key == entry.key {
-                            if *timestamp < entry.timestamp() {
-                                // next last iter.
-                                self.sstables[*top_index].borrow_mut().next();
-                                // use newer data.
-                                top = Some((index, entry.key.clone(), entry.timestamp()));
-                            } else {
-                                // drop older data.
-                                iter.borrow_mut().next();
-                            }
-                        }
-                    }
-                }
-            }
-        }
-
-        match top {
-            None => None,
-            Some((index, _, _)) => self.sstables[index].borrow_mut().next(),
-        }
+        self.0.next()
     }
 }
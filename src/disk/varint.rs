@@ -0,0 +1,286 @@
+//! Varint Module.
+//!
+//! Optional compact header format, for files of many small entries where
+//! the fixed header outweighs the data. Sizes, timestamp and the optional
+//! fields are LEB128 encoded, so headers take a variable number of bytes.
+//!
+//! The format is a property of the data file: a varint file starts with
+//! `VARINT_MAGIC`, files without it hold fixed headers, which stay the
+//! default. Each header stores the crc the fixed header would, so an
+//! entry read back is the very `DiskEntry` that was written, checked as
+//! any other.
+//!
+//! # varint header:
+//! - crc: u32, the lower half of the crc.
+//! - flags: u8
+//! - timestamp: LEB128
+//! - key_sz: LEB128
+//! - value_sz: LEB128
+//! - seq: LEB128, with the seq flag.
+//! - expire_at: LEB128, with the expire flag.
+//! - crc_hi: u32, the upper half of the crc with the crc64 flag.
+
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use super::format::{DiskEntry, EntryFlags, Field, HeaderFields};
+use crate::error::{LSMLibError, Result};
+
+/// Marker starting every varint file.
+pub const VARINT_MAGIC: [u8; 4] = *b"LSMV";
+
+/// Size of the largest varint header.
+pub const MAX_VARINT_HEADER_SIZE: usize = 4 + 1 + 5 + 10 + 10 + 10 + 5 + 4;
+
+/// Writes and reads entries with varint headers.
+pub struct VarintEntry;
+
+impl VarintEntry {
+    /// Start a varint file at the current position of `w`, returns the
+    /// offset of its first entry.
+    pub fn write_magic<W: Write + Seek>(w: &mut W) -> Result<u64> {
+        w.write_all(&VARINT_MAGIC)?;
+        Ok(w.stream_position()?)
+    }
+
+    /// Whether `r` starts with `VARINT_MAGIC`.
+    pub fn is_varint_file<R: Read + Seek>(r: &mut R) -> Result<bool> {
+        r.seek(SeekFrom::Start(0))?;
+        let mut magic = [0u8; VARINT_MAGIC.len()];
+        match r.read_exact(&mut magic) {
+            Ok(()) => Ok(magic == VARINT_MAGIC),
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Encoded header of `entry`, and its length.
+    pub fn encode_header(entry: &DiskEntry) -> ([u8; MAX_VARINT_HEADER_SIZE], usize) {
+        let fields = HeaderFields::from_header(entry.header());
+        let mut buf = [0u8; MAX_VARINT_HEADER_SIZE];
+        buf[0..4].copy_from_slice(&(fields.crc as u32).to_le_bytes());
+        buf[4] = fields.flags;
+        let mut pos = 5;
+        pos = put_uvarint(&mut buf, pos, fields.timestamp as u64);
+        pos = put_uvarint(&mut buf, pos, fields.key_sz);
+        pos = put_uvarint(&mut buf, pos, fields.value_sz);
+        if let Some(seq) = fields.seq {
+            pos = put_uvarint(&mut buf, pos, seq);
+        }
+        if let Some(expire_at) = fields.expire_at {
+            pos = put_uvarint(&mut buf, pos, expire_at as u64);
+        }
+        if EntryFlags::from_bits(fields.flags).contains(EntryFlags::CRC64) {
+            buf[pos..pos + 4].copy_from_slice(&((fields.crc >> 32) as u32).to_le_bytes());
+            pos += 4;
+        }
+        (buf, pos)
+    }
+
+    /// Size of `entry` with a varint header.
+    pub fn size(entry: &DiskEntry) -> u64 {
+        let (_, header_sz) = Self::encode_header(entry);
+        (header_sz + entry.key.len() + entry.stored_value().len()) as u64
+    }
+
+    /// Write `entry` at the current position of `w`, returns the offset it
+    /// starts at and the number of bytes written.
+    pub fn write<W: Write + Seek>(entry: &DiskEntry, w: &mut W) -> Result<(u64, u64)> {
+        let offset = w.stream_position()?;
+        let (header, header_sz) = Self::encode_header(entry);
+        let value = entry.stored_value();
+        let mut buf = Vec::with_capacity(header_sz + entry.key.len() + value.len());
+        buf.extend_from_slice(&header[..header_sz]);
+        buf.extend_from_slice(&entry.key);
+        buf.extend_from_slice(value);
+        w.write_all(&buf)?;
+        Ok((offset, buf.len() as u64))
+    }
+
+    /// Read the entry at `offset`, checked against its crc, along with
+    /// the offset of the next one. `None` at the end of the file.
+    pub fn read<R: Read + Seek>(r: &mut R, offset: u64) -> Result<Option<(DiskEntry, u64)>> {
+        r.seek(SeekFrom::Start(offset))?;
+        let mut buf = [0u8; MAX_VARINT_HEADER_SIZE];
+        let mut filled = 0;
+        while filled < buf.len() {
+            match r.read(&mut buf[filled..])? {
+                0 => break,
+                n => filled += n,
+            }
+        }
+        if filled == 0 {
+            return Ok(None);
+        }
+
+        let (fields, header_sz) = match decode_header(&buf[..filled]) {
+            Some(decoded) => decoded,
+            None if filled < buf.len() => {
+                return Err(LSMLibError::TruncatedEntry {
+                    field: Field::Header,
+                    offset,
+                    needed: filled as u64 + 1,
+                    available: filled as u64,
+                })
+            }
+            None => return Err(LSMLibError::ImplausibleHeader { offset }),
+        };
+
+        let body_sz = fields
+            .key_sz
+            .checked_add(fields.value_sz)
+            .ok_or(LSMLibError::ImplausibleHeader { offset })?;
+        r.seek(SeekFrom::Start(offset + header_sz as u64))?;
+        let mut body = Vec::new();
+        let read = r.by_ref().take(body_sz).read_to_end(&mut body)? as u64;
+        if read < body_sz {
+            let available = header_sz as u64 + read;
+            return Err(LSMLibError::TruncatedEntry {
+                field: Field::short_of(available, header_sz, fields.key_sz),
+                offset,
+                needed: header_sz as u64 + body_sz,
+                available,
+            });
+        }
+        let value = body.split_off(fields.key_sz as usize);
+
+        let entry = DiskEntry::decode(fields.to_header(), body, value).offset(offset);
+        entry.verify()?;
+        Ok(Some((entry, offset + header_sz as u64 + body_sz)))
+    }
+}
+
+/// Decode a varint header off the start of `buf`, `None` when it does not
+/// hold a whole valid header.
+fn decode_header(buf: &[u8]) -> Option<(HeaderFields, usize)> {
+    let mut crc = u32::from_le_bytes(buf.get(0..4)?.try_into().unwrap()) as u64;
+    let flags = *buf.get(4)?;
+    let mut pos = 5;
+    let timestamp = u32::try_from(get_uvarint(buf, &mut pos)?).ok()?;
+    let key_sz = get_uvarint(buf, &mut pos)?;
+    let value_sz = get_uvarint(buf, &mut pos)?;
+    let has = |flag| EntryFlags::from_bits(flags).contains(flag);
+    let seq = match has(EntryFlags::SEQ) {
+        true => Some(get_uvarint(buf, &mut pos)?),
+        false => None,
+    };
+    let expire_at = match has(EntryFlags::EXPIRE) {
+        true => Some(u32::try_from(get_uvarint(buf, &mut pos)?).ok()?),
+        false => None,
+    };
+    if has(EntryFlags::CRC64) {
+        let hi = u32::from_le_bytes(buf.get(pos..pos + 4)?.try_into().unwrap());
+        crc |= (hi as u64) << 32;
+        pos += 4;
+    }
+    let fields = HeaderFields {
+        crc,
+        timestamp,
+        key_sz,
+        value_sz,
+        flags,
+        seq,
+        expire_at,
+    };
+    Some((fields, pos))
+}
+
+/// Write `v` as LEB128 at `pos`, returns the position past it.
+fn put_uvarint(buf: &mut [u8], mut pos: usize, mut v: u64) -> usize {
+    while v >= 0x80 {
+        buf[pos] = v as u8 | 0x80;
+        v >>= 7;
+        pos += 1;
+    }
+    buf[pos] = v as u8;
+    pos + 1
+}
+
+/// Read a LEB128 value at `pos`, advancing it. `None` when cut short or
+/// overflowing a u64.
+fn get_uvarint(buf: &[u8], pos: &mut usize) -> Option<u64> {
+    let mut v = 0u64;
+    for shift in (0..64).step_by(7) {
+        let byte = *buf.get(*pos)?;
+        *pos += 1;
+        if shift == 63 && byte > 1 {
+            return None;
+        }
+        v |= ((byte & 0x7F) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Some(v);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    use crate::disk::format::DiskEntryBuilder;
+
+    #[test]
+    fn test_varint_entry() {
+        let entries = vec![
+            DiskEntry::new(b"k".to_vec(), b"v".to_vec()),
+            DiskEntry::tombstone(b"gone".to_vec()),
+            DiskEntryBuilder::new(b"seq".to_vec(), vec![7u8; 300])
+                .seq(u64::MAX)
+                .expire_at(1_234)
+                .build()
+                .unwrap(),
+            DiskEntryBuilder::new(b"big".to_vec(), vec![0u8; 70_000])
+                .build()
+                .unwrap(),
+        ];
+
+        let mut cursor = Cursor::new(Vec::new());
+        assert!(!VarintEntry::is_varint_file(&mut cursor).unwrap());
+        let mut offset = VarintEntry::write_magic(&mut cursor).unwrap();
+        for entry in &entries {
+            let (_, size) = VarintEntry::write(entry, &mut cursor).unwrap();
+            assert_eq!(size, VarintEntry::size(entry));
+            assert!(size < entry.size());
+        }
+        assert!(VarintEntry::is_varint_file(&mut cursor).unwrap());
+
+        for entry in &entries {
+            let (read, next) = VarintEntry::read(&mut cursor, offset).unwrap().unwrap();
+            assert_eq!(read.key, entry.key);
+            assert_eq!(read.value, entry.value);
+            assert_eq!(read.crc(), entry.crc());
+            assert_eq!(
+                (read.seq(), read.expire_at()),
+                (entry.seq(), entry.expire_at())
+            );
+            offset = next;
+        }
+        assert!(VarintEntry::read(&mut cursor, offset).unwrap().is_none());
+
+        let len = cursor.get_ref().len();
+        cursor.get_mut().truncate(len - 1);
+        let last = offset - VarintEntry::size(&entries[3]);
+        assert!(matches!(
+            VarintEntry::read(&mut cursor, last),
+            Err(LSMLibError::TruncatedEntry {
+                field: Field::Value,
+                ..
+            })
+        ));
+        cursor.get_mut()[VARINT_MAGIC.len() + 6] ^= 0xFF;
+        assert!(VarintEntry::read(&mut cursor, VARINT_MAGIC.len() as u64).is_err());
+
+        // sizes adding up past u64::MAX.
+        let mut header = [0u8; MAX_VARINT_HEADER_SIZE];
+        let mut pos = put_uvarint(&mut header, 5, 0);
+        pos = put_uvarint(&mut header, pos, u64::MAX);
+        pos = put_uvarint(&mut header, pos, 1);
+        let mut cursor = Cursor::new(header[..pos].to_vec());
+        assert!(matches!(
+            VarintEntry::read(&mut cursor, 0),
+            Err(LSMLibError::ImplausibleHeader { offset: 0 })
+        ));
+    }
+}
@@ -0,0 +1,198 @@
+//! Value Log Module.
+//!
+//! Key-value separation, as in WiscKey: data files hold each key along
+//! with a `ValuePointer`, the value itself appended to a value log file.
+//! Scanning and compacting data files then moves small pointers around
+//! rather than large values.
+//!
+//! Separation is a property of the data file, not of its entries: all
+//! flag bits are taken, so the value of a separated entry simply holds
+//! the encoded pointer, see `DiskEntry::with_value_pointer`. Value log
+//! files hold plain disk entries, the key kept along with the value so
+//! the log can be garbage collected.
+
+use std::collections::HashMap;
+use std::io::{Read, Seek, Write};
+
+use super::format::{DiskEntry, EntryIO};
+use crate::error::{LSMLibError, Result};
+
+/// Size of an encoded `ValuePointer`.
+pub const VALUE_POINTER_SIZE: usize = 24;
+
+/// Location of a value in a value log.
+///
+/// # fields:
+/// - file_id: u64
+/// - offset: u64, of the value log entry holding the value.
+/// - value_sz: u64
+///
+/// Fields are little endian, as in `Header`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ValuePointer {
+    pub file_id: u64,
+    pub offset: u64,
+    pub value_sz: u64,
+}
+
+impl ValuePointer {
+    pub fn encode(&self) -> [u8; VALUE_POINTER_SIZE] {
+        let mut buf = [0u8; VALUE_POINTER_SIZE];
+        buf[0..8].copy_from_slice(&self.file_id.to_le_bytes());
+        buf[8..16].copy_from_slice(&self.offset.to_le_bytes());
+        buf[16..24].copy_from_slice(&self.value_sz.to_le_bytes());
+        buf
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != VALUE_POINTER_SIZE {
+            return Err(LSMLibError::MalformedValuePointer { len: bytes.len() });
+        }
+        let field = |pos: usize| u64::from_le_bytes(bytes[pos..pos + 8].try_into().unwrap());
+        Ok(Self {
+            file_id: field(0),
+            offset: field(8),
+            value_sz: field(16),
+        })
+    }
+}
+
+/// Source of the values separated entries point at.
+pub trait ValueLogReader {
+    /// Value of `key` at `ptr`, checked against the key and size the
+    /// pointer was written for.
+    fn read_value(&mut self, key: &[u8], ptr: &ValuePointer) -> Result<Vec<u8>>;
+}
+
+/// Value log files open for reading, by file id.
+#[derive(Debug)]
+pub struct ValueLog<R> {
+    files: HashMap<u64, R>,
+}
+
+impl<R> Default for ValueLog<R> {
+    fn default() -> Self {
+        Self {
+            files: HashMap::new(),
+        }
+    }
+}
+
+impl<R> ValueLog<R> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add value log file `file_id`, replacing the one of that id if any.
+    pub fn insert(&mut self, file_id: u64, r: R) -> Option<R> {
+        self.files.insert(file_id, r)
+    }
+}
+
+impl<R> ValueLogReader for ValueLog<R>
+where
+    R: Read + Seek,
+{
+    fn read_value(&mut self, key: &[u8], ptr: &ValuePointer) -> Result<Vec<u8>> {
+        let dangling = || LSMLibError::DanglingValuePointer {
+            file_id: ptr.file_id,
+            offset: ptr.offset,
+        };
+        let r = self.files.get_mut(&ptr.file_id).ok_or_else(dangling)?;
+        let entry = DiskEntry::read_from_checked(r, ptr.offset)?.ok_or_else(dangling)?;
+
+        if entry.key[..] != *key {
+            return Err(LSMLibError::KeyMismatch {
+                offset: ptr.offset,
+                expected: key.to_vec(),
+                actual: entry.key.to_vec(),
+            });
+        }
+        if entry.value.len() as u64 != ptr.value_sz {
+            return Err(dangling());
+        }
+        Ok(entry.into_kv().1)
+    }
+}
+
+/// Appends values to value log file `file_id`.
+#[derive(Debug)]
+pub struct ValueLogWriter<W> {
+    inner: W,
+    file_id: u64,
+}
+
+impl<W> ValueLogWriter<W>
+where
+    W: Write + Seek,
+{
+    pub fn new(inner: W, file_id: u64) -> Self {
+        Self { inner, file_id }
+    }
+
+    /// Append `value` of `key`, returns where it landed. The data entry is
+    /// then `DiskEntry::with_value_pointer`.
+    pub fn append(&mut self, key: &[u8], value: &[u8]) -> Result<ValuePointer> {
+        let (offset, _) = DiskEntry::new(key.to_vec(), value.to_vec()).write_to(&mut self.inner)?;
+        Ok(ValuePointer {
+            file_id: self.file_id,
+            offset,
+            value_sz: value.len() as u64,
+        })
+    }
+
+    pub fn file_id(&self) -> u64 {
+        self.file_id
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::Cursor;
+
+    #[test]
+    fn test_value_log() {
+        let mut writer = ValueLogWriter::new(Cursor::new(Vec::new()), 7);
+        let big = vec![0xABu8; 4096];
+        let ptr = writer.append(b"big", &big).unwrap();
+        let other = writer.append(b"other", b"small").unwrap();
+        assert_eq!(ValuePointer::decode(&ptr.encode()).unwrap(), ptr);
+        assert_eq!((ptr.file_id, ptr.offset, other.value_sz), (7, 0, 5));
+
+        let entry = DiskEntry::with_value_pointer(b"big".to_vec(), &ptr);
+        assert_eq!(entry.value.len(), VALUE_POINTER_SIZE);
+        assert_eq!(entry.value_pointer().unwrap(), ptr);
+
+        let mut data = Cursor::new(Vec::new());
+        entry.write_to(&mut data).unwrap();
+        let entry = DiskEntry::read_from(&mut data, 0).unwrap().unwrap();
+
+        let mut vlog = ValueLog::new();
+        vlog.insert(7, writer.into_inner());
+        assert_eq!(entry.resolve_value(&mut vlog).unwrap(), big);
+        assert!(DiskEntry::tombstone(b"big".to_vec())
+            .resolve_value(&mut vlog)
+            .unwrap()
+            .is_empty());
+
+        assert!(matches!(
+            vlog.read_value(b"big", &other),
+            Err(LSMLibError::KeyMismatch { .. })
+        ));
+        let moved = ValuePointer { file_id: 8, ..ptr };
+        assert!(matches!(
+            vlog.read_value(b"big", &moved),
+            Err(LSMLibError::DanglingValuePointer { file_id: 8, .. })
+        ));
+        assert!(matches!(
+            DiskEntry::new(b"k".to_vec(), b"inline".to_vec()).value_pointer(),
+            Err(LSMLibError::MalformedValuePointer { len: 6 })
+        ));
+    }
+}
@@ -2,4 +2,5 @@
 
 use super::sstable::SSTable;
 
+#[allow(clippy::upper_case_acronyms)]
 pub type WAL = SSTable;
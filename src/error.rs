@@ -2,6 +2,8 @@
 
 use thiserror::Error;
 
+use crate::disk::format::Field;
+
 pub type Result<T> = std::result::Result<T, LSMLibError>;
 
 #[derive(Debug, Error)]
@@ -21,11 +23,14 @@ pub enum LSMLibError {
     #[error("key '{}' not found", String::from_utf8_lossy(.0))]
     KeyNotFound(Vec<u8>),
 
-    #[error("key is too large")]
-    KeyIsTooLarge,
+    #[error("key is empty")]
+    EmptyKey,
+
+    #[error("key of {size} bytes is larger than the limit of {limit} bytes")]
+    KeyIsTooLarge { size: u64, limit: u64 },
 
-    #[error("value is too large")]
-    ValueIsTooLarge,
+    #[error("value of {size} bytes is larger than the limit of {limit} bytes")]
+    ValueIsTooLarge { size: u64, limit: u64 },
 
     #[error("file '{}' is not writeable", .0.display())]
     FileNotWriteable(std::path::PathBuf),
@@ -33,6 +38,213 @@ pub enum LSMLibError {
     #[error("db is already locked")]
     AlreadyLocked,
 
+    #[error("unknown format version {0}")]
+    UnknownVersion(u8),
+
+    #[error("{field} of entry at offset {offset} is truncated, the entry needs {needed} bytes but only {available} remain")]
+    TruncatedEntry {
+        field: Field,
+        offset: u64,
+        needed: u64,
+        available: u64,
+    },
+
+    #[error("entry at offset {offset} is larger than the {limit} bytes of a small entry")]
+    NotSmallEntry { offset: u64, limit: u64 },
+
+    #[error("entry has no offset, it was not written yet")]
+    MissingOffset,
+
+    #[error("offset {offset} is past the end of the file, {len} bytes long")]
+    OffsetOutOfRange { offset: u64, len: u64 },
+
+    #[error("{len} bytes of zero padding at offset {offset}")]
+    Padding { offset: u64, len: u64 },
+
+    #[error("header of entry at offset {offset} is inconsistent, it is corrupted")]
+    ImplausibleHeader { offset: u64 },
+
+    #[error(
+        "crc mismatch for key '{}' in file {file_id:?} at offset {offset:?}, expected {expected} actual {actual}",
+        String::from_utf8_lossy(.key)
+    )]
+    CrcMismatch {
+        key: Vec<u8>,
+        file_id: Option<u64>,
+        offset: Option<u64>,
+        expected: u64,
+        actual: u64,
+    },
+
+    #[error("range {start}..{end} is out of bounds of a value of {len} bytes")]
+    RangeOutOfBounds { start: usize, end: usize, len: u64 },
+
+    #[error("entry size {size} is smaller than its header and key, at least {min}")]
+    InvalidEntrySize { size: u64, min: u64 },
+
+    #[error(
+        "entry at offset {offset} has key '{}', expected '{}'",
+        String::from_utf8_lossy(.actual),
+        String::from_utf8_lossy(.expected)
+    )]
+    KeyMismatch {
+        offset: u64,
+        expected: Vec<u8>,
+        actual: Vec<u8>,
+    },
+
+    #[error(
+        "value of key '{}' failed to decrypt, wrong cipher or tampered data",
+        String::from_utf8_lossy(.key)
+    )]
+    DecryptionFailed { key: Vec<u8> },
+
+    #[error(
+        "value of key '{}' is encrypted and no cipher was given",
+        String::from_utf8_lossy(.key)
+    )]
+    MissingCipher { key: Vec<u8> },
+
+    #[error("frame of {frame} bytes holds an entry of {entry} bytes")]
+    FrameSizeMismatch { frame: u64, entry: u64 },
+
+    #[error("hint of entry at offset {offset} disagrees with the entry on its {field}")]
+    HintMismatch { offset: u64, field: &'static str },
+
+    #[error("value pointer of {len} bytes, the entry is not from a separated data file")]
+    MalformedValuePointer { len: usize },
+
+    #[error("no value in value log file {file_id} at offset {offset}")]
+    DanglingValuePointer { file_id: u64, offset: u64 },
+
+    #[error("chunk at offset {offset} does not follow the previous ones on its {field}")]
+    ChunkMismatch { offset: u64, field: &'static str },
+
+    #[error("batch marker at offset {offset} disagrees with its batch, it is corrupted")]
+    BatchMismatch { offset: u64 },
+
+    #[error("index snapshot is corrupted: {0}")]
+    CorruptedSnapshot(String),
+
+    #[error("bloom filter is corrupted: {0}")]
+    CorruptedBloomFilter(String),
+
+    #[error("invalid input: {0}")]
+    InvalidInput(String),
+
     #[error("{}", .0)]
     Custom(String),
 }
+
+/// Category of a `LSMLibError`, for callers choosing between skipping
+/// what failed and aborting, see `LSMLibError::kind`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// The underlying reader or writer failed, the data may be fine.
+    Io,
+
+    /// Data doesn't match its crc.
+    Crc,
+
+    /// An entry runs past the end of the data, typically torn by a crash.
+    Truncated,
+
+    /// A key, value or entry is over a size limit.
+    SizeLimit,
+
+    /// A header has a format version this build doesn't know.
+    UnknownVersion,
+
+    /// Zero padding where an entry was expected, skip it by its length.
+    Padding,
+
+    /// Data is inconsistent beyond a crc mismatch.
+    Corrupted,
+
+    /// Anything else, such as a missing key or a locked db.
+    Other,
+}
+
+impl LSMLibError {
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            Self::Io(_) => ErrorKind::Io,
+            Self::CrcMismatch { .. } => ErrorKind::Crc,
+            Self::TruncatedEntry { .. } => ErrorKind::Truncated,
+            Self::KeyIsTooLarge { .. }
+            | Self::ValueIsTooLarge { .. }
+            | Self::NotSmallEntry { .. } => ErrorKind::SizeLimit,
+            Self::UnknownVersion(_) => ErrorKind::UnknownVersion,
+            Self::Padding { .. } => ErrorKind::Padding,
+            Self::ImplausibleHeader { .. }
+            | Self::InvalidEntrySize { .. }
+            | Self::KeyMismatch { .. }
+            | Self::DecryptionFailed { .. }
+            | Self::FrameSizeMismatch { .. }
+            | Self::HintMismatch { .. }
+            | Self::ChunkMismatch { .. }
+            | Self::BatchMismatch { .. }
+            | Self::MalformedValuePointer { .. }
+            | Self::DanglingValuePointer { .. }
+            | Self::OffsetOutOfRange { .. }
+            | Self::CorruptedSnapshot(_)
+            | Self::CorruptedBloomFilter(_) => ErrorKind::Corrupted,
+            Self::IntParse(_)
+            | Self::Glob(_)
+            | Self::Pattern(_)
+            | Self::KeyNotFound(_)
+            | Self::EmptyKey
+            | Self::FileNotWriteable(_)
+            | Self::AlreadyLocked
+            | Self::RangeOutOfBounds { .. }
+            | Self::MissingOffset
+            | Self::MissingCipher { .. }
+            | Self::InvalidInput(_)
+            | Self::Custom(_) => ErrorKind::Other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::error::Error;
+    use std::io::Cursor;
+
+    use crate::disk::format::{DiskEntry, EntryIO};
+
+    #[test]
+    fn test_error_kind() {
+        let mut cursor = Cursor::new(Vec::new());
+        DiskEntry::new(b"key".to_vec(), b"value".to_vec())
+            .write_to(&mut cursor)
+            .unwrap();
+        let len = cursor.get_ref().len();
+
+        cursor.get_mut().truncate(len - 1);
+        let e = DiskEntry::read_from(&mut cursor, 0).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::Truncated);
+
+        cursor.get_mut().extend_from_slice(&[b'!', 0, 0]);
+        cursor.get_mut()[0] ^= 0xFF;
+        let e = DiskEntry::read_from_checked(&mut cursor, 0).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::Crc);
+        assert_eq!(
+            DiskEntry::read_from(&mut cursor, len as u64)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::Truncated
+        );
+
+        let inner = std::io::Error::other("disk on fire");
+        let e = LSMLibError::from(inner);
+        assert_eq!(e.kind(), ErrorKind::Io);
+        assert_eq!(e.to_string(), "disk on fire");
+        assert!(LSMLibError::EmptyKey.source().is_none());
+        assert_eq!(
+            LSMLibError::UnknownVersion(9).kind(),
+            ErrorKind::UnknownVersion
+        );
+    }
+}
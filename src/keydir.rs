@@ -117,7 +117,7 @@ impl Keydir for HashmapKeydir {
             .entry(key)
             .and_modify(|e| {
                 if e.timestamp <= entry.timestamp {
-                    *e = entry.clone();
+                    *e = entry;
                 }
             })
             .or_insert(entry)
@@ -1,17 +1,40 @@
 // #![cfg_attr(debug_assertions, allow(dead_code, unused_imports, unused_variables))]
 #![cfg_attr(debug_assertions, allow(dead_code))]
+// without `std` only the byte layout and checksums of the format are
+// built, see `layout` and `crc`.
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+#[cfg(feature = "std")]
 mod bloomfilter;
+#[cfg(feature = "std")]
 mod config;
+#[cfg(not(feature = "bench"))]
 mod disk;
+#[cfg(feature = "bench")]
+#[doc(hidden)]
+pub mod disk;
+#[cfg(feature = "std")]
 mod error;
+#[cfg(feature = "std")]
 mod keydir;
 
+#[cfg(feature = "std")]
 mod request;
+#[cfg(feature = "std")]
 mod stats;
+#[cfg(feature = "std")]
 mod storage;
+#[cfg(feature = "std")]
 mod utils;
+#[cfg(feature = "std")]
 mod worker;
 
+#[cfg(feature = "std")]
+pub mod clock;
+#[cfg(feature = "std")]
 pub mod lsm;
+#[cfg(feature = "std")]
+pub mod seq;
 
+pub use disk::{crc, layout};
+#[cfg(feature = "std")]
 pub use lsm::{Lsm, OpenOptions};
@@ -6,11 +6,13 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::{mpsc, Arc, RwLock};
 
+use crate::clock::{Clock, SystemClock};
 use crate::config::Config;
-use crate::disk::format::DiskEntry;
+use crate::disk::format::{DiskEntry, DiskEntryBuilder};
 use crate::disk::sstable::SSTable;
 use crate::disk::wal::WAL;
 use crate::error::Result;
+use crate::seq::{AtomicSeq, SeqGenerator};
 use crate::storage::{Storage, Store};
 use crate::utils;
 use crate::worker::compact::{Compactor, CompactorMessage};
@@ -55,6 +57,12 @@ pub struct Lsm {
     /// dirty_bytes.
     dirty_bytes: u64,
 
+    /// generator of the sequence numbers of new entries.
+    seq: Box<dyn SeqGenerator>,
+
+    /// source of the timestamps of new entries.
+    clock: Box<dyn Clock>,
+
     /// config of store.
     config: Config,
     //// stats.
@@ -63,6 +71,12 @@ pub struct Lsm {
 
 pub struct OpenOptions(Config);
 
+impl Default for OpenOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl OpenOptions {
     pub fn new() -> Self {
         Self(Config::default())
@@ -99,7 +113,7 @@ impl OpenOptions {
     }
 
     pub fn open(&self, path: impl AsRef<Path>) -> Result<Lsm> {
-        Lsm::open_with_options(path, self.0.clone())
+        Lsm::open_with_options(path, self.0)
     }
 }
 
@@ -113,11 +127,16 @@ impl Lsm {
 
         let store = Store::open_with_options(path, config)?;
         let sstables = store.list_sstables();
+        let stored_seq = store.max_seq();
 
         let store = Arc::new(RwLock::new(store));
 
         // build memtable from WAL.
         let (log, memtable, dirty_bytes) = Self::build_memtable(path)?;
+        let last_seq = memtable
+            .values()
+            .filter_map(|e| e.seq())
+            .fold(stored_seq, u64::max);
 
         // create worker message channel.
         let (tx, rx) = mpsc::channel();
@@ -127,7 +146,7 @@ impl Lsm {
             sstables,
             store: Arc::clone(&store),
             inbox: rx,
-            config: config.clone(),
+            config,
         };
 
         std::thread::spawn(move || worker.run());
@@ -145,12 +164,25 @@ impl Lsm {
             memtable,
             log,
             dirty_bytes,
+            seq: Box::new(AtomicSeq::starting_after(last_seq)),
+            clock: Box::new(SystemClock),
             config,
             worker_outbox: tx,
             // stats: Stats::default(),
         })
     }
 
+    /// Replace the generator of sequence numbers, it has to keep handing
+    /// out numbers greater than the ones already stored.
+    pub fn set_seq_generator(&mut self, seq: impl SeqGenerator + 'static) {
+        self.seq = Box::new(seq);
+    }
+
+    /// Replace the source of the timestamps of new entries.
+    pub fn set_clock(&mut self, clock: impl Clock + 'static) {
+        self.clock = Box::new(clock);
+    }
+
     /// Create or Recover memtable
     fn build_memtable(path: &Path) -> Result<(SSTable, BTreeMap<Vec<u8>, DiskEntry>, u64)> {
         let path = utils::format_wal_path(path, 0);
@@ -177,7 +209,7 @@ impl Lsm {
 
             recoverd += entry.size();
 
-            memtable.insert(entry.key.clone(), entry);
+            memtable.insert(entry.key.to_vec(), entry);
         }
 
         // truncate log file.
@@ -192,13 +224,18 @@ impl Lsm {
         Ok((log, memtable, recoverd))
     }
 
-    fn log_mutation(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
+    fn log_mutation(&mut self, entry: DiskEntry) -> Result<()> {
         // first: record log.
-        let disk_entry = self.log.write(&key, &value)?;
+        let disk_entry = self.log.write_entry(entry)?;
         self.dirty_bytes += disk_entry.size();
 
         // then: insert memory.
-        self.memtable.insert(key, disk_entry);
+        self.memtable.insert(disk_entry.key.to_vec(), disk_entry);
+
+        // rotate log and flush memtable to disk.
+        if self.dirty_bytes > self.config.max_log_length {
+            self.flush()?;
+        }
 
         Ok(())
     }
@@ -220,7 +257,7 @@ impl Lsm {
                 self.memtable = memtable;
 
                 log::error!("failed to flush memtable to sstable, error: {}", e);
-                return Err(e.into());
+                return Err(e);
             }
 
             let (next_sstable_id, size) = sstable.unwrap();
@@ -228,7 +265,7 @@ impl Lsm {
             // Send message to worker, it may trigger compacting.
             if let Err(e) = self.worker_outbox.send(CompactorMessage::NewSSTable {
                 id: next_sstable_id,
-                size: size,
+                size,
             }) {
                 log::error!("failed to send message to worker: {:?}", e);
                 log::logger().flush();
@@ -266,16 +303,11 @@ impl Drop for Lsm {
 
 impl KVStore for Lsm {
     fn put(&mut self, key: Vec<u8>, value: Vec<u8>) -> Result<()> {
-        self.log_mutation(key, value)?;
-
-        // log::info!("dirty_bytes: {:?}", self.dirty_bytes);
-
-        // rotate log and flush memtable to disk.
-        if self.dirty_bytes > self.config.max_log_length {
-            self.flush()?;
-        }
-
-        Ok(())
+        let entry = DiskEntryBuilder::new(key, value)
+            .seq(self.seq.next_seq())
+            .clock(self.clock.as_ref())
+            .build()?;
+        self.log_mutation(entry)
     }
 
     fn delete(&mut self, key: &[u8]) -> Result<()> {
@@ -287,15 +319,20 @@ impl KVStore for Lsm {
             return Ok(());
         }
 
-        self.put(key.to_vec(), Vec::new())
+        let entry = DiskEntryBuilder::new(key.to_vec(), Vec::new())
+            .tombstone()
+            .seq(self.seq.next_seq())
+            .clock(self.clock.as_ref())
+            .build()?;
+        self.log_mutation(entry)
     }
 
     fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>> {
         if let Some(entry) = self.memtable.get(key) {
-            if entry.value.is_empty() {
+            if entry.is_tombstone() {
                 return Ok(None);
             }
-            return Ok(Some(entry.value.clone()));
+            Ok(Some(entry.value.to_vec()))
         } else {
             self.store.write().unwrap().get(key)
         }
@@ -303,8 +340,8 @@ impl KVStore for Lsm {
 
     fn contains(&self, key: &[u8]) -> bool {
         // first: check memtable.
-        if self.memtable.contains_key(key) {
-            return true;
+        if let Some(entry) = self.memtable.get(key) {
+            return !entry.is_tombstone();
         }
         // then: check keydir.
         self.store.read().unwrap().contains_key(key)
@@ -315,7 +352,7 @@ impl KVStore for Lsm {
         keys.sort();
 
         self.memtable.iter().for_each(|v| {
-            if !v.1.value.is_empty() {
+            if !v.1.is_tombstone() {
                 keys.push(v.0.clone());
             } else {
                 if let Ok(index) = keys.binary_search(v.0) {
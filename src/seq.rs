@@ -0,0 +1,28 @@
+//! Sequence Module.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Source of sequence numbers ordering writes.
+///
+/// Numbers handed out must be strictly increasing, also across restarts
+/// of the store, so the newest write of a key always has the greatest one.
+pub trait SeqGenerator: Send + Sync {
+    fn next_seq(&self) -> u64;
+}
+
+/// In-memory counter, resumed after the greatest number already used.
+#[derive(Debug, Default)]
+pub struct AtomicSeq(AtomicU64);
+
+impl AtomicSeq {
+    /// Counter whose first number is `last + 1`.
+    pub fn starting_after(last: u64) -> Self {
+        Self(AtomicU64::new(last))
+    }
+}
+
+impl SeqGenerator for AtomicSeq {
+    fn next_seq(&self) -> u64 {
+        self.0.fetch_add(1, Ordering::SeqCst) + 1
+    }
+}
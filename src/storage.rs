@@ -6,7 +6,7 @@ use std::io::Write;
 use std::path::{Path, PathBuf};
 
 use crate::config::{self, Config};
-use crate::disk::format::DiskEntry;
+use crate::disk::format::{into_vec, DiskEntry};
 use crate::disk::{format::HintEntry, hint::HintFile, sstable::SSTable};
 use crate::error::{LSMLibError, Result};
 use crate::keydir::{HashmapKeydir, Keydir, KeydirEntry};
@@ -106,6 +106,9 @@ where
     /// Keydir maintains key value index for fast query.
     keydir: K,
 
+    /// greatest sequence number seen in the sstables.
+    max_seq: u64,
+
     /// config options.
     config: Config,
 }
@@ -144,6 +147,7 @@ where
             _lock: lock,
             sstables: BTreeMap::new(),
             keydir: K::default(),
+            max_seq: 0,
             config,
         };
 
@@ -153,6 +157,12 @@ where
         Ok(store)
     }
 
+    /// Greatest sequence number of the entries stored so far, 0 if none
+    /// carries one.
+    pub fn max_seq(&self) -> u64 {
+        self.max_seq
+    }
+
     pub fn list_sstables(&self) -> BTreeMap<u64, u64> {
         self.sstables.iter().map(|s| (*s.0, s.1.size())).collect()
     }
@@ -194,14 +204,25 @@ where
     fn build_keydir_from_hint(&mut self, path: &Path) -> Result<()> {
         log::trace!("build keydir from hint file {}", path.display());
         let mut hint_file = HintFile::new(path, false)?;
-        let _hint_file_id = hint_file.id();
+        let hints = match hint_file.load_index() {
+            Ok(hints) => hints,
+            Err(e) => {
+                log::warn!(
+                    "hint file {} is corrupted, build keydir from its data file: {}",
+                    path.display(),
+                    e
+                );
+                return self.build_keydir_from_sstable(hint_file.id());
+            }
+        };
 
-        for entry in hint_file.iter() {
-            if entry.value_sz() != 0 {
+        for entry in hints {
+            self.max_seq = self.max_seq.max(entry.seq().unwrap_or(0));
+            if entry.is_tombstone() {
+                self.keydir.remove(&entry.key);
+            } else {
                 let keydir_entry = KeydirEntry::try_from(&entry)?;
                 self.keydir.put(entry.key, keydir_entry);
-            } else {
-                self.keydir.remove(&entry.key);
             }
         }
 
@@ -213,14 +234,15 @@ where
         log::info!("build keydir from data file {}", sst.path().display());
 
         for entry in sst.iter() {
-            if entry.value.is_empty() {
+            self.max_seq = self.max_seq.max(entry.seq().unwrap_or(0));
+            if entry.is_tombstone() {
                 log::trace!("{} is a remove tomestone", &entry);
 
                 self.keydir.remove(&entry.key);
                 continue;
             }
             let keydir_entry = KeydirEntry::try_from(&entry)?;
-            let _ = self.keydir.put(entry.key, keydir_entry);
+            let _ = self.keydir.put(into_vec(entry.key), keydir_entry);
         }
 
         Ok(())
@@ -232,7 +254,7 @@ where
     K: Keydir + Default,
 {
     fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>> {
-        if let Some(keydir_entry) = self.keydir.get(&key) {
+        if let Some(keydir_entry) = self.keydir.get(key) {
             log::trace!(
                 "found key `{}` in keydir, got value `{:?}`",
                 String::from_utf8_lossy(key),
@@ -247,7 +269,7 @@ where
                 });
 
             if let Some(disk_entry) = sst.read(keydir_entry.offset)? {
-                return Ok(disk_entry.value.into());
+                return Ok(Some(into_vec(disk_entry.value)));
             }
         }
 
@@ -266,13 +288,14 @@ where
         for (k, entry) in items {
             // write sstable file.
             let disk_entry = sstable.write_entry(entry.clone())?;
+            self.max_seq = self.max_seq.max(disk_entry.seq().unwrap_or(0));
 
             // write hint file.
-            hint.write_entry(HintEntry::from(&disk_entry))?;
+            hint.write_entry(HintEntry::try_from(&disk_entry)?)?;
 
             // not hint
-            if disk_entry.value.is_empty() {
-                self.keydir.remove(&k);
+            if disk_entry.is_tombstone() {
+                self.keydir.remove(k);
             } else {
                 // update keydir.
                 self.keydir
@@ -6,7 +6,7 @@ use std::sync::{mpsc, Arc, RwLock};
 
 use crate::config::Config;
 use crate::disk::{
-    format::HintEntry,
+    format::{self, HintEntry},
     hint::HintFile,
     sstable::{self, SSTable},
 };
@@ -66,7 +66,7 @@ impl Compactor {
             );
         }
 
-        return true;
+        true
     }
 
     fn handle_message(&mut self, msg: CompactorMessage) -> bool {
@@ -106,7 +106,7 @@ impl Compactor {
                 .skip(1)
                 .all(|w| *w.1 * self.config.merge_ratio as u64 > *window[0].1)
             {
-                let run_to_compact: Vec<u64> = window.into_iter().map(|(id, _sum)| **id).collect();
+                let run_to_compact: Vec<u64> = window.iter().map(|(id, _sum)| **id).collect();
 
                 self.compact_sstable_run(&run_to_compact)?;
                 return Ok(());
@@ -157,13 +157,22 @@ impl Compactor {
         let merge_hint_tmp_path = utils::format_hint_tmp_path(&self.path, max_sstable_id);
         let mut merge_hint = HintFile::new(&merge_hint_tmp_path, true)?;
 
+        let now = format::now_timestamp();
         let ms_iter = sstable::CompactMergeIter::new(sstables);
         for entry in ms_iter {
+            // an expired entry can't simply be left out, an older write
+            // of its key in a sstable outside this run would come back.
+            let entry = if entry.is_expired(now) {
+                entry.to_tombstone()
+            } else {
+                entry
+            };
+
             // write to merge sstable.
             let disk_entry = merge_sstable.write_entry(entry)?;
 
             // write hint file.
-            merge_hint.write_entry(HintEntry::from(&disk_entry))?;
+            merge_hint.write_entry(HintEntry::from_owned(disk_entry)?)?;
         }
 
         // sync all write.